@@ -11,6 +11,11 @@ fn main() {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let out_var = env::var("OUT_DIR").unwrap();
 
+    // Generate the protobuf representation of the store types served over gRPC.
+    #[cfg(feature = "grpc")]
+    prost_build::compile_protos(&["proto/store.proto"], &["proto"])
+        .expect("failed to generate store protobuf definitions");
+
     // Download the Wormhole repository at a certain tag, which we need to access the protobuf definitions
     // for Wormhole P2P message types.
     //
@@ -5,6 +5,7 @@
 use {
     crate::store::Store,
     anyhow::Result,
+    std::time::Duration,
     structopt::StructOpt,
 };
 
@@ -15,6 +16,9 @@ mod macros;
 mod network;
 mod store;
 
+/// How often the store is checked for stalled ingestion.
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
 /// Initialize the Application. This can be invoked either by real main, or by the Geyser plugin.
 async fn init() -> Result<()> {
     log::info!("Initializing Hermes...");
@@ -37,6 +41,9 @@ async fn init() -> Result<()> {
             log::info!("Running Hermes...");
             let store = Store::new(update_tx, 1000);
 
+            // Spawn the watchdog surfacing stalled ingestion.
+            store.clone().spawn_stall_watchdog(STALL_CHECK_INTERVAL);
+
             // Spawn the P2P layer.
             log::info!("Starting P2P server on {:?}", wh_listen_addrs);
             network::p2p::spawn(
@@ -6,6 +6,7 @@ use {
     crate::store::{
         types::{
             AccumulatorMessages,
+            UnixTimestamp,
             Update,
         },
         wormhole::{
@@ -50,12 +51,13 @@ use {
     tokio::time::Instant,
 };
 
-/// Using a Solana RPC endpoint, fetches the target GuardianSet based on an index.
+/// Using a Solana RPC endpoint, fetches the target GuardianSet based on an index, along with
+/// its expiration time.
 async fn fetch_guardian_set(
     client: &RpcClient,
     wormhole_contract_addr: Pubkey,
     guardian_set_index: u32,
-) -> Result<GuardianSet> {
+) -> Result<GuardianSetData> {
     // Fetch GuardianSet account from Solana RPC.
     let guardian_set = client
         .get_account_with_commitment(
@@ -76,12 +78,10 @@ async fn fetch_guardian_set(
         Err(err) => return Err(anyhow!("Failed to fetch GuardianSet account: {}", err)),
     };
 
-    // Deserialize the result into a GuardianSet, this is where we can
+    // Deserialize the result into a GuardianSetData, this is where we can
     // extract the new Signer set.
     match GuardianSetData::deserialize(&mut guardian_set.data.as_ref()) {
-        Ok(guardian_set) => Ok(GuardianSet {
-            keys: guardian_set.keys,
-        }),
+        Ok(guardian_set) => Ok(guardian_set),
 
         Err(err) => Err(anyhow!(
             "Failed to deserialize GuardianSet account: {}",
@@ -211,8 +211,11 @@ async fn fetch_existing_guardian_sets(
     let bridge = fetch_bridge_data(&client, &wormhole_contract_addr).await?;
 
     // Fetch the current GuardianSet we know is valid for signing.
-    let current =
-        fetch_guardian_set(&client, wormhole_contract_addr, bridge.guardian_set_index).await?;
+    let current = GuardianSet {
+        keys: fetch_guardian_set(&client, wormhole_contract_addr, bridge.guardian_set_index)
+            .await?
+            .keys,
+    };
 
     log::info!(
         "Retrieved Current GuardianSet ({}): {}",
@@ -222,17 +225,20 @@ async fn fetch_existing_guardian_sets(
 
     store
         .update_guardian_set(bridge.guardian_set_index, current)
-        .await;
+        .await?;
 
     // If there are more than one guardian set, we want to fetch the previous one as well as it
     // may still be in transition phase if a guardian upgrade has just occurred.
     if bridge.guardian_set_index >= 1 {
-        let previous = fetch_guardian_set(
+        let previous_data = fetch_guardian_set(
             &client,
             wormhole_contract_addr,
             bridge.guardian_set_index - 1,
         )
         .await?;
+        let previous = GuardianSet {
+            keys: previous_data.keys,
+        };
 
         log::info!(
             "Retrieved Previous GuardianSet ({}): {}",
@@ -240,9 +246,25 @@ async fn fetch_existing_guardian_sets(
             previous
         );
 
-        store
-            .update_guardian_set(bridge.guardian_set_index - 1, previous)
-            .await;
+        // The previous set expires the bridge's guardian set expiration time after it was
+        // replaced, which is when its retirement grace period starts, rather than at this fetch
+        // that happens again on every restart. An expiration time of 0 means it never expired.
+        if previous_data.expiration_time == 0 {
+            store
+                .update_guardian_set(bridge.guardian_set_index - 1, previous)
+                .await?;
+        } else {
+            let retired_at = previous_data
+                .expiration_time
+                .saturating_sub(bridge.config.guardian_set_expiration_time);
+            store
+                .update_retired_guardian_set(
+                    bridge.guardian_set_index - 1,
+                    previous,
+                    retired_at as UnixTimestamp,
+                )
+                .await?;
+        }
     }
 
     Ok(())
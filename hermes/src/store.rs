@@ -12,31 +12,81 @@ use std::time::{
 };
 use {
     self::{
+        metadata::{
+            FeedMetadata,
+            FeedMetadataProvider,
+        },
         proof::wormhole_merkle::{
+            construct_bundled_update_data,
+            construct_leaves_only_update_data,
+            construct_multiproof_update_data,
+            construct_root_only_update_data,
             construct_update_data,
+            construct_vaa_update_data,
+            WormholeMerkleMessageProof,
             WormholeMerkleState,
         },
+        sink::UpdateSink,
+        snapshot::{
+            Snapshot,
+            SnapshotSlot,
+            SNAPSHOT_VERSION,
+        },
         storage::{
+            EvictionCallback,
+            IntegrityReport,
             MessageState,
             MessageStateFilter,
+            MessageStateKey,
+            MessageStateTime,
             Storage,
         },
         types::{
             AccumulatorMessages,
+            CompletedSlot,
+            Direction,
+            LayeredPriceFeed,
             PriceFeedUpdate,
+            PriceFeedsQueryOptions,
             PriceFeedsWithUpdateData,
+            PrimeReport,
+            ProofGranularity,
             RequestTime,
+            SlotSummary,
+            StoreError,
+            StoreStatus,
+            StructuredProof,
             Update,
         },
-        wormhole::GuardianSet,
+        watermark::SequenceWatermarkStore,
+        wormhole::{
+            split_vaa,
+            GuardianSet,
+            GuardianSetInfo,
+            VaaHeader,
+        },
     },
     crate::store::{
+        metrics::{
+            NoopStoreMetricsRecorder,
+            StoreMetrics,
+            StoreMetricsRecorder,
+            VaaIgnoreReason,
+        },
         proof::wormhole_merkle::{
-            construct_message_states_proofs,
+            construct_message_states_proofs_pooled,
+            construct_update_data_pooled,
             store_wormhole_merkle_verified_message,
+            ProofPool,
+        },
+        subscription::{
+            BatchedSubscriber,
+            UpdateListener,
+            BATCHED_SUBSCRIPTION_CHAN_LEN,
         },
         types::{
             ProofSet,
+            Slot,
             UnixTimestamp,
         },
         wormhole::verify_vaa,
@@ -45,16 +95,40 @@ use {
         anyhow,
         Result,
     },
+    borsh::{
+        BorshDeserialize,
+        BorshSerialize,
+    },
     byteorder::BigEndian,
+    futures::{
+        future::join_all,
+        stream::{
+            self,
+            Stream,
+            StreamExt,
+            TryStreamExt,
+        },
+    },
+    prometheus_client::metrics::histogram::Histogram,
     pyth_sdk::PriceIdentifier,
     pythnet_sdk::{
+        accumulators::merkle::{
+            MerklePath,
+            MerkleRoot,
+            MerkleTree,
+        },
+        hashers::keccak256_160::Keccak160,
         messages::{
+            FeedId,
             Message,
             MessageType,
+            PriceFeedMessage,
         },
         wire::{
             from_slice,
             v1::{
+                AccumulatorUpdateData,
+                Proof,
                 WormholeMessage,
                 WormholePayload,
             },
@@ -64,15 +138,33 @@ use {
         collections::{
             BTreeMap,
             BTreeSet,
+            HashMap,
             HashSet,
         },
-        sync::Arc,
+        ops::Range,
+        sync::{
+            atomic::{
+                AtomicBool,
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+            OnceLock,
+        },
         time::Duration,
     },
+    strum::IntoEnumIterator,
     tokio::sync::{
-        mpsc::Sender,
+        mpsc::{
+            error::TrySendError,
+            Receiver,
+            Sender,
+        },
+        oneshot,
         RwLock,
+        Semaphore,
     },
+    tokio_util::sync::CancellationToken,
     wormhole_sdk::{
         Address,
         Chain,
@@ -80,45 +172,587 @@ use {
     },
 };
 
+pub mod metadata;
+pub mod metrics;
 pub mod proof;
+#[cfg(feature = "grpc")]
+pub mod proto;
+pub mod sink;
+pub mod snapshot;
 pub mod storage;
+pub mod subscription;
 pub mod types;
+pub mod watermark;
 pub mod wormhole;
 
 const OBSERVED_CACHE_SIZE: usize = 1000;
+/// Source of the updates stored without an explicit source.
+pub const DEFAULT_SOURCE: &str = "default";
+/// Number of completed slots a completion stream can lag behind before it
+/// misses some of them.
+const COMPLETION_CHAN_LEN: usize = 1000;
 const READINESS_STALENESS_THRESHOLD: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_FEEDS_PER_REQUEST: usize = 1000;
+const DEFAULT_MAX_PINNED_SLOTS: usize = 10;
+/// Wormhole guardian sets have at most 19 keys, so the default cap leaves
+/// room for growth while keeping verification cheap.
+const DEFAULT_MAX_GUARDIAN_KEYS: usize = 50;
+/// Wormhole keeps accepting the Vaas of a guardian set for a day after it
+/// is replaced.
+const DEFAULT_RETIRED_SET_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+/// Updates are expected to be stored within milliseconds, so waiting longer
+/// for a permit means the store cannot keep up.
+const DEFAULT_UPDATE_PERMIT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Minimum interval between two warnings about Vaas received while no
+/// guardian set is loaded.
+const NO_GUARDIAN_SET_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+/// Multiple of the stall threshold after which a stall is logged as an
+/// error instead of a warning.
+const STALL_ERROR_MULTIPLIER: u32 = 10;
+
+/// The shard of the feeds handled by a store in a deployment where the feeds
+/// are partitioned over several instances.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShardConfig {
+    pub total_shards: u64,
+    pub shard_index:  u64,
+}
+
+impl ShardConfig {
+    /// Returns whether the feed belongs to this shard. The shard of a feed
+    /// is its first 8 bytes, as a big-endian integer, modulo the number of
+    /// shards, so it is stable across instances and releases.
+    pub fn contains(&self, feed_id: &FeedId) -> bool {
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&feed_id[..8]);
+        u64::from_be_bytes(prefix) % self.total_shards.max(1) == self.shard_index
+    }
+}
+
+/// How accumulator messages arriving for a slot that already holds a
+/// different message set are handled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AccumulatorConflictPolicy {
+    /// Keep the stored message set and ignore the new one.
+    FirstWins,
+    /// Replace the stored message set with the new one.
+    LastWins,
+    /// Keep the stored message set and fail the update with a
+    /// `ConflictingAccumulatorMessages` error.
+    #[default]
+    RejectConflict,
+}
+
+/// How Vaas that were already processed are recognized and ignored.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum VaaDedupStrategy {
+    /// Ignore the Vaas whose sequence is among the last observed sequences.
+    /// Under high throughput the remembered sequences span little time.
+    #[default]
+    SequenceCount,
+    /// Ignore the Vaas of slots that are already finalized or older than
+    /// all the retained slots, regardless of how many Vaas were observed
+    /// since.
+    Slot,
+}
+
+/// How price feed messages whose previous publish time is after their
+/// publish time, which is impossible for intact messages, are handled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum InvertedPublishTimePolicy {
+    /// Store the message as is.
+    #[default]
+    Accept,
+    /// Skip the message.
+    Reject,
+    /// Serve the price feed with its previous publish time clamped to its
+    /// publish time. The proven message and its update data are unchanged.
+    Clamp,
+}
+
+/// How the completed slots of a bulk ingestion are signaled to the api.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BulkSignalPolicy {
+    /// Signal each completed slot, like individually stored updates.
+    #[default]
+    PerSlot,
+    /// Signal once when the whole batch is stored, so a slow consumer of the
+    /// signals does not hold back the ingestion.
+    Once,
+}
+
+/// How the signals of the completed slots are delivered to the api.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SignalCoalescing {
+    /// Signal every completed slot.
+    #[default]
+    None,
+    /// Signal once the given window elapsed since the first slot completed
+    /// after the last signal, so a burst of completed slots is signaled once
+    /// and a steady stream of them at most once per window.
+    Debounced(Duration),
+}
+
+/// Fields of a price feed whose change from the previous retained update of
+/// the feed makes the update part of the changed set emitted to the update
+/// sinks. Updates of feeds without a previous retained update always are.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChangeTriggers {
+    pub price:        bool,
+    pub conf:         bool,
+    pub ema_price:    bool,
+    pub publish_time: bool,
+}
+
+impl Default for ChangeTriggers {
+    fn default() -> Self {
+        Self {
+            price:        false,
+            conf:         false,
+            ema_price:    false,
+            publish_time: true,
+        }
+    }
+}
+
+impl ChangeTriggers {
+    /// Returns whether any of the trigger fields differs between the updates.
+    pub fn is_changed(&self, previous: &PriceFeedMessage, current: &PriceFeedMessage) -> bool {
+        (self.price && previous.price != current.price)
+            || (self.conf && previous.conf != current.conf)
+            || (self.ema_price && previous.ema_price != current.ema_price)
+            || (self.publish_time && previous.publish_time != current.publish_time)
+    }
+}
+
+/// Tunable behaviour of the store.
+#[derive(Clone, Debug)]
+pub struct StoreConfig {
+    /// Maximum number of price feeds that can be queried at once.
+    pub max_feeds_per_request:             usize,
+    /// Maximum age (based on publish time) of the latest update of a feed
+    /// before it is considered stale. Latest queries for stale feeds fail
+    /// unless stale updates are explicitly allowed. Disabled when `None`.
+    pub latest_staleness_threshold:        Option<Duration>,
+    /// Maximum number of slots that can be pinned at the same time.
+    pub max_pinned_slots:                  usize,
+    /// Attach a checksum to each stored message state so the cache can be
+    /// verified with `Store::verify_cache_integrity`.
+    pub cache_checksums:                   bool,
+    /// Reject Vaas whose sequence is more than this many sequences behind
+    /// the highest processed sequence of their emitter, to prevent replays
+    /// of old Vaas. Out of order arrival within the window is accepted.
+    /// Disabled when `None`.
+    pub replay_window:                     Option<u64>,
+    /// Minimum number of feeds the store must hold before it reports
+    /// ready, so a warming up instance does not receive traffic.
+    pub warmup_min_feeds:                  usize,
+    /// Minimum number of slots the store must hold before it reports
+    /// ready.
+    pub warmup_min_slots:                  usize,
+    /// Time without a completed slot after which the stall watchdog
+    /// reports the ingestion as stalled.
+    pub stall_threshold:                   Duration,
+    /// Number of newer slots that must be observed before the message states
+    /// of a slot are built, so data of a slot that might be reorged is not
+    /// served. Zero builds the message states as soon as a slot is complete.
+    pub finality_confirmations:            u64,
+    /// Treat accumulator messages arriving for a slot whose Vaa is not yet
+    /// stored as a fragment extending the slot's message set, instead of a
+    /// replacement of it. The merkle root signed in the Vaa must match the
+    /// full set once it arrives.
+    pub incremental_accumulation:          bool,
+    /// Fail updates with a `ReadOnly` error while the store is read-only
+    /// instead of silently ignoring them.
+    pub strict_read_only:                  bool,
+    /// Only store the feeds of this shard. All the feeds are stored when
+    /// `None`.
+    pub shard:                             Option<ShardConfig>,
+    /// Reject accumulator message sets without any message with an
+    /// `EmptySlot` error. Otherwise the slot is stored and completed like any
+    /// other slot, and its queries find no feeds.
+    pub reject_empty_slots:                bool,
+    /// Handling of accumulator messages that differ from the ones already
+    /// stored for their slot. Fragments of a slot accumulated incrementally
+    /// are not conflicts.
+    pub accumulator_conflict_policy:       AccumulatorConflictPolicy,
+    /// Maximum distance between the publish time of a message and the time
+    /// of its slot, as signed in the slot's Vaa. Messages outside of it are
+    /// flagged as misattributed to the slot. Disabled when `None`.
+    pub publish_time_window:               Option<Duration>,
+    /// Skip the messages flagged by `publish_time_window` instead of only
+    /// counting them.
+    pub reject_inconsistent_publish_times: bool,
+    /// Log each completed slot as a single JSON line with its slot, message
+    /// and feed counts, merkle root and Vaa sequence, instead of free text.
+    pub json_logging:                      bool,
+    /// Number of blocking threads constructing proofs, shared by the slots
+    /// being completed and the update data being served.
+    pub proof_workers:                     usize,
+    /// Handling of price feed messages whose previous publish time is after
+    /// their publish time. They are counted regardless of the policy.
+    pub inverted_publish_time_policy:      InvertedPublishTimePolicy,
+    /// Maximum number of keys of a guardian set. Larger sets are rejected
+    /// as every key makes the verification of Vaas more expensive.
+    pub max_guardian_keys:                 usize,
+    /// Maximum ratio by which a price may deviate from the previous retained
+    /// price of its feed before the update is flagged as suspect. Suspect
+    /// updates are still stored and served. Disabled when `None`.
+    pub max_deviation_ratio:               Option<f64>,
+    /// Maximum age of a Vaa, as told by its signed timestamp, when it is
+    /// received. Older Vaas may be replayed and are rejected with a
+    /// `StaleVaa` error. Disabled when `None`.
+    pub max_vaa_age:                       Option<Duration>,
+    /// Number of distinct sources, such as redundant Wormhole spies, that
+    /// must deliver the same Vaa before it is used. The default of 1 uses a
+    /// Vaa as soon as it is received.
+    pub source_quorum:                     usize,
+    /// Time during which a replaced guardian set is still considered active.
+    pub retired_set_grace_period:          Duration,
+    /// How already processed Vaas are recognized and ignored.
+    pub vaa_dedup_strategy:                VaaDedupStrategy,
+    /// Fields that must change for a price feed update to be emitted to the
+    /// update sinks, so they can skip no-op updates.
+    pub sink_change_triggers:              ChangeTriggers,
+    /// Minimum consistency level of the Vaas, for emitters encoding their
+    /// finality expectations in it. Vaas below it are rejected. All the Vaas
+    /// are accepted when `None`.
+    pub min_consistency_level:             Option<u8>,
+    /// Maximum number of updates stored concurrently, to bound the memory
+    /// used under extreme load. Unbounded when `None`.
+    pub max_concurrent_updates:            Option<usize>,
+    /// Maximum time an update waits for one of the `max_concurrent_updates`
+    /// before it fails with an `Overloaded` error.
+    pub update_permit_timeout:             Duration,
+    /// Time during which the price feed update of a feed, with its update
+    /// data, is reused by the queries resolving to the same slot, so the
+    /// hottest feeds are not rebuilt on every query. Disabled when `None`.
+    pub price_feed_update_cache_ttl:       Option<Duration>,
+    /// How the signals of the completed slots are coalesced.
+    pub signal_coalescing:                 SignalCoalescing,
+    /// Serve the data verified by guardian sets replaced for longer than
+    /// `retired_set_grace_period`, as it was valid when signed. Otherwise
+    /// queries resolving to such data fail with a `RetiredGuardianSet` error.
+    pub serve_under_retired_set:           bool,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            max_feeds_per_request:             DEFAULT_MAX_FEEDS_PER_REQUEST,
+            latest_staleness_threshold:        None,
+            max_pinned_slots:                  DEFAULT_MAX_PINNED_SLOTS,
+            cache_checksums:                   false,
+            replay_window:                     None,
+            warmup_min_feeds:                  0,
+            warmup_min_slots:                  0,
+            stall_threshold:                   READINESS_STALENESS_THRESHOLD,
+            finality_confirmations:            0,
+            incremental_accumulation:          false,
+            strict_read_only:                  false,
+            shard:                             None,
+            reject_empty_slots:                false,
+            accumulator_conflict_policy:       AccumulatorConflictPolicy::default(),
+            publish_time_window:               None,
+            reject_inconsistent_publish_times: false,
+            json_logging:                      false,
+            proof_workers:                     1,
+            inverted_publish_time_policy:      InvertedPublishTimePolicy::default(),
+            max_guardian_keys:                 DEFAULT_MAX_GUARDIAN_KEYS,
+            max_deviation_ratio:               None,
+            max_vaa_age:                       None,
+            source_quorum:                     1,
+            retired_set_grace_period:          DEFAULT_RETIRED_SET_GRACE_PERIOD,
+            vaa_dedup_strategy:                VaaDedupStrategy::default(),
+            sink_change_triggers:              ChangeTriggers::default(),
+            min_consistency_level:             None,
+            max_concurrent_updates:            None,
+            update_permit_timeout:             DEFAULT_UPDATE_PERMIT_TIMEOUT,
+            price_feed_update_cache_ttl:       None,
+            signal_coalescing:                 SignalCoalescing::None,
+            serve_under_retired_set:           true,
+        }
+    }
+}
+
+/// Senders resolving the first update of each awaited price feed.
+type FeedWaiters = HashMap<PriceIdentifier, Vec<oneshot::Sender<PriceFeedUpdate>>>;
 
 pub struct Store {
     /// Storage is a short-lived cache of the state of all the updates
     /// that have been passed to the store.
     pub storage:                  Storage,
+    /// Message states backfilled from archived accumulator messages without
+    /// their Vaa. They are kept apart so they are only ever served to the
+    /// queries allowing unverified data.
+    pub unverified_storage:       Storage,
     /// Sequence numbers of lately observed Vaas. Store uses this set
     /// to ignore the previously observed Vaas as a performance boost.
     pub observed_vaa_seqs:        RwLock<BTreeSet<u64>>,
     /// Wormhole guardian sets. It is used to verify Vaas before using
     /// them.
     pub guardian_set:             RwLock<BTreeMap<u32, GuardianSet>>,
+    /// When each guardian set was replaced by a newer one.
+    guardian_set_retired_at:      RwLock<BTreeMap<u32, UnixTimestamp>>,
     /// The sender to the channel between Store and Api to notify
     /// completed updates.
     pub update_tx:                Sender<()>,
+    /// The sender of the completed slots to the completion streams.
+    completion_tx:                tokio::sync::broadcast::Sender<CompletedSlot>,
     /// Time of the last completed update. This is used for the health
     /// probes.
     pub last_completed_update_at: RwLock<Option<Instant>>,
+    /// The highest slot for which the message states are built.
+    pub latest_completed_slot:    RwLock<Option<Slot>>,
+    /// Retained slots whose message states are built. It is only tracked
+    /// with the `Slot` Vaa dedup strategy.
+    finalized_slots:              RwLock<BTreeSet<Slot>>,
+    /// When the store was constructed.
+    started_at:                   Instant,
+    /// When the store first became ready after its construction.
+    first_ready_at:               OnceLock<Instant>,
+    /// Subscribers that receive the updates of a completed slot as a
+    /// single batch.
+    pub batched_subscribers:      RwLock<Vec<BatchedSubscriber>>,
+    pub config:                   StoreConfig,
+    /// Metrics collected while ingesting updates.
+    pub metrics:                  StoreMetrics,
+    /// Operator supplied metadata of the price feeds.
+    pub feed_metadata:            RwLock<HashMap<PriceIdentifier, FeedMetadata>>,
+    /// Highest contiguous observed Vaa sequence. Vaas at or below it are
+    /// ignored. It is only tracked when a watermark store is configured.
+    pub seq_watermark:            RwLock<Option<u64>>,
+    /// Persistence of the sequence watermark across restarts.
+    pub watermark_store:          Option<Box<dyn SequenceWatermarkStore>>,
+    /// Highest processed Vaa sequence of each emitter. It is used to reject
+    /// replays of old Vaas.
+    pub emitter_high_water_seqs:  RwLock<HashMap<(Chain, Address), u64>>,
+    /// Sinks receiving the message states of each completed slot.
+    pub update_sinks:             RwLock<Vec<Arc<dyn UpdateSink>>>,
+    /// Whether notifying a completed update failed. The store keeps
+    /// ingesting updates but the api is no longer notified.
+    pub degraded:                 Arc<AtomicBool>,
+    /// Listeners notified of each completed slot, each with its own buffer.
+    pub update_listeners:         RwLock<Vec<UpdateListener>>,
+    /// Identifier of the next registered update listener.
+    next_listener_id:             AtomicUsize,
+    /// Feeds the task debouncing the signals of the completed slots. The
+    /// task is started with the first debounced signal.
+    debounced_signals:            OnceLock<Sender<()>>,
+    /// Types of the messages that are stored and served. Messages of the
+    /// other types are skipped even if they can be parsed.
+    pub enabled_message_types:    RwLock<HashSet<MessageType>>,
+    /// Complete slots waiting for their finality confirmations.
+    pending_final_slots:          RwLock<BTreeSet<Slot>>,
+    /// Whether updates are ignored, freezing the cached state while it is
+    /// still served.
+    read_only:                    AtomicBool,
+    /// Highest sequence up to which all the Vaas since the first observed
+    /// one are observed. Sequences observed beyond it leave gaps.
+    seq_frontier:                 RwLock<Option<u64>>,
+    /// When the last warning about a missing guardian set was logged.
+    no_guardian_set_warned_at:    RwLock<Option<Instant>>,
+    /// Operator-defined groups of feeds, such as the constituents of an
+    /// index, that are queried together by name.
+    feed_groups:                  RwLock<HashMap<String, Vec<PriceIdentifier>>>,
+    /// Pluggable backend recording the ingestion events after `metrics`.
+    metrics_recorder:             Box<dyn StoreMetricsRecorder>,
+    /// Sources that delivered each verified Vaa, identified by its sequence
+    /// and payload, which has not reached the source quorum yet.
+    vaa_sources:                  RwLock<BTreeMap<(u64, Vec<u8>), HashSet<String>>>,
+    /// Source of the accumulator messages of each recent slot, for those
+    /// stored from an explicit source.
+    accumulator_sources:          RwLock<BTreeMap<Slot, String>>,
+    /// Permits bounding the updates stored concurrently, if limited.
+    update_permits:               Option<Semaphore>,
+    /// Workers constructing the proofs off the async runtime.
+    proof_pool:                   ProofPool,
+    /// Latest built price feed update of each feed, with when it was built.
+    price_feed_update_cache:      RwLock<HashMap<PriceIdentifier, (Instant, PriceFeedUpdate)>>,
+    /// Waiters for the first update of each feed not stored yet.
+    feed_waiters:                 RwLock<FeedWaiters>,
+}
+
+/// Builds a store with any combination of a metrics recorder, an eviction
+/// callback and a watermark store.
+pub struct StoreBuilder {
+    update_tx:        Sender<()>,
+    cache_size:       u64,
+    config:           StoreConfig,
+    metrics_recorder: Option<Box<dyn StoreMetricsRecorder>>,
+    on_evict:         Option<EvictionCallback>,
+    watermark_store:  Option<Box<dyn SequenceWatermarkStore>>,
+}
+
+impl StoreBuilder {
+    pub fn new(update_tx: Sender<()>, cache_size: u64) -> Self {
+        Self {
+            update_tx,
+            cache_size,
+            config: StoreConfig::default(),
+            metrics_recorder: None,
+            on_evict: None,
+            watermark_store: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: StoreConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Reports the ingestion events of the store to the recorder in addition
+    /// to its own metrics.
+    pub fn with_metrics_recorder(
+        mut self,
+        metrics_recorder: Box<dyn StoreMetricsRecorder>,
+    ) -> Self {
+        self.metrics_recorder = Some(metrics_recorder);
+        self
+    }
+
+    /// Passes the message states of each slot evicted from the cache to the
+    /// callback, for instance to archive them in a colder storage tier.
+    pub fn with_eviction_callback(mut self, on_evict: EvictionCallback) -> Self {
+        self.on_evict = Some(on_evict);
+        self
+    }
+
+    /// Persists the highest contiguous observed Vaa sequence in the watermark
+    /// store. The watermark persisted by a previous run is loaded when the
+    /// store is built, so already processed Vaas are skipped.
+    pub fn with_watermark_store(
+        mut self,
+        watermark_store: Box<dyn SequenceWatermarkStore>,
+    ) -> Self {
+        self.watermark_store = Some(watermark_store);
+        self
+    }
+
+    /// Builds the store. It fails if the persisted watermark cannot be loaded.
+    pub fn build(self) -> Result<Arc<Store>> {
+        let seq_watermark = match &self.watermark_store {
+            Some(watermark_store) => watermark_store.load()?,
+            None => None,
+        };
+        Ok(Arc::new(Store::build(self, seq_watermark)))
+    }
 }
 
 impl Store {
     pub fn new(update_tx: Sender<()>, cache_size: u64) -> Arc<Self> {
-        Arc::new(Self {
-            storage: Storage::new(cache_size),
+        Self::new_with_config(update_tx, cache_size, StoreConfig::default())
+    }
+
+    pub fn new_with_config(
+        update_tx: Sender<()>,
+        cache_size: u64,
+        config: StoreConfig,
+    ) -> Arc<Self> {
+        Arc::new(Self::build(
+            StoreBuilder::new(update_tx, cache_size).with_config(config),
+            None,
+        ))
+    }
+
+    fn build(builder: StoreBuilder, seq_watermark: Option<u64>) -> Self {
+        let StoreBuilder {
+            update_tx,
+            cache_size,
+            config,
+            metrics_recorder,
+            on_evict,
+            watermark_store,
+        } = builder;
+        let storage = match on_evict {
+            Some(on_evict) => Storage::new(cache_size).with_on_evict(on_evict),
+            None => Storage::new(cache_size),
+        };
+
+        Self {
+            storage,
+            unverified_storage: Storage::new(cache_size),
             observed_vaa_seqs: RwLock::new(Default::default()),
             guardian_set: RwLock::new(Default::default()),
+            guardian_set_retired_at: RwLock::new(BTreeMap::new()),
             update_tx,
+            completion_tx: tokio::sync::broadcast::channel(COMPLETION_CHAN_LEN).0,
             last_completed_update_at: RwLock::new(None),
-        })
+            latest_completed_slot: RwLock::new(None),
+            finalized_slots: RwLock::new(BTreeSet::new()),
+            started_at: Instant::now(),
+            first_ready_at: OnceLock::new(),
+            batched_subscribers: RwLock::new(Vec::new()),
+            // The permits are sized before the config is moved.
+            update_permits: config.max_concurrent_updates.map(Semaphore::new),
+            proof_pool: ProofPool::new(config.proof_workers),
+            config,
+            metrics: StoreMetrics::new(),
+            feed_metadata: RwLock::new(HashMap::new()),
+            seq_watermark: RwLock::new(seq_watermark),
+            watermark_store,
+            emitter_high_water_seqs: RwLock::new(HashMap::new()),
+            update_sinks: RwLock::new(Vec::new()),
+            degraded: Arc::new(AtomicBool::new(false)),
+            update_listeners: RwLock::new(Vec::new()),
+            next_listener_id: AtomicUsize::new(0),
+            debounced_signals: OnceLock::new(),
+            enabled_message_types: RwLock::new(MessageType::iter().collect()),
+            pending_final_slots: RwLock::new(BTreeSet::new()),
+            read_only: AtomicBool::new(false),
+            seq_frontier: RwLock::new(None),
+            no_guardian_set_warned_at: RwLock::new(None),
+            feed_groups: RwLock::new(HashMap::new()),
+            metrics_recorder: metrics_recorder
+                .unwrap_or_else(|| Box::new(NoopStoreMetricsRecorder)),
+            vaa_sources: RwLock::new(BTreeMap::new()),
+            accumulator_sources: RwLock::new(BTreeMap::new()),
+            price_feed_update_cache: RwLock::new(HashMap::new()),
+            feed_waiters: RwLock::new(HashMap::new()),
+        }
     }
 
     /// Stores the update data in the store
     pub async fn store_update(&self, update: Update) -> Result<()> {
+        self.store_update_from_source(update, DEFAULT_SOURCE).await
+    }
+
+    /// Stores the update data received from the given source. With a source
+    /// quorum above 1, a Vaa is only used once enough distinct sources have
+    /// delivered it.
+    pub async fn store_update_from_source(&self, update: Update, source: &str) -> Result<()> {
+        self.store_update_with_signal_policy(update, source, BulkSignalPolicy::PerSlot)
+            .await
+    }
+
+    /// Stores the update data, signaling the slots it completes as the
+    /// policy says. The policy only affects the slots completed by this
+    /// update.
+    #[tracing::instrument(name = "store_update", skip_all, fields(slot, sequence))]
+    async fn store_update_with_signal_policy(
+        &self,
+        update: Update,
+        source: &str,
+        signal_policy: BulkSignalPolicy,
+    ) -> Result<()> {
+        let _permit = match (&self.update_permits, self.config.max_concurrent_updates) {
+            (Some(update_permits), Some(max_concurrent_updates)) => Some(
+                tokio::time::timeout(self.config.update_permit_timeout, update_permits.acquire())
+                    .await
+                    .map_err(|_| StoreError::Overloaded {
+                        max_concurrent_updates,
+                    })??,
+            ),
+            _ => None,
+        };
+
+        if self.is_read_only() {
+            if self.config.strict_read_only {
+                return Err(StoreError::ReadOnly.into());
+            }
+            return Ok(());
+        }
+
         // The slot that the update is originating from. It should be available
         // in all the updates.
         let slot = match update {
@@ -126,27 +760,130 @@ impl Store {
                 // FIXME: Move to wormhole.rs
                 let vaa =
                     serde_wormhole::from_slice::<Vaa<&serde_wormhole::RawMessage>>(&vaa_bytes)?;
+                self.record_event(|recorder| recorder.record_vaa_received());
 
                 if vaa.emitter_chain != Chain::Pythnet
                     || vaa.emitter_address != Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS)
                 {
+                    self.record_event(|recorder| {
+                        recorder.record_vaa_ignored(VaaIgnoreReason::OtherEmitter)
+                    });
                     return Ok(()); // Ignore VAA from other emitters
                 }
 
-                if self.observed_vaa_seqs.read().await.contains(&vaa.sequence) {
+                tracing::Span::current().record("sequence", vaa.sequence);
+
+                if self
+                    .seq_watermark
+                    .read()
+                    .await
+                    .is_some_and(|seq_watermark| vaa.sequence <= seq_watermark)
+                {
+                    self.record_event(|recorder| {
+                        recorder.record_vaa_ignored(VaaIgnoreReason::BelowWatermark)
+                    });
+                    return Ok(()); // Ignore VAA if it is at or below the watermark
+                }
+
+                let is_processed = match self.config.vaa_dedup_strategy {
+                    VaaDedupStrategy::SequenceCount => {
+                        self.observed_vaa_seqs.read().await.contains(&vaa.sequence)
+                    }
+                    VaaDedupStrategy::Slot => {
+                        // The Vaa is not verified yet, so a malformed payload
+                        // is ignored as an invalid Vaa rather than failing the
+                        // update.
+                        let message = match WormholeMessage::try_from_bytes(vaa.payload) {
+                            Ok(message) => message,
+                            Err(err) => {
+                                tracing::info!("Ignoring VAA with invalid payload: {:?}", err);
+                                self.record_event(|recorder| {
+                                    recorder.record_vaa_ignored(VaaIgnoreReason::Invalid)
+                                });
+                                return Ok(());
+                            }
+                        };
+                        let WormholePayload::Merkle(proof) = message.payload;
+                        self.finalized_slots.read().await.contains(&proof.slot)
+                            || self.storage.is_older_than_retained_slots(proof.slot).await
+                    }
+                };
+                if is_processed {
+                    self.record_event(|recorder| {
+                        recorder.record_vaa_ignored(VaaIgnoreReason::AlreadyObserved)
+                    });
                     return Ok(()); // Ignore VAA if we have already seen it
                 }
 
+                if let Some(replay_window) = self.config.replay_window {
+                    if let Some(high_water_sequence) = self
+                        .emitter_high_water_seqs
+                        .read()
+                        .await
+                        .get(&(vaa.emitter_chain, vaa.emitter_address))
+                    {
+                        if vaa.sequence.saturating_add(replay_window) < *high_water_sequence {
+                            self.record_event(|recorder| {
+                                recorder.record_vaa_ignored(VaaIgnoreReason::Replayed)
+                            });
+                            return Err(StoreError::ReplayedVaa {
+                                sequence:            vaa.sequence,
+                                high_water_sequence: *high_water_sequence,
+                            }
+                            .into());
+                        }
+                    }
+                }
+
+                // Without any guardian set every Vaa fails verification, which
+                // usually means the guardian sets were never fetched.
+                if self.guardian_set.read().await.is_empty() {
+                    self.warn_no_guardian_set().await;
+                    self.record_event(|recorder| {
+                        recorder.record_vaa_ignored(VaaIgnoreReason::NoGuardianSet)
+                    });
+                    return Ok(());
+                }
+                self.metrics.no_guardian_set.set(0);
+
                 let vaa = verify_vaa(self, vaa).await;
 
-                let vaa = match vaa {
-                    Ok(vaa) => vaa,
+                let (vaa, guardian_set_index) = match vaa {
+                    Ok(verified) => verified,
                     Err(err) => {
-                        log::info!("Ignoring invalid VAA: {:?}", err);
+                        tracing::info!("Ignoring invalid VAA: {:?}", err);
+                        self.record_event(|recorder| {
+                            recorder.record_vaa_ignored(VaaIgnoreReason::Invalid)
+                        });
                         return Ok(());
                     }
                 };
 
+                let received_at = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                if let Some(max_vaa_age) = self.config.max_vaa_age {
+                    let age = received_at.saturating_sub(Duration::from_secs(vaa.timestamp as u64));
+                    if age > max_vaa_age {
+                        self.record_event(|recorder| {
+                            recorder.record_vaa_ignored(VaaIgnoreReason::Stale)
+                        });
+                        return Err(StoreError::StaleVaa {
+                            sequence:  vaa.sequence,
+                            timestamp: vaa.timestamp,
+                            max_age:   max_vaa_age,
+                        }
+                        .into());
+                    }
+                }
+
+                if !self.has_source_quorum(&vaa, source).await {
+                    return Ok(()); // Wait for the other sources to deliver the VAA
+                }
+
+                self.metrics
+                    .vaa_receive_latency
+                    .observe(received_at.as_secs_f64() - vaa.timestamp as f64);
+                self.metrics.observed_vaas.inc();
+
                 {
                     let mut observed_vaa_seqs = self.observed_vaa_seqs.write().await;
                     observed_vaa_seqs.insert(vaa.sequence);
@@ -155,21 +892,82 @@ impl Store {
                     }
                 }
 
+                self.advance_seq_watermark(vaa.sequence).await;
+                self.advance_seq_frontier(vaa.sequence).await;
+
+                if self.config.replay_window.is_some() {
+                    let mut emitter_high_water_seqs = self.emitter_high_water_seqs.write().await;
+                    let high_water_sequence = emitter_high_water_seqs
+                        .entry((vaa.emitter_chain, vaa.emitter_address))
+                        .or_insert(vaa.sequence);
+                    *high_water_sequence = (*high_water_sequence).max(vaa.sequence);
+                }
+
                 match WormholeMessage::try_from_bytes(vaa.payload)?.payload {
                     WormholePayload::Merkle(proof) => {
-                        log::info!("Storing merkle proof for slot {:?}", proof.slot,);
-                        store_wormhole_merkle_verified_message(self, proof.clone(), vaa_bytes)
-                            .await?;
+                        tracing::Span::current().record("slot", proof.slot);
+                        tracing::info!("Storing merkle proof for slot {:?}", proof.slot,);
+                        store_wormhole_merkle_verified_message(
+                            self,
+                            proof.clone(),
+                            vaa_bytes,
+                            vaa.sequence,
+                            guardian_set_index,
+                        )
+                        .await?;
                         proof.slot
                     }
                 }
             }
             Update::AccumulatorMessages(accumulator_messages) => {
                 let slot = accumulator_messages.slot;
-                log::info!("Storing accumulator messages for slot {:?}.", slot,);
-                self.storage
-                    .store_accumulator_messages(accumulator_messages)
-                    .await?;
+                tracing::Span::current().record("slot", slot);
+                if accumulator_messages.raw_messages.is_empty() {
+                    self.metrics.empty_slots.inc();
+                    if self.config.reject_empty_slots {
+                        return Err(StoreError::EmptySlot { slot }.into());
+                    }
+                }
+                tracing::info!("Storing accumulator messages for slot {:?}.", slot,);
+                if source != DEFAULT_SOURCE {
+                    let mut accumulator_sources = self.accumulator_sources.write().await;
+                    accumulator_sources.insert(slot, source.to_string());
+                    while accumulator_sources.len() > OBSERVED_CACHE_SIZE {
+                        accumulator_sources.pop_first();
+                    }
+                }
+                // The Vaa carries the final root of the slot, so the message
+                // set is not extended any more once it arrives.
+                if self.config.incremental_accumulation
+                    && self
+                        .storage
+                        .fetch_wormhole_merkle_state(slot)
+                        .await?
+                        .is_none()
+                {
+                    self.storage
+                        .extend_accumulator_messages(accumulator_messages)
+                        .await?;
+                } else {
+                    if let Some(stored) = self.storage.fetch_accumulator_messages(slot).await? {
+                        if stored != accumulator_messages {
+                            self.metrics.conflicting_accumulator_messages.inc();
+                            match self.config.accumulator_conflict_policy {
+                                AccumulatorConflictPolicy::FirstWins => return Ok(()),
+                                AccumulatorConflictPolicy::LastWins => {}
+                                AccumulatorConflictPolicy::RejectConflict => {
+                                    return Err(StoreError::ConflictingAccumulatorMessages {
+                                        slot,
+                                    }
+                                    .into());
+                                }
+                            }
+                        }
+                    }
+                    self.storage
+                        .store_accumulator_messages(accumulator_messages)
+                        .await?;
+                }
                 slot
             }
         };
@@ -185,134 +983,2401 @@ impl Store {
                 _ => return Ok(()),
             };
 
+        // A finalized slot is not rebuilt when its messages are delivered
+        // again.
+        if self.config.vaa_dedup_strategy == VaaDedupStrategy::Slot
+            && self.finalized_slots.read().await.contains(&slot)
+        {
+            return Ok(());
+        }
+
+        if self.config.finality_confirmations == 0 {
+            return self
+                .complete_slot(
+                    slot,
+                    accumulator_messages,
+                    wormhole_merkle_state,
+                    signal_policy,
+                )
+                .await;
+        }
+
+        // The complete slot is buffered until enough newer slots are
+        // observed, which might make older buffered slots final.
+        for slot in self.take_final_slots(slot).await {
+            let accumulator_messages = self.storage.fetch_accumulator_messages(slot).await?;
+            let wormhole_merkle_state = self.storage.fetch_wormhole_merkle_state(slot).await?;
+            if let (Some(accumulator_messages), Some(wormhole_merkle_state)) =
+                (accumulator_messages, wormhole_merkle_state)
+            {
+                self.complete_slot(
+                    slot,
+                    accumulator_messages,
+                    wormhole_merkle_state,
+                    signal_policy,
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Buffers the complete slot and takes the buffered slots that are at
+    /// least `finality_confirmations` slots behind the newest complete slot.
+    async fn take_final_slots(&self, slot: Slot) -> BTreeSet<Slot> {
+        let mut pending_final_slots = self.pending_final_slots.write().await;
+        pending_final_slots.insert(slot);
+
+        // The newest slot is never final, hence it always remains buffered.
+        let newest_slot = *pending_final_slots.last().unwrap_or(&slot);
+        match newest_slot.checked_sub(self.config.finality_confirmations) {
+            Some(final_slot) => {
+                let not_final_slots = pending_final_slots.split_off(&(final_slot + 1));
+                std::mem::replace(&mut *pending_final_slots, not_final_slots)
+            }
+            None => BTreeSet::new(),
+        }
+    }
+
+    /// Builds the message states of a complete slot and notifies about them.
+    /// The api is only signaled per slot under `BulkSignalPolicy::PerSlot`.
+    async fn complete_slot(
+        &self,
+        slot: Slot,
+        accumulator_messages: AccumulatorMessages,
+        wormhole_merkle_state: WormholeMerkleState,
+        signal_policy: BulkSignalPolicy,
+    ) -> Result<()> {
         // Once the accumulator reaches a complete state for a specific slot
         // we can build the message states
-        self.build_message_states(accumulator_messages, wormhole_merkle_state)
+        let message_states = self
+            .build_message_states(accumulator_messages, wormhole_merkle_state)
             .await?;
 
-        self.update_tx.send(()).await?;
+        self.record_event(|recorder| recorder.record_slot_completed(slot, message_states.len()));
+        self.notify_batched_subscribers(&message_states).await;
+        self.emit_to_sinks(&message_states).await;
+        self.notify_update_listeners(slot).await;
+        // Sending only fails when there is no completion stream.
+        let _ = self.completion_tx.send(CompletedSlot { slot });
+
+        if signal_policy == BulkSignalPolicy::PerSlot {
+            self.signal_completed_slot().await;
+        }
 
         self.last_completed_update_at
             .write()
             .await
             .replace(Instant::now());
 
+        {
+            let mut latest_completed_slot = self.latest_completed_slot.write().await;
+            if latest_completed_slot.map_or(true, |latest| latest < slot) {
+                latest_completed_slot.replace(slot);
+            }
+        }
+
+        if self.config.vaa_dedup_strategy == VaaDedupStrategy::Slot {
+            let mut finalized_slots = self.finalized_slots.write().await;
+            finalized_slots.insert(slot);
+            // The slots older than the retained ones are ignored anyway.
+            if let Some(&oldest_slot) = self.storage.wormhole_merkle_state_slots().await.first() {
+                finalized_slots.retain(|finalized_slot| *finalized_slot >= oldest_slot);
+            }
+        }
+
+        if self.first_ready_at.get().is_none() && self.is_ready().await {
+            let _ = self.first_ready_at.set(Instant::now());
+        }
+
         Ok(())
     }
 
-    async fn build_message_states(
+    /// Ingests a batch of updates, such as recent updates of important feeds
+    /// fetched from an archive on startup, and reports the slots and price
+    /// feeds that became available, so the readiness of critical feeds can be
+    /// asserted before the live stream catches up.
+    pub async fn prime_feeds(&self, updates: Vec<Update>) -> Result<PrimeReport> {
+        let mut completions = self.completion_tx.subscribe();
+        for update in updates {
+            self.store_update(update).await?;
+        }
+
+        let mut slots = BTreeSet::new();
+        while let Ok(CompletedSlot { slot }) = completions.try_recv() {
+            slots.insert(slot);
+        }
+
+        let mut price_ids = BTreeSet::new();
+        for slot in slots.iter() {
+            let Some(accumulator_messages) = self.storage.fetch_accumulator_messages(*slot).await?
+            else {
+                continue;
+            };
+            for raw_message in accumulator_messages.raw_messages.iter() {
+                if let Ok(Message::PriceFeedMessage(price_feed)) =
+                    from_slice::<BigEndian, Message>(raw_message.as_ref())
+                {
+                    price_ids.insert(price_feed.feed_id);
+                }
+            }
+        }
+
+        Ok(PrimeReport {
+            slots:     slots.into_iter().collect(),
+            price_ids: price_ids.into_iter().map(PriceIdentifier::new).collect(),
+        })
+    }
+
+    /// Stores a batch of updates, signaling the slots they complete to the
+    /// api as the policy says. Updates stored concurrently outside of the
+    /// batch are signaled as usual.
+    pub async fn store_updates_in_bulk(
         &self,
-        accumulator_messages: AccumulatorMessages,
-        wormhole_merkle_state: WormholeMerkleState,
+        updates: Vec<Update>,
+        policy: BulkSignalPolicy,
     ) -> Result<()> {
-        let wormhole_merkle_message_states_proofs =
-            construct_message_states_proofs(&accumulator_messages, &wormhole_merkle_state)?;
+        let mut result = Ok(());
+        for update in updates {
+            if let Err(err) = self
+                .store_update_with_signal_policy(update, DEFAULT_SOURCE, policy)
+                .await
+            {
+                result = Err(err);
+                break;
+            }
+        }
 
-        let current_time: UnixTimestamp =
-            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+        // The slots completed before a failure are signaled all the same.
+        if policy == BulkSignalPolicy::Once {
+            self.signal_update().await;
+        }
+        result
+    }
 
-        let message_states = accumulator_messages
-            .raw_messages
-            .into_iter()
-            .enumerate()
-            .map(|(idx, raw_message)| {
-                Ok(MessageState::new(
-                    from_slice::<BigEndian, _>(raw_message.as_ref())
-                        .map_err(|e| anyhow!("Failed to deserialize message: {:?}", e))?,
-                    raw_message,
-                    ProofSet {
-                        wormhole_merkle_proof: wormhole_merkle_message_states_proofs
-                            .get(idx)
-                            .ok_or(anyhow!("Missing proof for message"))?
-                            .clone(),
-                    },
-                    accumulator_messages.slot,
-                    current_time,
-                ))
-            })
-            .collect::<Result<Vec<_>>>()?;
+    /// Signals the api that a slot was completed, as configured by
+    /// `signal_coalescing`.
+    async fn signal_completed_slot(&self) {
+        let SignalCoalescing::Debounced(window) = self.config.signal_coalescing else {
+            return self.signal_update().await;
+        };
+
+        let debounced_signals = self.debounced_signals.get_or_init(|| {
+            let (signals_tx, signals_rx) = tokio::sync::mpsc::channel(1);
+            tokio::spawn(debounce_update_signals(
+                signals_rx,
+                window,
+                self.update_tx.clone(),
+                self.degraded.clone(),
+                self.metrics.update_notification_latency.clone(),
+            ));
+            signals_tx
+        });
+        // A full channel already holds a pending signal, which covers this slot.
+        let _ = debounced_signals.try_send(());
+    }
+
+    /// Signals the api that updates were completed.
+    async fn signal_update(&self) {
+        send_update_signal(
+            &self.update_tx,
+            &self.degraded,
+            &self.metrics.update_notification_latency,
+        )
+        .await;
+    }
 
-        log::info!("Message states len: {:?}", message_states.len());
+    /// Records that the verified Vaa is delivered by the source and returns
+    /// whether enough distinct sources delivered it to reach the quorum.
+    async fn has_source_quorum(
+        &self,
+        vaa: &Vaa<&serde_wormhole::RawMessage>,
+        source: &str,
+    ) -> bool {
+        if self.config.source_quorum <= 1 {
+            return true;
+        }
 
-        self.storage.store_message_states(message_states).await?;
+        let key = (vaa.sequence, vaa.payload.to_vec());
+        let mut vaa_sources = self.vaa_sources.write().await;
+        let sources = vaa_sources.entry(key.clone()).or_default();
+        sources.insert(source.to_string());
+        if sources.len() >= self.config.source_quorum {
+            vaa_sources.remove(&key);
+            return true;
+        }
 
-        Ok(())
+        tracing::debug!(
+            "VAA {} delivered by {} of {} sources",
+            vaa.sequence,
+            sources.len(),
+            self.config.source_quorum
+        );
+        while vaa_sources.len() > OBSERVED_CACHE_SIZE {
+            vaa_sources.pop_first();
+        }
+        false
     }
 
-    pub async fn update_guardian_set(&self, id: u32, guardian_set: GuardianSet) {
-        let mut guardian_sets = self.guardian_set.write().await;
-        guardian_sets.insert(id, guardian_set);
+    /// Returns whether the price of the message state deviates from the
+    /// previous retained price of its feed by more than
+    /// `max_deviation_ratio`.
+    fn is_suspect(&self, message_state: &MessageState) -> bool {
+        let (Some(max_deviation_ratio), Message::PriceFeedMessage(price_feed)) =
+            (self.config.max_deviation_ratio, &message_state.message)
+        else {
+            return false;
+        };
+        let Some(Message::PriceFeedMessage(previous_price_feed)) = self
+            .storage
+            .fetch_message_state_before(&message_state.key(), &message_state.time())
+            .map(|previous| previous.message)
+        else {
+            return false;
+        };
+
+        let price = price_feed.price as f64 * 10f64.powi(price_feed.exponent);
+        let previous_price =
+            previous_price_feed.price as f64 * 10f64.powi(previous_price_feed.exponent);
+        if previous_price == 0.0 {
+            return false;
+        }
+
+        let suspect = (price - previous_price).abs() / previous_price.abs() > max_deviation_ratio;
+        if suspect {
+            tracing::warn!(
+                "Price {} of feed {:?} deviates anomalously from the previous price {}",
+                price,
+                price_feed.feed_id,
+                previous_price
+            );
+        }
+        suspect
     }
 
-    pub async fn get_price_feeds_with_update_data(
-        &self,
-        price_ids: Vec<PriceIdentifier>,
-        request_time: RequestTime,
-    ) -> Result<PriceFeedsWithUpdateData> {
-        let messages = self
+    /// Returns whether the price feed update changes any of the sink change
+    /// triggers from the previous retained update of its feed. The messages
+    /// of the other types are always considered changed.
+    fn is_changed(&self, message_state: &MessageState) -> bool {
+        let Message::PriceFeedMessage(price_feed) = &message_state.message else {
+            return true;
+        };
+        match self
             .storage
-            .fetch_message_states(
-                price_ids
-                    .iter()
-                    .map(|price_id| price_id.to_bytes())
-                    .collect(),
-                request_time,
-                MessageStateFilter::Only(MessageType::PriceFeedMessage),
-            )
-            .await?;
+            .fetch_message_state_before(&message_state.key(), &message_state.time())
+            .map(|previous| previous.message)
+        {
+            Some(Message::PriceFeedMessage(previous_price_feed)) => self
+                .config
+                .sink_change_triggers
+                .is_changed(&previous_price_feed, price_feed),
+            _ => true,
+        }
+    }
 
-        let price_feeds = messages
-            .iter()
-            .map(|message_state| match message_state.message {
-                Message::PriceFeedMessage(price_feed) => Ok(PriceFeedUpdate {
-                    price_feed,
-                    received_at: message_state.received_at,
-                    slot: message_state.slot,
-                    wormhole_merkle_update_data: construct_update_data(vec![message_state])?
-                        .into_iter()
-                        .next()
-                        .ok_or(anyhow!("Missing update data for message"))?,
-                }),
-                _ => Err(anyhow!("Invalid message state type")),
-            })
-            .collect::<Result<Vec<_>>>()?;
+    /// Records an ingestion event in the Prometheus metrics of the store and
+    /// in the pluggable recorder.
+    fn record_event(&self, record: impl Fn(&dyn StoreMetricsRecorder)) {
+        record(&self.metrics);
+        record(self.metrics_recorder.as_ref());
+    }
 
-        let update_data = construct_update_data(messages.iter().collect())?;
+    /// Flags that a Vaa is received while no guardian set is loaded, logging
+    /// a warning at most once per `NO_GUARDIAN_SET_WARNING_INTERVAL`.
+    async fn warn_no_guardian_set(&self) {
+        self.metrics.no_guardian_set.set(1);
 
-        Ok(PriceFeedsWithUpdateData {
-            price_feeds,
-            wormhole_merkle_update_data: update_data,
-        })
+        let mut warned_at = self.no_guardian_set_warned_at.write().await;
+        if warned_at.is_some_and(|at| at.elapsed() < NO_GUARDIAN_SET_WARNING_INTERVAL) {
+            return;
+        }
+        tracing::warn!("Ignoring VAA as no guardian set is loaded to verify it");
+        warned_at.replace(Instant::now());
     }
 
-    pub async fn get_price_feed_ids(&self) -> HashSet<PriceIdentifier> {
-        self.storage
-            .message_state_keys()
+    /// Advances the sequence frontier over the contiguous observed sequences.
+    /// The first observed sequence becomes the frontier.
+    async fn advance_seq_frontier(&self, sequence: u64) {
+        let observed_vaa_seqs = self.observed_vaa_seqs.read().await;
+        let mut seq_frontier = self.seq_frontier.write().await;
+        let mut new_seq_frontier = seq_frontier.unwrap_or(sequence);
+        while observed_vaa_seqs.contains(&(new_seq_frontier + 1)) {
+            new_seq_frontier += 1;
+        }
+        seq_frontier.replace(new_seq_frontier);
+    }
+
+    /// Returns the ranges of sequences missing between the sequence frontier
+    /// and the highest observed sequence, so they can be backfilled.
+    pub async fn missing_sequences(&self) -> Vec<Range<u64>> {
+        let seq_frontier = match *self.seq_frontier.read().await {
+            Some(seq_frontier) => seq_frontier,
+            None => return vec![],
+        };
+
+        let mut missing_sequences = vec![];
+        let mut previous = seq_frontier;
+        for sequence in self
+            .observed_vaa_seqs
+            .read()
             .await
-            .iter()
-            .map(|key| PriceIdentifier::new(key.feed_id))
-            .collect()
+            .range(seq_frontier + 1..)
+        {
+            if *sequence > previous + 1 {
+                missing_sequences.push(previous + 1..*sequence);
+            }
+            previous = *sequence;
+        }
+        missing_sequences
     }
 
-    pub async fn is_ready(&self) -> bool {
-        let last_completed_update_at = self.last_completed_update_at.read().await;
-        match last_completed_update_at.as_ref() {
-            Some(last_completed_update_at) => {
-                last_completed_update_at.elapsed() < READINESS_STALENESS_THRESHOLD
+    /// Advances the sequence watermark over the contiguous observed
+    /// sequences and persists it. Without a persisted watermark, the first
+    /// observed sequence becomes the watermark.
+    async fn advance_seq_watermark(&self, sequence: u64) {
+        let watermark_store = match &self.watermark_store {
+            Some(watermark_store) => watermark_store,
+            None => return,
+        };
+
+        let observed_vaa_seqs = self.observed_vaa_seqs.read().await;
+        let mut seq_watermark = self.seq_watermark.write().await;
+        let mut new_seq_watermark = seq_watermark.unwrap_or(sequence);
+        while observed_vaa_seqs.contains(&(new_seq_watermark + 1)) {
+            new_seq_watermark += 1;
+        }
+
+        if *seq_watermark != Some(new_seq_watermark) {
+            *seq_watermark = Some(new_seq_watermark);
+            if let Err(err) = watermark_store.save(new_seq_watermark) {
+                tracing::warn!("Failed to persist the sequence watermark: {:?}", err);
             }
-            None => false,
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use {
-        super::{
-            types::Slot,
-            *,
-        },
-        futures::future::join_all,
+    /// Returns whether the message is of an enabled type and in the shard,
+    /// the messages of a slot that are stored unless their content is
+    /// rejected.
+    fn is_message_kept(
+        &self,
+        message: &Message,
+        enabled_message_types: &HashSet<MessageType>,
+    ) -> bool {
+        enabled_message_types.contains(&MessageType::from(message))
+            && self
+                .config
+                .shard
+                .map_or(true, |shard| shard.contains(&message.feed_id()))
+    }
+
+    /// Returns whether the message of the slot is stored as configured: it
+    /// must be kept by `is_message_kept`, published within the publish time
+    /// window of the slot time when that time is known, and its publish
+    /// times must not be inverted under `InvertedPublishTimePolicy::Reject`.
+    /// The rejected messages are logged and counted.
+    fn admit_message(
+        &self,
+        message: &Message,
+        slot: Slot,
+        slot_time: Option<UnixTimestamp>,
+        enabled_message_types: &HashSet<MessageType>,
+    ) -> bool {
+        if !enabled_message_types.contains(&MessageType::from(message)) {
+            self.metrics.skipped_messages.inc();
+            return false;
+        }
+        if !self.is_message_kept(message, enabled_message_types) {
+            return false;
+        }
+
+        if let (Some(window), Some(slot_time)) = (self.config.publish_time_window, slot_time) {
+            if message.publish_time().abs_diff(slot_time) > window.as_secs() {
+                tracing::warn!(
+                    "Publish time {} of feed {:?} is inconsistent with slot {} at {}",
+                    message.publish_time(),
+                    message.feed_id(),
+                    slot,
+                    slot_time
+                );
+                self.metrics.inconsistent_publish_times.inc();
+                if self.config.reject_inconsistent_publish_times {
+                    return false;
+                }
+            }
+        }
+
+        if let Message::PriceFeedMessage(price_feed) = message {
+            if price_feed.prev_publish_time > price_feed.publish_time {
+                tracing::warn!(
+                    "Previous publish time {} of feed {:?} is after its publish time {}",
+                    price_feed.prev_publish_time,
+                    price_feed.feed_id,
+                    price_feed.publish_time
+                );
+                self.metrics.inverted_publish_times.inc();
+                if self.config.inverted_publish_time_policy == InvertedPublishTimePolicy::Reject {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns whether the served price feed of the admitted message gets its
+    /// previous publish time clamped, and counts it if so.
+    fn needs_clamp(&self, message: &Message) -> bool {
+        let clamped = match message {
+            Message::PriceFeedMessage(price_feed) => {
+                self.config.inverted_publish_time_policy == InvertedPublishTimePolicy::Clamp
+                    && price_feed.prev_publish_time > price_feed.publish_time
+            }
+            _ => false,
+        };
+        if clamped {
+            self.metrics.clamped_publish_times.inc();
+        }
+        clamped
+    }
+
+    /// Builds the message states of a complete slot. The messages keep the
+    /// order of `accumulator_messages.raw_messages`, which is the canonical
+    /// order of the leaves of the merkle tree. Messages whose tree does not
+    /// reproduce the root signed in the Vaa, for instance because they were
+    /// reordered, are rejected with a `MerkleRootMismatch` error.
+    #[tracing::instrument(
+        skip_all,
+        fields(
+            slot = accumulator_messages.slot,
+            num_messages = accumulator_messages.raw_messages.len(),
+        )
+    )]
+    async fn build_message_states(
+        &self,
+        accumulator_messages: AccumulatorMessages,
+        wormhole_merkle_state: WormholeMerkleState,
+    ) -> Result<Vec<MessageState>> {
+        let accumulator_messages = Arc::new(accumulator_messages);
+        let wormhole_merkle_state = Arc::new(wormhole_merkle_state);
+        let (merkle_tree, wormhole_merkle_message_states_proofs) =
+            construct_message_states_proofs_pooled(
+                &self.proof_pool,
+                accumulator_messages.clone(),
+                wormhole_merkle_state.clone(),
+            )
+            .await?;
+        // The workers dropped their handle of the messages once done with them.
+        let accumulator_messages = Arc::try_unwrap(accumulator_messages)
+            .unwrap_or_else(|accumulator_messages| (*accumulator_messages).clone());
+        if let Some(merkle_tree) = merkle_tree {
+            self.metrics.merkle_tree_builds.inc();
+            self.storage
+                .store_merkle_tree(accumulator_messages.slot, merkle_tree)
+                .await;
+        }
+
+        self.build_message_states_with_proofs(
+            accumulator_messages,
+            &wormhole_merkle_state,
+            wormhole_merkle_message_states_proofs,
+        )
+        .await
+    }
+
+    /// Builds and stores the message states of the accumulator messages with
+    /// their proofs, in the order of the messages. Messages without a proof
+    /// are skipped so the rest of the slot stays available.
+    async fn build_message_states_with_proofs(
+        &self,
+        accumulator_messages: AccumulatorMessages,
+        wormhole_merkle_state: &WormholeMerkleState,
+        wormhole_merkle_message_states_proofs: Vec<WormholeMerkleMessageProof>,
+    ) -> Result<Vec<MessageState>> {
+        let current_time: UnixTimestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+
+        let slot_time = match self.config.publish_time_window {
+            Some(_) => Some(
+                serde_wormhole::from_slice::<Vaa<&serde_wormhole::RawMessage>>(
+                    &wormhole_merkle_state.vaa,
+                )?
+                .timestamp as UnixTimestamp,
+            ),
+            None => None,
+        };
+
+        let enabled_message_types = self.enabled_message_types.read().await.clone();
+        let source = self
+            .accumulator_sources
+            .read()
+            .await
+            .get(&accumulator_messages.slot)
+            .cloned();
+        let messages = accumulator_messages
+            .raw_messages
+            .into_iter()
+            .enumerate()
+            .map(|(idx, raw_message)| {
+                let message: Message = from_slice::<BigEndian, _>(raw_message.as_ref())
+                    .map_err(|e| anyhow!("Failed to deserialize message: {:?}", e))?;
+                Ok((idx, message, raw_message))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let message_states = messages
+            .into_iter()
+            .filter(|(_, message, _)| {
+                self.admit_message(
+                    message,
+                    accumulator_messages.slot,
+                    slot_time,
+                    &enabled_message_types,
+                )
+            })
+            .filter_map(|(idx, message, raw_message)| {
+                match wormhole_merkle_message_states_proofs.get(idx) {
+                    Some(proof) => Some((message, raw_message, proof.clone())),
+                    None => {
+                        tracing::error!(
+                            "Missing proof for message of feed {:?} in slot {}",
+                            message.feed_id(),
+                            accumulator_messages.slot
+                        );
+                        self.metrics.missing_proofs.inc();
+                        None
+                    }
+                }
+            })
+            .map(|(message, raw_message, wormhole_merkle_proof)| {
+                // The message is stored as proven by the merkle root, so its
+                // update data still verifies. Only the served price feed is
+                // clamped.
+                let clamped = self.needs_clamp(&message);
+
+                let mut message_state = MessageState::new(
+                    message,
+                    raw_message,
+                    ProofSet {
+                        wormhole_merkle_proof,
+                    },
+                    accumulator_messages.slot,
+                    current_time,
+                );
+                message_state.suspect = self.is_suspect(&message_state);
+                message_state.clamped = clamped;
+                message_state.source = source.clone();
+                if self.config.cache_checksums {
+                    message_state.with_checksum()
+                } else {
+                    message_state
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if self.config.json_logging {
+            let feed_count = message_states
+                .iter()
+                .map(|message_state| message_state.message.feed_id())
+                .collect::<HashSet<_>>()
+                .len();
+            tracing::info!(
+                "{}",
+                serde_json::json!({
+                    "slot": accumulator_messages.slot,
+                    "message_count": message_states.len(),
+                    "feed_count": feed_count,
+                    "root": hex::encode(wormhole_merkle_state.root.root),
+                    "sequence": wormhole_merkle_state.sequence,
+                })
+            );
+        } else {
+            tracing::info!("Message states len: {:?}", message_states.len());
+        }
+
+        self.storage
+            .store_message_states(message_states.clone())
+            .await?;
+        self.notify_feed_waiters(&message_states).await;
+
+        // The cached updates of the feeds are superseded by the new slot.
+        if self.config.price_feed_update_cache_ttl.is_some() {
+            let mut price_feed_update_cache = self.price_feed_update_cache.write().await;
+            for message_state in message_states.iter() {
+                price_feed_update_cache
+                    .remove(&PriceIdentifier::new(message_state.message.feed_id()));
+            }
+        }
+
+        Ok(message_states)
+    }
+
+    /// Resolves the waiters of the price feeds among the stored message
+    /// states.
+    async fn notify_feed_waiters(&self, message_states: &[MessageState]) {
+        let mut feed_waiters = self.feed_waiters.write().await;
+        if feed_waiters.is_empty() {
+            return;
+        }
+
+        for message_state in message_states {
+            let price_id = PriceIdentifier::new(message_state.message.feed_id());
+            let Some(waiters) = feed_waiters.remove(&price_id) else {
+                continue;
+            };
+            match build_price_feed_update(message_state) {
+                Ok(price_feed_update) => {
+                    for waiter in waiters {
+                        // The waiter might have given up waiting.
+                        let _ = waiter.send(price_feed_update.clone());
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to notify the first update of {}: {:?}",
+                        price_id,
+                        err
+                    )
+                }
+            }
+        }
+    }
+
+    /// Sends the price feed updates of a completed slot to the batched
+    /// subscribers, one batch per subscriber. Batches are not waited for, so
+    /// a slow subscriber never holds up the slot: a batch arriving on a full
+    /// buffer is dropped, and a subscriber whose buffer stays full for longer
+    /// than its maximum latency is dropped, ending its stream.
+    async fn notify_batched_subscribers(&self, message_states: &[MessageState]) {
+        let mut batches = Vec::new();
+        {
+            let mut subscribers = self.batched_subscribers.write().await;
+            subscribers.retain_mut(|subscriber| {
+                if subscriber.is_closed() {
+                    return false;
+                }
+                if subscriber.is_lagging() {
+                    tracing::warn!("Dropping a batched subscriber lagging behind");
+                    return false;
+                }
+                true
+            });
+
+            for subscriber in subscribers.iter_mut() {
+                let mut batch = Vec::new();
+                for message_state in message_states {
+                    let Message::PriceFeedMessage(price_feed) = message_state.message else {
+                        continue;
+                    };
+                    if !subscriber.accepts(&price_feed) {
+                        continue;
+                    }
+
+                    match build_price_feed_update(message_state) {
+                        Ok(mut price_feed_update) => {
+                            price_feed_update.links_previous =
+                                subscriber.links_previous(&price_feed);
+                            batch.push(price_feed_update);
+                        }
+                        Err(err) => tracing::error!(
+                            "Failed to build the update of feed {:?} for a batch: {:?}",
+                            price_feed.feed_id,
+                            err
+                        ),
+                    }
+                }
+
+                if !batch.is_empty() {
+                    batches.push((subscriber.sender.clone(), batch));
+                }
+            }
+        }
+
+        for (sender, batch) in batches {
+            if let Err(err) = sender.try_send(batch) {
+                tracing::warn!("Dropping batch for a slow subscriber: {:?}", err);
+            }
+        }
+    }
+
+    /// Emits the message states to all the sinks concurrently. Errors are
+    /// logged per sink and do not fail the update.
+    async fn emit_to_sinks(&self, message_states: &[MessageState]) {
+        let update_sinks = self.update_sinks.read().await;
+        if update_sinks.is_empty() {
+            return;
+        }
+
+        let changed_states = message_states
+            .iter()
+            .filter(|message_state| self.is_changed(message_state))
+            .cloned()
+            .collect::<Vec<_>>();
+        if changed_states.is_empty() {
+            return;
+        }
+
+        let results = join_all(
+            update_sinks
+                .iter()
+                .map(|update_sink| update_sink.emit(&changed_states)),
+        )
+        .await;
+
+        for result in results {
+            if let Err(err) = result {
+                tracing::warn!("Update sink failed to emit: {:?}", err);
+            }
+        }
+    }
+
+    /// Notifies the listeners of the completed slot without waiting for any
+    /// of them. Listeners with a full buffer miss the notification and the
+    /// closed ones are removed.
+    async fn notify_update_listeners(&self, slot: Slot) {
+        let mut update_listeners = self.update_listeners.write().await;
+        update_listeners.retain(|update_listener| {
+            match update_listener.sender.try_send(CompletedSlot { slot }) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.metrics
+                        .dropped_notifications
+                        .get_or_create(&StoreMetrics::listener_labels(update_listener.id))
+                        .inc();
+                    true
+                }
+                Err(TrySendError::Closed(_)) => false,
+            }
+        });
+    }
+
+    /// Registers a listener notified of each completed slot through a
+    /// channel with the given buffer size. The listener is identified in the
+    /// metrics by the order of registration, starting from 0.
+    pub async fn add_update_listener(&self, buffer: usize) -> Receiver<CompletedSlot> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(buffer);
+        let id = self.next_listener_id.fetch_add(1, Ordering::Relaxed);
+        self.update_listeners
+            .write()
+            .await
+            .push(UpdateListener { id, sender });
+        receiver
+    }
+
+    /// Enables or disables storing and serving the messages of the given
+    /// type. It applies to the slots completed afterwards.
+    pub async fn set_message_type_enabled(&self, message_type: MessageType, enabled: bool) {
+        let mut enabled_message_types = self.enabled_message_types.write().await;
+        if enabled {
+            enabled_message_types.insert(message_type);
+        } else {
+            enabled_message_types.remove(&message_type);
+        }
+    }
+
+    /// Registers a sink that receives the message states of each completed
+    /// slot.
+    pub async fn add_update_sink(&self, update_sink: Arc<dyn UpdateSink>) {
+        self.update_sinks.write().await.push(update_sink);
+    }
+
+    /// Subscribes to the given price feeds. All the matching updates of a
+    /// completed slot are delivered as a single batch within `max_latency`.
+    pub async fn subscribe_batched(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        max_latency: Duration,
+    ) -> Receiver<Vec<PriceFeedUpdate>> {
+        self.subscribe_batched_with_change_filter(price_ids, max_latency, HashMap::new())
+            .await
+    }
+
+    /// Like `subscribe_batched`, but an update of a feed with a minimum
+    /// change ratio is only delivered if its price moved by at least that
+    /// ratio since the last delivered update of the feed. The first update
+    /// of a feed is always delivered.
+    pub async fn subscribe_batched_with_change_filter(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        max_latency: Duration,
+        min_change_ratios: HashMap<PriceIdentifier, f64>,
+    ) -> Receiver<Vec<PriceFeedUpdate>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(BATCHED_SUBSCRIPTION_CHAN_LEN);
+        self.batched_subscribers
+            .write()
+            .await
+            .push(BatchedSubscriber::new(
+                price_ids.into_iter().collect(),
+                max_latency,
+                sender,
+                min_change_ratios,
+            ));
+        receiver
+    }
+
+    /// Returns a receiver resolving with the first update of the price feed,
+    /// so clients waiting for a newly listed feed do not have to poll. It
+    /// resolves right away if the feed is already stored.
+    pub async fn notify_on_feed(
+        &self,
+        price_id: PriceIdentifier,
+    ) -> oneshot::Receiver<PriceFeedUpdate> {
+        let (sender, receiver) = oneshot::channel();
+
+        // The waiters are locked before the storage is checked, so an update
+        // stored in between still resolves the waiter.
+        let mut feed_waiters = self.feed_waiters.write().await;
+        let stored = self
+            .storage
+            .fetch_available_message_states(
+                vec![price_id.to_bytes()],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await;
+        match stored.first().map(build_price_feed_update) {
+            Some(Ok(price_feed_update)) => {
+                let _ = sender.send(price_feed_update);
+            }
+            _ => feed_waiters.entry(price_id).or_default().push(sender),
+        }
+        receiver
+    }
+
+    /// Like `subscribe_batched`, but the subscription can be dropped by
+    /// cancelling the token. The subscriber is removed, and its stream ends
+    /// without further batches, by the next completed slot.
+    pub async fn subscribe_batched_cancellable(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        max_latency: Duration,
+        cancellation_token: CancellationToken,
+    ) -> Receiver<Vec<PriceFeedUpdate>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(BATCHED_SUBSCRIPTION_CHAN_LEN);
+        self.batched_subscribers.write().await.push(
+            BatchedSubscriber::new(
+                price_ids.into_iter().collect(),
+                max_latency,
+                sender,
+                HashMap::new(),
+            )
+            .with_cancellation_token(cancellation_token),
+        );
+        receiver
+    }
+
+    pub async fn update_guardian_set(&self, id: u32, guardian_set: GuardianSet) -> Result<()> {
+        self.check_guardian_set_size(id, &guardian_set)?;
+
+        let mut guardian_sets = self.guardian_set.write().await;
+        guardian_sets.insert(id, guardian_set);
+        self.retire_replaced_guardian_sets(&guardian_sets).await?;
+        Ok(())
+    }
+
+    /// Same as `update_guardian_set` for a set already replaced at
+    /// `retired_at`, as recorded on chain. Its retirement grace period runs
+    /// from that time rather than from when it is inserted, so inserting it
+    /// again, such as on every restart, doesn't extend it.
+    pub async fn update_retired_guardian_set(
+        &self,
+        id: u32,
+        guardian_set: GuardianSet,
+        retired_at: UnixTimestamp,
+    ) -> Result<()> {
+        self.check_guardian_set_size(id, &guardian_set)?;
+
+        let mut guardian_sets = self.guardian_set.write().await;
+        guardian_sets.insert(id, guardian_set);
+        self.retire_replaced_guardian_sets(&guardian_sets).await?;
+        self.guardian_set_retired_at
+            .write()
+            .await
+            .insert(id, retired_at);
+        Ok(())
+    }
+
+    /// Replaces all the guardian sets at once, such as during a coordinated
+    /// bootstrap. Concurrent verifications observe either the previous sets
+    /// or the new ones, never a partially populated map. Nothing is replaced
+    /// if any of the sets has too many keys.
+    pub async fn replace_guardian_sets(&self, sets: BTreeMap<u32, GuardianSet>) -> Result<()> {
+        for (id, guardian_set) in sets.iter() {
+            self.check_guardian_set_size(*id, guardian_set)?;
+        }
+
+        let mut guardian_sets = self.guardian_set.write().await;
+        *guardian_sets = sets;
+        self.retire_replaced_guardian_sets(&guardian_sets).await?;
+        Ok(())
+    }
+
+    fn check_guardian_set_size(&self, id: u32, guardian_set: &GuardianSet) -> Result<()> {
+        if guardian_set.keys.len() > self.config.max_guardian_keys {
+            self.metrics.rejected_guardian_sets.inc();
+            return Err(StoreError::TooManyGuardianKeys {
+                index: id,
+                keys:  guardian_set.keys.len(),
+                limit: self.config.max_guardian_keys,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Records the retirement of every set but the newest one, and forgets
+    /// the retirement of the newest one and of the removed ones, as they may
+    /// have been replaced by a previous set of sets. It is called with the
+    /// guardian sets locked for writing.
+    async fn retire_replaced_guardian_sets(
+        &self,
+        guardian_sets: &BTreeMap<u32, GuardianSet>,
+    ) -> Result<()> {
+        let now: UnixTimestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+        let newest = guardian_sets.last_key_value().map(|(&newest, _)| newest);
+
+        let mut guardian_set_retired_at = self.guardian_set_retired_at.write().await;
+        guardian_set_retired_at
+            .retain(|index, _| guardian_sets.contains_key(index) && Some(*index) != newest);
+        for &index in guardian_sets.keys().filter(|&&index| Some(index) != newest) {
+            guardian_set_retired_at.entry(index).or_insert(now);
+        }
+        Ok(())
+    }
+
+    /// Returns whether the guardian set is replaced for longer than the grace
+    /// period.
+    async fn is_past_retirement_grace(&self, guardian_set_index: u32) -> Result<bool> {
+        let now: UnixTimestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+        Ok(self
+            .guardian_set_retired_at
+            .read()
+            .await
+            .get(&guardian_set_index)
+            .is_some_and(|retired_at| {
+                now - retired_at > self.config.retired_set_grace_period.as_secs() as UnixTimestamp
+            }))
+    }
+
+    /// Fails with a `RetiredGuardianSet` error if any of the message states
+    /// is only verified by a guardian set past its retirement grace period,
+    /// unless serving under retired sets is allowed.
+    async fn check_guardian_sets_active(&self, message_states: &[MessageState]) -> Result<()> {
+        if self.config.serve_under_retired_set {
+            return Ok(());
+        }
+
+        for message_state in message_states.iter().filter(|state| !state.unverified) {
+            let Some(wormhole_merkle_state) = self
+                .storage
+                .fetch_wormhole_merkle_state(message_state.slot)
+                .await?
+            else {
+                continue;
+            };
+            if self
+                .is_past_retirement_grace(wormhole_merkle_state.guardian_set_index)
+                .await?
+            {
+                return Err(StoreError::RetiredGuardianSet {
+                    price_id:           PriceIdentifier::new(message_state.message.feed_id()),
+                    guardian_set_index: wormhole_merkle_state.guardian_set_index,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Defines the named group of feeds, replacing any group of the same name.
+    pub async fn define_feed_group(&self, name: String, price_ids: Vec<PriceIdentifier>) {
+        self.feed_groups.write().await.insert(name, price_ids);
+    }
+
+    /// Returns the updates of the feeds of the named group, in the order of
+    /// the group's definition.
+    pub async fn get_group(
+        &self,
+        group_name: &str,
+        request_time: RequestTime,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        let price_ids = self
+            .feed_groups
+            .read()
+            .await
+            .get(group_name)
+            .cloned()
+            .ok_or_else(|| StoreError::UnknownFeedGroup {
+                name: group_name.to_string(),
+            })?;
+        self.get_price_feeds_with_update_data(price_ids, request_time)
+            .await
+    }
+
+    /// Pins the slot so its data is kept regardless of newer slots arriving.
+    pub async fn pin_slot(&self, slot: Slot) -> Result<()> {
+        self.storage
+            .pin_slot(slot, self.config.max_pinned_slots)
+            .await
+    }
+
+    /// Returns a stream of the slots completed from now on. A stream lagging
+    /// more than `COMPLETION_CHAN_LEN` slots behind skips the oldest ones.
+    pub fn completion_stream(&self) -> impl Stream<Item = CompletedSlot> {
+        stream::unfold(self.completion_tx.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(completed_slot) => return Some((completed_slot, receiver)),
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Completion stream skipped {} slots", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Subscribes to the slots whose message states are evicted from the
+    /// cache, so mirrors of the cache can be invalidated.
+    pub fn subscribe_evictions(&self) -> tokio::sync::broadcast::Receiver<Slot> {
+        self.storage.subscribe_evictions()
+    }
+
+    /// Unpins the slot so its data can be evicted again.
+    pub async fn unpin_slot(&self, slot: Slot) {
+        self.storage.unpin_slot(slot).await
+    }
+
+    /// Returns the merkle root and the Vaa sequence of each retained slot, in
+    /// ascending order of the slots.
+    pub async fn root_index(&self) -> Vec<(Slot, [u8; 20], u64)> {
+        self.storage
+            .wormhole_merkle_states()
+            .await
+            .into_iter()
+            .filter_map(|wormhole_merkle_state| {
+                match serde_wormhole::from_slice::<Vaa<&serde_wormhole::RawMessage>>(
+                    &wormhole_merkle_state.vaa,
+                ) {
+                    Ok(vaa) => Some((
+                        wormhole_merkle_state.root.slot,
+                        wormhole_merkle_state.root.root,
+                        vaa.sequence,
+                    )),
+                    Err(err) => {
+                        tracing::error!("Failed to parse a stored Vaa: {:?}", err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Backfills the message states of archived accumulator messages whose
+    /// Vaa is not available. The states carry no proof and are only served
+    /// to queries allowing unverified data. Messages already stored with a
+    /// proof are skipped as verified data always takes precedence. The
+    /// messages are admitted as the verified ones, except for the publish
+    /// time window as there is no Vaa to tell the slot time.
+    pub async fn store_unverified_accumulator(
+        &self,
+        accumulator_messages: AccumulatorMessages,
+    ) -> Result<()> {
+        if self.is_read_only() {
+            if self.config.strict_read_only {
+                return Err(StoreError::ReadOnly.into());
+            }
+            return Ok(());
+        }
+
+        let received_at: UnixTimestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+        let enabled_message_types = self.enabled_message_types.read().await.clone();
+        let message_states = accumulator_messages
+            .raw_messages
+            .into_iter()
+            .enumerate()
+            .map(|(idx, raw_message)| {
+                let message: Message = from_slice::<BigEndian, _>(raw_message.as_ref())
+                    .map_err(|e| anyhow!("Failed to deserialize message: {:?}", e))?;
+                Ok((idx, message, raw_message))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|(_, message, _)| {
+                self.admit_message(
+                    message,
+                    accumulator_messages.slot,
+                    None,
+                    &enabled_message_types,
+                )
+            })
+            .map(|(idx, message, raw_message)| {
+                let clamped = self.needs_clamp(&message);
+                let mut message_state = MessageState::new(
+                    message,
+                    raw_message,
+                    ProofSet {
+                        wormhole_merkle_proof: WormholeMerkleMessageProof {
+                            vaa:        vec![],
+                            proof:      MerklePath::new(vec![]),
+                            leaf_index: idx.try_into()?,
+                        },
+                    },
+                    accumulator_messages.slot,
+                    received_at,
+                );
+                message_state.unverified = true;
+                message_state.clamped = clamped;
+                Ok(message_state)
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|message_state| {
+                self.storage
+                    .fetch_message_state(&message_state.key(), &message_state.time())
+                    .is_none()
+            })
+            .collect();
+
+        self.unverified_storage
+            .store_message_states(message_states)
+            .await
+    }
+
+    /// Returns the sequence of the Vaa that signed the merkle root of the
+    /// slot, if the slot is retained.
+    pub async fn slot_sequence(&self, slot: Slot) -> Option<u64> {
+        self.storage
+            .fetch_wormhole_merkle_state(slot)
+            .await
+            .ok()
+            .flatten()
+            .map(|wormhole_merkle_state| wormhole_merkle_state.sequence)
+    }
+
+    /// Lists every retained slot in ascending order with the number of price
+    /// feeds it updated.
+    pub async fn slot_summary(&self) -> Vec<SlotSummary> {
+        let mut price_feed_counts = self.storage.price_feed_counts_by_slot().await;
+        self.storage
+            .wormhole_merkle_state_slots()
+            .await
+            .into_iter()
+            .map(|slot| {
+                let (feed_count, received_at) = match price_feed_counts.remove(&slot) {
+                    Some((feed_count, received_at)) => (feed_count, Some(received_at)),
+                    None => (0, None),
+                };
+                SlotSummary {
+                    slot,
+                    feed_count,
+                    received_at,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns a snapshot of the guardian sets held by the store ordered by
+    /// their index.
+    pub async fn guardian_set_info(&self) -> Vec<GuardianSetInfo> {
+        self.guardian_set
+            .read()
+            .await
+            .iter()
+            .map(|(index, guardian_set)| GuardianSetInfo {
+                index:    *index,
+                num_keys: guardian_set.keys.len(),
+            })
+            .collect()
+    }
+
+    pub async fn get_price_feeds_with_update_data(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        self.get_price_feeds_with_options(price_ids, request_time, Default::default())
+            .await
+    }
+
+    /// Same as `get_price_feeds_with_update_data` with control over how the
+    /// update data is constructed.
+    pub async fn get_price_feeds_with_options(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+        options: PriceFeedsQueryOptions,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        if price_ids.len() > self.config.max_feeds_per_request {
+            return Err(StoreError::TooManyFeeds {
+                requested: price_ids.len(),
+                limit:     self.config.max_feeds_per_request,
+            }
+            .into());
+        }
+
+        self.query_price_feeds(price_ids, request_time, options)
+            .await
+    }
+
+    /// Same as `get_price_feeds_with_options` without a limit on the number
+    /// of price ids.
+    async fn query_price_feeds(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+        options: PriceFeedsQueryOptions,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        if let RequestTime::LatestConfirmed { behind } = request_time {
+            if self.storage.confirmed_slot(behind).await.is_none() {
+                return Err(StoreError::NotEnoughRetainedSlots {
+                    behind,
+                    retained: self.storage.num_wormhole_merkle_states().await,
+                }
+                .into());
+            }
+        }
+
+        // The message states, and hence the price feeds, are in the order of
+        // the requested price ids.
+        let feed_ids = price_ids
+            .iter()
+            .map(|price_id| price_id.to_bytes())
+            .collect();
+        let filter = MessageStateFilter::Only(MessageType::PriceFeedMessage);
+        let messages = if options.allow_unverified {
+            self.fetch_message_states_allowing_unverified(
+                feed_ids,
+                request_time.clone(),
+                filter,
+                options.partial,
+            )
+            .await?
+        } else if options.partial {
+            self.storage
+                .fetch_available_message_states(feed_ids, request_time.clone(), filter)
+                .await
+        } else {
+            self.storage
+                .fetch_message_states(feed_ids, request_time.clone(), filter)
+                .await?
+        };
+        self.check_guardian_sets_active(&messages).await?;
+
+        let price_feeds_with_update_data =
+            self.build_price_feeds_with_update_data(messages, &request_time, &options);
+        match options.deadline {
+            Some(deadline) => tokio::time::timeout(deadline, price_feeds_with_update_data)
+                .await
+                .map_err(|_| StoreError::Timeout { deadline })?,
+            None => price_feeds_with_update_data.await,
+        }
+    }
+
+    /// Builds the price feeds and the update data of the queried message
+    /// states. Everything CPU-bound runs on the proof pool, so the deadline of
+    /// the query applies to all of it.
+    async fn build_price_feeds_with_update_data(
+        &self,
+        messages: Vec<MessageState>,
+        request_time: &RequestTime,
+        options: &PriceFeedsQueryOptions,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        let mut price_feeds = join_all(
+            messages
+                .iter()
+                .map(|message_state| self.build_cached_price_feed_update(message_state)),
+        )
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+        if let (RequestTime::Latest, Some(staleness_threshold)) =
+            (request_time, self.config.latest_staleness_threshold)
+        {
+            let current_time: UnixTimestamp =
+                SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+
+            for price_feed in price_feeds.iter_mut() {
+                let age = current_time - price_feed.price_feed.publish_time;
+                if age > staleness_threshold.as_secs() as UnixTimestamp {
+                    if !options.allow_stale {
+                        return Err(StoreError::StalePriceFeed {
+                            price_id: PriceIdentifier::new(price_feed.price_feed.feed_id),
+                        }
+                        .into());
+                    }
+                    price_feed.is_stale = true;
+                }
+            }
+        }
+
+        if let RequestTime::FirstAfterWithin { time, tolerance } = *request_time {
+            if let Some(price_feed) = price_feeds.iter().find(|price_feed| {
+                price_feed.price_feed.publish_time - time > tolerance.as_secs() as UnixTimestamp
+            }) {
+                return Err(StoreError::NoDataWithinTolerance {
+                    price_id: PriceIdentifier::new(price_feed.price_feed.feed_id),
+                    time,
+                    tolerance,
+                }
+                .into());
+            }
+        }
+
+        // Unverified message states have no proof to put in the update data.
+        let messages: Vec<_> = messages
+            .into_iter()
+            .filter(|message_state| !message_state.unverified)
+            .collect();
+        let checked_messages = options.max_update_bytes.map(|_| messages.clone());
+        let update_data = self.build_update_data(messages, options).await?;
+
+        if let (Some(max_update_bytes), Some(checked_messages)) =
+            (options.max_update_bytes, checked_messages)
+        {
+            if update_data
+                .iter()
+                .any(|update_data| update_data.len() > max_update_bytes)
+            {
+                return Err(StoreError::UpdateTooLarge {
+                    price_ids: self
+                        .find_oversized_feeds(checked_messages, options, max_update_bytes)
+                        .await?,
+                    limit:     max_update_bytes,
+                }
+                .into());
+            }
+        }
+
+        Ok(PriceFeedsWithUpdateData {
+            price_feeds,
+            wormhole_merkle_update_data: update_data,
+        })
+    }
+
+    /// Builds the price feed update of the message state, reusing the update
+    /// cached for its feed if it is of the same slot and younger than the
+    /// cache TTL.
+    async fn build_cached_price_feed_update(
+        &self,
+        message_state: &MessageState,
+    ) -> Result<PriceFeedUpdate> {
+        let Some(ttl) = self.config.price_feed_update_cache_ttl else {
+            self.metrics.price_feed_update_builds.inc();
+            return self.build_pooled_price_feed_update(message_state).await;
+        };
+
+        let price_id = PriceIdentifier::new(message_state.message.feed_id());
+        if let Some((cached_at, price_feed_update)) =
+            self.price_feed_update_cache.read().await.get(&price_id)
+        {
+            if price_feed_update.slot == message_state.slot
+                && price_feed_update.unverified == message_state.unverified
+                && cached_at.elapsed() < ttl
+            {
+                return Ok(price_feed_update.clone());
+            }
+        }
+
+        self.metrics.price_feed_update_builds.inc();
+        let price_feed_update = self.build_pooled_price_feed_update(message_state).await?;
+        let mut price_feed_update_cache = self.price_feed_update_cache.write().await;
+        // Only the update of the newest slot of a feed is worth caching.
+        if price_feed_update_cache
+            .get(&price_id)
+            .map_or(true, |(_, cached)| cached.slot <= price_feed_update.slot)
+        {
+            price_feed_update_cache.insert(price_id, (Instant::now(), price_feed_update.clone()));
+        }
+        Ok(price_feed_update)
+    }
+
+    /// Builds the price feed update of the message state on the proof pool, as
+    /// constructing its own update data is CPU-bound.
+    async fn build_pooled_price_feed_update(
+        &self,
+        message_state: &MessageState,
+    ) -> Result<PriceFeedUpdate> {
+        let message_state = message_state.clone();
+        self.proof_pool
+            .run(move || build_price_feed_update(&message_state))
+            .await?
+    }
+
+    /// Same as the update data of `get_price_feeds_with_update_data`, with
+    /// each update data blob keyed by the slot it proves the feeds of. The
+    /// blobs are in ascending slot order, so they can be submitted in order.
+    pub async fn get_price_feeds_with_update_data_by_slot(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+    ) -> Result<Vec<(Slot, Vec<u8>)>> {
+        if price_ids.len() > self.config.max_feeds_per_request {
+            return Err(StoreError::TooManyFeeds {
+                requested: price_ids.len(),
+                limit:     self.config.max_feeds_per_request,
+            }
+            .into());
+        }
+
+        let mut messages_by_slot: BTreeMap<Slot, Vec<MessageState>> = BTreeMap::new();
+        for message_state in self
+            .storage
+            .fetch_message_states(
+                price_ids
+                    .iter()
+                    .map(|price_id| price_id.to_bytes())
+                    .collect(),
+                request_time,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await?
+        {
+            messages_by_slot
+                .entry(message_state.slot)
+                .or_default()
+                .push(message_state);
+        }
+
+        join_all(
+            messages_by_slot
+                .into_iter()
+                .map(|(slot, messages)| async move {
+                    // The messages of a slot are all proven by the Vaa of the slot,
+                    // hence in a single update data. More would each need a key.
+                    let mut update_data = self
+                        .build_update_data(messages, &PriceFeedsQueryOptions::default())
+                        .await?;
+                    match update_data.len() {
+                        1 => Ok((slot, update_data.remove(0))),
+                        len => Err(anyhow!(
+                            "Expected a single update data for slot {}, got {}",
+                            slot,
+                            len
+                        )),
+                    }
+                }),
+        )
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// Same as the update data of `get_price_feeds_with_update_data`, yielded
+    /// one Vaa at a time. The update data of a Vaa is only constructed when
+    /// the stream is polled for it, so large responses can be flushed
+    /// incrementally.
+    pub fn stream_price_feeds_update_data(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + '_ {
+        stream::once(self.fetch_message_states_by_vaa(price_ids, request_time))
+            .map_ok(|groups| {
+                stream::iter(groups)
+                    .map(|messages| construct_vaa_update_data(&messages.iter().collect::<Vec<_>>()))
+            })
+            .try_flatten()
+    }
+
+    /// Fetches the message states of the price feeds, grouped by their Vaa in
+    /// the order of `construct_update_data`.
+    async fn fetch_message_states_by_vaa(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+    ) -> Result<Vec<Vec<MessageState>>> {
+        if price_ids.len() > self.config.max_feeds_per_request {
+            return Err(StoreError::TooManyFeeds {
+                requested: price_ids.len(),
+                limit:     self.config.max_feeds_per_request,
+            }
+            .into());
+        }
+
+        let mut messages = self
+            .storage
+            .fetch_message_states(
+                price_ids
+                    .iter()
+                    .map(|price_id| price_id.to_bytes())
+                    .collect(),
+                request_time,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await?;
+        messages.sort_by(|a, b| {
+            a.proof_set
+                .wormhole_merkle_proof
+                .vaa
+                .cmp(&b.proof_set.wormhole_merkle_proof.vaa)
+        });
+
+        let mut groups: Vec<Vec<MessageState>> = Vec::new();
+        for message in messages {
+            match groups.last_mut() {
+                Some(group)
+                    if group[0].proof_set.wormhole_merkle_proof.vaa
+                        == message.proof_set.wormhole_merkle_proof.vaa =>
+                {
+                    group.push(message)
+                }
+                _ => groups.push(vec![message]),
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Fetches the message states of the feeds in their order, falling back
+    /// to the unverified message states for the feeds without a verified
+    /// one.
+    async fn fetch_message_states_allowing_unverified(
+        &self,
+        feed_ids: Vec<FeedId>,
+        request_time: RequestTime,
+        filter: MessageStateFilter,
+        partial: bool,
+    ) -> Result<Vec<MessageState>> {
+        let mut message_states = Vec::with_capacity(feed_ids.len());
+        for feed_id in feed_ids {
+            let mut message_state = self
+                .storage
+                .fetch_available_message_states(vec![feed_id], request_time.clone(), filter)
+                .await
+                .pop();
+            if message_state.is_none() {
+                message_state = self
+                    .unverified_storage
+                    .fetch_available_message_states(vec![feed_id], request_time.clone(), filter)
+                    .await
+                    .pop();
+            }
+
+            match message_state {
+                Some(message_state) => message_states.push(message_state),
+                None if partial => {}
+                None => return Err(anyhow!("Message not found")),
+            }
+        }
+        Ok(message_states)
+    }
+
+    /// Returns a single bundle of the update data of the feeds, each at its
+    /// own request time. Feeds resolving to different slots are proven by
+    /// the Vaas of their slots, all within the same bundle.
+    pub async fn get_bundled_update(
+        &self,
+        requests: Vec<(PriceIdentifier, RequestTime)>,
+    ) -> Result<Vec<u8>> {
+        if requests.len() > self.config.max_feeds_per_request {
+            return Err(StoreError::TooManyFeeds {
+                requested: requests.len(),
+                limit:     self.config.max_feeds_per_request,
+            }
+            .into());
+        }
+
+        let mut message_states = Vec::with_capacity(requests.len());
+        for (price_id, request_time) in requests {
+            message_states.extend(
+                self.storage
+                    .fetch_message_states(
+                        vec![price_id.to_bytes()],
+                        request_time,
+                        MessageStateFilter::Only(MessageType::PriceFeedMessage),
+                    )
+                    .await?,
+            );
+        }
+
+        construct_bundled_update_data(message_states.iter().collect())
+    }
+
+    /// Returns the merkle proof of the feed's update resolving the request as
+    /// sibling hashes with their directions, along with the root signed in
+    /// the Vaa of its slot.
+    pub async fn get_structured_proof(
+        &self,
+        price_id: PriceIdentifier,
+        request_time: RequestTime,
+    ) -> Result<StructuredProof> {
+        let message_state = self
+            .storage
+            .fetch_message_states(
+                vec![price_id.to_bytes()],
+                request_time,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await?
+            .remove(0);
+        let wormhole_merkle_state = self
+            .storage
+            .fetch_wormhole_merkle_state(message_state.slot)
+            .await?
+            .ok_or(StoreError::SlotNotFound {
+                slot: message_state.slot,
+            })?;
+
+        build_structured_proof(&message_state, wormhole_merkle_state.root.root)
+    }
+
+    /// Returns the structured proof of the feed against the given merkle
+    /// root, such as one a client read on-chain earlier, built from the
+    /// retained slot signing that root.
+    pub async fn get_proof_against_root(
+        &self,
+        price_id: PriceIdentifier,
+        root: [u8; 20],
+    ) -> Result<StructuredProof> {
+        let slot = self
+            .storage
+            .find_slot_by_root(root)
+            .await
+            .ok_or(StoreError::RootNotRetained { root })?;
+        let message_state = self
+            .storage
+            .fetch_message_state_at_slot(
+                &MessageStateKey {
+                    feed_id: price_id.to_bytes(),
+                    type_:   MessageType::PriceFeedMessage,
+                },
+                slot,
+            )
+            .ok_or(StoreError::FeedNotInSlot { price_id, slot })?;
+        build_structured_proof(&message_state, root)
+    }
+
+    /// Returns every retained update of the feed, oldest first, each with its
+    /// own update data. The history spans the whole storage cache.
+    pub async fn get_feed_history(
+        &self,
+        price_id: PriceIdentifier,
+    ) -> Result<Vec<PriceFeedUpdate>> {
+        self.storage
+            .fetch_all_message_states(&MessageStateKey {
+                feed_id: price_id.to_bytes(),
+                type_:   MessageType::PriceFeedMessage,
+            })
+            .iter()
+            .map(build_price_feed_update)
+            .collect()
+    }
+
+    /// Returns whether the slot is still retained, so update data of the slot
+    /// obtained earlier would still be served. Clients can refresh their
+    /// update data before the slot is evicted.
+    pub async fn is_slot_retained(&self, slot: Slot) -> bool {
+        self.storage.is_slot_retained(slot).await
+    }
+
+    /// Returns the whole merkle tree of a retained slot, with all its leaves
+    /// and internal nodes, for auditing the proofs independently. The tree
+    /// built on ingestion is returned if still cached, otherwise it is
+    /// rebuilt from the accumulator messages of the slot.
+    pub async fn get_merkle_tree(&self, slot: Slot) -> Option<MerkleTree<Keccak160>> {
+        if let Some(merkle_tree) = self.storage.fetch_merkle_tree(slot).await {
+            return Some((*merkle_tree).clone());
+        }
+
+        let accumulator_messages = self.storage.fetch_accumulator_messages(slot).await.ok()??;
+        self.metrics.merkle_tree_builds.inc();
+        MerkleTree::<Keccak160>::new(
+            &accumulator_messages
+                .raw_messages
+                .iter()
+                .map(|m| m.as_ref())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Returns the sorted slots in which the feed has a retained message, for
+    /// finding the gaps in its updates.
+    pub fn feed_slots(&self, price_id: PriceIdentifier) -> Vec<Slot> {
+        self.storage.feed_slots(price_id.to_bytes())
+    }
+
+    /// Returns the price feeds with the accumulator proof and the wormhole
+    /// root as distinct layers, for verifiers checking the message against
+    /// the root and the root against the Vaa in two independent steps.
+    pub async fn get_price_feeds_layered(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        request_time: RequestTime,
+    ) -> Result<Vec<LayeredPriceFeed>> {
+        let message_states = self
+            .storage
+            .fetch_message_states(
+                price_ids
+                    .iter()
+                    .map(|price_id| price_id.to_bytes())
+                    .collect(),
+                request_time,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await?;
+
+        let mut layered_price_feeds = Vec::with_capacity(message_states.len());
+        for message_state in message_states {
+            let wormhole_merkle_state = self
+                .storage
+                .fetch_wormhole_merkle_state(message_state.slot)
+                .await?
+                .ok_or(StoreError::SlotNotFound {
+                    slot: message_state.slot,
+                })?;
+            layered_price_feeds.push(LayeredPriceFeed {
+                message:           message_state.raw_message,
+                accumulator_proof: message_state.proof_set.wormhole_merkle_proof.proof,
+                wormhole_root:     wormhole_merkle_state.root.root,
+                vaa:               wormhole_merkle_state.vaa,
+            });
+        }
+        Ok(layered_price_feeds)
+    }
+
+    /// Returns the Vaa of the slot split into its header, carrying the
+    /// guardian set index and signatures, and its body, for verifiers taking
+    /// them as separate parameters. `join_vaa` recombines them.
+    pub async fn get_split_vaa(&self, slot: Slot) -> Result<(VaaHeader, Vec<u8>)> {
+        let wormhole_merkle_state = self
+            .storage
+            .fetch_wormhole_merkle_state(slot)
+            .await?
+            .ok_or(StoreError::SlotNotFound { slot })?;
+        split_vaa(&wormhole_merkle_state.vaa)
+    }
+
+    /// Returns the Vaa bytes, as verified by the store, of the slot that
+    /// resolves the request for the feed.
+    pub async fn get_vaa_for_request(
+        &self,
+        price_id: PriceIdentifier,
+        request_time: RequestTime,
+    ) -> Result<Vec<u8>> {
+        let message_state = self
+            .storage
+            .fetch_message_states(
+                vec![price_id.to_bytes()],
+                request_time,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await?
+            .remove(0);
+
+        let wormhole_merkle_state = self
+            .storage
+            .fetch_wormhole_merkle_state(message_state.slot)
+            .await?
+            .ok_or(StoreError::SlotNotFound {
+                slot: message_state.slot,
+            })?;
+        Ok(wormhole_merkle_state.vaa)
+    }
+
+    /// Returns the update of the feed in exactly the given slot. It fails
+    /// with `SlotNotFound` if the slot is not retained and with
+    /// `FeedNotInSlot` if the feed is not updated in the slot.
+    pub async fn get_price_at_slot(
+        &self,
+        price_id: PriceIdentifier,
+        slot: Slot,
+    ) -> Result<PriceFeedUpdate> {
+        if self
+            .storage
+            .fetch_wormhole_merkle_state(slot)
+            .await?
+            .is_none()
+        {
+            return Err(StoreError::SlotNotFound { slot }.into());
+        }
+
+        let message_state = self
+            .storage
+            .fetch_message_state_at_slot(
+                &MessageStateKey {
+                    feed_id: price_id.to_bytes(),
+                    type_:   MessageType::PriceFeedMessage,
+                },
+                slot,
+            )
+            .ok_or(StoreError::FeedNotInSlot { price_id, slot })?;
+
+        build_price_feed_update(&message_state)
+    }
+
+    /// Returns the updates of the feeds as of a single point in time, so no
+    /// feed is ahead of the others. The point is the earliest publish time
+    /// among the updates of the feeds matching `as_of`, and each feed is
+    /// served with its last update published at or before it.
+    pub async fn get_consistent_snapshot(
+        &self,
+        price_ids: Vec<PriceIdentifier>,
+        as_of: RequestTime,
+        options: PriceFeedsQueryOptions,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        let feed_ids = price_ids
+            .iter()
+            .map(|price_id| price_id.to_bytes())
+            .collect();
+        let resolved_time = self
+            .storage
+            .fetch_available_message_states(
+                feed_ids,
+                as_of,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .iter()
+            .map(|message_state| message_state.message.publish_time())
+            .min()
+            .ok_or(anyhow!("No update of the requested feeds"))?;
+
+        self.get_price_feeds_with_options(
+            price_ids,
+            RequestTime::AtOrBefore(resolved_time),
+            options,
+        )
+        .await
+    }
+
+    /// Builds the update data of the message states as requested by the
+    /// options. The update data is constructed on the proof pool as it is
+    /// CPU-bound.
+    async fn build_update_data(
+        &self,
+        messages: Vec<MessageState>,
+        options: &PriceFeedsQueryOptions,
+    ) -> Result<Vec<Vec<u8>>> {
+        match options.proof_granularity {
+            ProofGranularity::LeavesOnly => {
+                construct_leaves_only_update_data(self, messages.iter().collect()).await
+            }
+            ProofGranularity::RootOnly => {
+                self.proof_pool
+                    .run(move || construct_root_only_update_data(messages.iter().collect()))
+                    .await?
+            }
+            ProofGranularity::Full if options.multiproof => {
+                construct_multiproof_update_data(self, messages.iter().collect()).await
+            }
+            ProofGranularity::Full => {
+                construct_update_data_pooled(&self.proof_pool, messages).await
+            }
+        }
+    }
+
+    /// Returns the price ids of the message states whose update data, built
+    /// separately for each Vaa, is larger than `max_update_bytes`.
+    async fn find_oversized_feeds(
+        &self,
+        messages: Vec<MessageState>,
+        options: &PriceFeedsQueryOptions,
+        max_update_bytes: usize,
+    ) -> Result<Vec<PriceIdentifier>> {
+        let mut messages_by_vaa: BTreeMap<Vec<u8>, Vec<MessageState>> = BTreeMap::new();
+        for message_state in messages {
+            messages_by_vaa
+                .entry(message_state.proof_set.wormhole_merkle_proof.vaa.clone())
+                .or_default()
+                .push(message_state);
+        }
+
+        let mut price_ids = Vec::new();
+        for messages in messages_by_vaa.into_values() {
+            let vaa_price_ids = messages
+                .iter()
+                .map(|message_state| PriceIdentifier::new(message_state.message.feed_id()))
+                .collect::<Vec<_>>();
+            if self
+                .build_update_data(messages, options)
+                .await?
+                .iter()
+                .any(|update_data| update_data.len() > max_update_bytes)
+            {
+                price_ids.extend(vaa_price_ids);
+            }
+        }
+        Ok(price_ids)
+    }
+
+    /// Returns the updates of all the stored price feeds whose id starts with
+    /// the given prefix, ordered by id. The feeds without an update at the
+    /// request time are omitted. The number of matched feeds is set by the
+    /// prefix rather than listed by the caller, so it is not limited by
+    /// `max_feeds_per_request`.
+    pub async fn get_feeds_by_prefix(
+        &self,
+        prefix: &[u8],
+        request_time: RequestTime,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        let price_ids = self
+            .storage
+            .price_feed_ids_with_prefix(prefix)
+            .await
+            .into_iter()
+            .map(PriceIdentifier::new)
+            .collect();
+
+        self.query_price_feeds(
+            price_ids,
+            request_time,
+            PriceFeedsQueryOptions {
+                partial: true,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Returns the latest updates of the feeds whose latest retained update
+    /// is in a slot after `since_slot`, so mirrors can sync incrementally.
+    /// All the stored feeds are considered unless `price_ids` is given.
+    pub async fn get_changes_since(
+        &self,
+        since_slot: Slot,
+        price_ids: Option<Vec<PriceIdentifier>>,
+    ) -> Result<PriceFeedsWithUpdateData> {
+        let price_ids = match price_ids {
+            Some(price_ids) => price_ids,
+            None => {
+                let mut price_ids: Vec<_> = self.get_price_feed_ids().await.into_iter().collect();
+                price_ids.sort_by_key(|price_id| price_id.to_bytes());
+                price_ids
+            }
+        };
+
+        let changed_price_ids = self
+            .storage
+            .fetch_available_message_states(
+                price_ids
+                    .iter()
+                    .map(|price_id| price_id.to_bytes())
+                    .collect(),
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .into_iter()
+            .filter(|message_state| message_state.slot > since_slot)
+            .map(|message_state| PriceIdentifier::new(message_state.message.feed_id()))
+            .collect();
+
+        self.get_price_feeds_with_update_data(changed_price_ids, RequestTime::Latest)
+            .await
+    }
+
+    /// Returns the metrics collected by the store.
+    pub fn metrics(&self) -> &StoreMetrics {
+        &self.metrics
+    }
+
+    pub async fn get_price_feed_ids(&self) -> HashSet<PriceIdentifier> {
+        self.storage
+            .message_state_keys()
+            .await
+            .iter()
+            .map(|key| PriceIdentifier::new(key.feed_id))
+            .collect()
+    }
+
+    /// Returns the feeds that did not receive an update for longer than the
+    /// threshold with the time since their last update, the stalest first.
+    pub async fn stale_feeds(
+        &self,
+        threshold: Duration,
+    ) -> Result<Vec<(PriceIdentifier, Duration)>> {
+        let current_time: UnixTimestamp =
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as _;
+
+        let mut stale_feeds = self
+            .storage
+            .price_feed_received_times()
+            .await
+            .into_iter()
+            .map(|(feed_id, received_at)| {
+                (
+                    PriceIdentifier::new(feed_id),
+                    Duration::from_secs(current_time.saturating_sub(received_at).max(0) as u64),
+                )
+            })
+            .filter(|(_, staleness)| *staleness > threshold)
+            .collect::<Vec<_>>();
+        stale_feeds.sort_by(|(_, a), (_, b)| b.cmp(a));
+        Ok(stale_feeds)
+    }
+
+    /// Serializes all the retained slots that have both their accumulator
+    /// messages and their Vaa, so another store can be bootstrapped from
+    /// them with `import_snapshot`.
+    pub async fn export_snapshot(&self) -> Result<Vec<u8>> {
+        let mut slots = Vec::new();
+        for slot in self.storage.wormhole_merkle_state_slots().await {
+            let accumulator_messages = self.storage.fetch_accumulator_messages(slot).await?;
+            let wormhole_merkle_state = self.storage.fetch_wormhole_merkle_state(slot).await?;
+            if let (Some(accumulator_messages), Some(wormhole_merkle_state)) =
+                (accumulator_messages, wormhole_merkle_state)
+            {
+                slots.push(SnapshotSlot {
+                    accumulator_messages,
+                    vaa: wormhole_merkle_state.vaa,
+                });
+            }
+        }
+
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            slots,
+        }
+        .try_to_vec()?)
+    }
+
+    /// Loads the slots of a snapshot exported by `export_snapshot`. The
+    /// slots go through the regular ingestion, so their Vaas are verified
+    /// against the current guardian sets and the message states are rebuilt
+    /// (with the import time as their receive time). Fails if any Vaa is
+    /// rejected.
+    pub async fn import_snapshot(&self, bytes: &[u8]) -> Result<()> {
+        let snapshot = Snapshot::try_from_slice(bytes)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(anyhow!(
+                "Unsupported snapshot version: {}",
+                snapshot.version
+            ));
+        }
+
+        for snapshot_slot in snapshot.slots {
+            let slot = snapshot_slot.accumulator_messages.slot;
+            self.store_update(Update::AccumulatorMessages(
+                snapshot_slot.accumulator_messages,
+            ))
+            .await?;
+            self.store_update(Update::Vaa(snapshot_slot.vaa)).await?;
+
+            if self
+                .storage
+                .fetch_wormhole_merkle_state(slot)
+                .await?
+                .is_none()
+            {
+                return Err(anyhow!("Invalid Vaa in snapshot for slot {}", slot));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Recomputes the checksums of the cached message states. Only states
+    /// stored while `cache_checksums` is enabled are verified.
+    pub async fn verify_cache_integrity(&self) -> IntegrityReport {
+        self.storage.verify_integrity().await
+    }
+
+    /// Replaces the feed metadata with the metadata loaded from the provider.
+    pub async fn load_feed_metadata(&self, provider: &dyn FeedMetadataProvider) -> Result<()> {
+        let feed_metadata = provider.load()?;
+        *self.feed_metadata.write().await = feed_metadata;
+        Ok(())
+    }
+
+    pub async fn feed_metadata(&self, price_id: PriceIdentifier) -> Option<FeedMetadata> {
+        self.feed_metadata.read().await.get(&price_id).cloned()
+    }
+
+    /// Returns the ids of the price feeds in the store along with their
+    /// metadata, if any was loaded for them.
+    pub async fn list_feeds_with_metadata(&self) -> HashMap<PriceIdentifier, Option<FeedMetadata>> {
+        let price_ids = self.get_price_feed_ids().await;
+        let feed_metadata = self.feed_metadata.read().await;
+        price_ids
+            .into_iter()
+            .map(|price_id| (price_id, feed_metadata.get(&price_id).cloned()))
+            .collect()
+    }
+
+    /// Returns a summary of the state of the store.
+    pub async fn status(&self) -> StoreStatus {
+        StoreStatus {
+            is_ready:                       self.is_ready().await,
+            latest_completed_slot:          *self.latest_completed_slot.read().await,
+            num_feeds:                      self.get_price_feed_ids().await.len(),
+            last_completed_update_age_secs: self
+                .last_completed_update_at
+                .read()
+                .await
+                .map(|last_completed_update_at| last_completed_update_at.elapsed().as_secs_f64()),
+            num_observed_vaas:              self.metrics.observed_vaas.get(),
+            num_completed_slots:            self.metrics.completed_slots.get(),
+        }
+    }
+
+    /// Spawns a watchdog that checks for stalled ingestion every
+    /// `check_interval`. Aborting the returned handle cancels it.
+    pub fn spawn_stall_watchdog(
+        self: Arc<Self>,
+        check_interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(check_interval);
+            loop {
+                interval.tick().await;
+                self.check_stall().await;
+            }
+        })
+    }
+
+    /// Updates the stall gauge with the time since the last completed slot
+    /// and logs stalls at a level escalating with their duration.
+    pub async fn check_stall(&self) {
+        let since_last_update = match self.last_completed_update_at.read().await.as_ref() {
+            Some(last_completed_update_at) => last_completed_update_at.elapsed(),
+            None => return,
+        };
+
+        let threshold = self.config.stall_threshold;
+        if since_last_update <= threshold {
+            self.metrics.stall_seconds.set(0);
+            return;
+        }
+
+        self.metrics
+            .stall_seconds
+            .set(since_last_update.as_secs() as i64);
+        if since_last_update > threshold * STALL_ERROR_MULTIPLIER {
+            tracing::error!("No slot completed for {:?}", since_last_update);
+        } else {
+            tracing::warn!("No slot completed for {:?}", since_last_update);
+        }
+    }
+
+    /// Returns whether the store failed to notify a completed update.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Turns the read-only mode on or off. While it is on, updates are not
+    /// ingested and the queries are served from the frozen cache.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the store had a recent update and holds enough feeds
+    /// and slots to be considered warmed up.
+    pub async fn is_ready(&self) -> bool {
+        let has_recent_update = match self.last_completed_update_at.read().await.as_ref() {
+            Some(last_completed_update_at) => {
+                last_completed_update_at.elapsed() < READINESS_STALENESS_THRESHOLD
+            }
+            None => false,
+        };
+
+        has_recent_update
+            && self.get_price_feed_ids().await.len() >= self.config.warmup_min_feeds
+            && self.storage.num_wormhole_merkle_states().await >= self.config.warmup_min_slots
+    }
+
+    /// Returns how long after its construction the store first became ready,
+    /// or `None` if it has not been ready yet. It tracks the cold start time
+    /// across deployments.
+    pub fn time_since_start_ready(&self) -> Option<Duration> {
+        self.first_ready_at
+            .get()
+            .map(|first_ready_at| first_ready_at.duration_since(self.started_at))
+    }
+
+    /// Checks the whole proof pipeline on the latest completed slot: it
+    /// constructs the update data of a message state stored for the slot and
+    /// verifies its merkle proof against the root signed in the Vaa. A slot
+    /// whose messages are all outside the shard or of disabled types has
+    /// nothing to check, but it fails if none of the other messages of the
+    /// slot is stored.
+    pub async fn deep_health_check(&self) -> Result<()> {
+        let slot = self
+            .latest_completed_slot
+            .read()
+            .await
+            .ok_or(anyhow!("No completed slot"))?;
+
+        let accumulator_messages = self
+            .storage
+            .fetch_accumulator_messages(slot)
+            .await?
+            .ok_or(anyhow!("Accumulator messages of slot {} are missing", slot))?;
+        // Messages filtered out by the shard or the enabled message types
+        // have no state, so the first other message is checked.
+        let enabled_message_types = self.enabled_message_types.read().await.clone();
+        let messages = accumulator_messages
+            .raw_messages
+            .iter()
+            .filter_map(|raw_message| from_slice::<BigEndian, Message>(raw_message.as_ref()).ok())
+            .filter(|message| self.is_message_kept(message, &enabled_message_types))
+            .collect::<Vec<_>>();
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let message_state = messages
+            .iter()
+            .find_map(|message| {
+                self.storage.fetch_message_state(
+                    &MessageStateKey {
+                        feed_id: message.feed_id(),
+                        type_:   message.into(),
+                    },
+                    &MessageStateTime {
+                        publish_time: message.publish_time(),
+                        slot,
+                    },
+                )
+            })
+            .ok_or(anyhow!("No message state of slot {} is stored", slot))?;
+
+        let update_data = self
+            .proof_pool
+            .run(move || construct_update_data(vec![&message_state]))
+            .await??;
+        for update_data in update_data {
+            let update_data = AccumulatorUpdateData::try_from_slice(update_data.as_ref())
+                .map_err(|e| anyhow!("Failed to parse update data: {:?}", e))?;
+            match update_data.proof {
+                Proof::WormholeMerkle { vaa, updates } => {
+                    let vaa: Vec<u8> = vaa.into();
+                    let vaa = serde_wormhole::from_slice::<Vaa<&serde_wormhole::RawMessage>>(&vaa)?;
+                    let root = match WormholeMessage::try_from_bytes(vaa.payload)?.payload {
+                        WormholePayload::Merkle(merkle_root) => {
+                            MerkleRoot::<Keccak160>::new(merkle_root.root)
+                        }
+                    };
+
+                    for update in updates {
+                        let message: Vec<u8> = update.message.into();
+                        if !root.check(update.proof, &message) {
+                            return Err(anyhow!("Invalid merkle proof in slot {}", slot));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends an update signal to the api and observes how long it took.
+async fn send_update_signal(
+    update_tx: &Sender<()>,
+    degraded: &AtomicBool,
+    update_notification_latency: &Histogram,
+) {
+    let signaled_at = Instant::now();
+    // A dropped receiver must not stop the ingestion, so the store keeps
+    // going in a degraded state instead.
+    if let Err(err) = update_tx.send(()).await {
+        tracing::error!("Failed to notify the completed update: {:?}", err);
+        degraded.store(true, Ordering::Relaxed);
+    }
+    update_notification_latency.observe(signaled_at.elapsed().as_secs_f64());
+}
+
+/// Sends a single update signal for all the slots completed within `window`
+/// of the first pending one, so a burst of completed slots is signaled once
+/// and a steady stream of them is still signaled every `window`. It runs
+/// until the store, which holds the sender of `signals`, is dropped.
+async fn debounce_update_signals(
+    mut signals: Receiver<()>,
+    window: Duration,
+    update_tx: Sender<()>,
+    degraded: Arc<AtomicBool>,
+    update_notification_latency: Histogram,
+) {
+    while signals.recv().await.is_some() {
+        let flush_at = tokio::time::Instant::now() + window;
+        while let Ok(Some(())) = tokio::time::timeout_at(flush_at, signals.recv()).await {}
+        send_update_signal(&update_tx, &degraded, &update_notification_latency).await;
+    }
+}
+
+/// Builds the price feed update of a single price feed message state,
+/// including its own update data.
+fn build_price_feed_update(message_state: &MessageState) -> Result<PriceFeedUpdate> {
+    match message_state.message {
+        Message::PriceFeedMessage(mut price_feed) => Ok(PriceFeedUpdate {
+            price_feed:                  {
+                if message_state.clamped {
+                    price_feed.prev_publish_time = price_feed.publish_time;
+                }
+                price_feed
+            },
+            received_at:                 message_state.received_at,
+            slot:                        message_state.slot,
+            wormhole_merkle_update_data: if message_state.unverified {
+                vec![]
+            } else {
+                construct_update_data(vec![message_state])?
+                    .into_iter()
+                    .next()
+                    .ok_or(anyhow!("Missing update data for message"))?
+            },
+            is_stale:                    false,
+            merkle_leaf_index:           message_state.proof_set.wormhole_merkle_proof.leaf_index,
+            suspect:                     message_state.suspect,
+            links_previous:              true,
+            unverified:                  message_state.unverified,
+            source:                      message_state.source.clone(),
+        }),
+        _ => Err(anyhow!("Invalid message state type")),
+    }
+}
+
+/// Builds the structured proof of the message state against the root its
+/// merkle path leads to.
+fn build_structured_proof(message_state: &MessageState, root: [u8; 20]) -> Result<StructuredProof> {
+    let leaf = MerkleTree::<Keccak160>::hash_leaf(&message_state.raw_message);
+
+    // Parent nodes hash their children ordered by value, not by position, so
+    // the direction at each level is the side of the sibling in that order.
+    let mut node = leaf;
+    let siblings = message_state
+        .proof_set
+        .wormhole_merkle_proof
+        .proof
+        .to_bytes()
+        .chunks_exact(20)
+        .map(|sibling| {
+            let sibling: [u8; 20] = sibling.try_into()?;
+            let direction = if sibling < node {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+            node = MerkleTree::<Keccak160>::hash_node(&node, &sibling);
+            Ok((sibling, direction))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(StructuredProof {
+        leaf,
+        siblings,
+        root,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::store::{
+            proof::wormhole_merkle::{
+                construct_message_states_proofs,
+                BundledUpdateData,
+                WormholeMerkleMultiProofUpdateData,
+            },
+            watermark::FileSequenceWatermarkStore,
+        },
         mock_instant::MockClock,
         pythnet_sdk::{
             accumulators::{
@@ -320,349 +3385,5162 @@ mod test {
                     MerkleRoot,
                     MerkleTree,
                 },
-                Accumulator,
+                Accumulator,
+            },
+            hashers::{
+                keccak256_160::Keccak160,
+                Hasher,
+            },
+            messages::{
+                Message,
+                PriceFeedMessage,
+                TwapMessage,
+            },
+            wire::v1::{
+                AccumulatorUpdateData,
+                Proof,
+                WormholeMerkleRoot,
+            },
+        },
+        rand::seq::SliceRandom,
+        serde_wormhole::RawMessage,
+        std::{
+            collections::HashMap,
+            path::Path,
+            sync::Mutex,
+        },
+        tracing_subscriber::{
+            layer::{
+                Context,
+                SubscriberExt,
+            },
+            Layer,
+        },
+    };
+
+    /// Generate list of updates for the given list of messages at a given slot with given sequence
+    ///
+    /// Sequence in Vaas is used to filter duplicate messages (as by wormhole design there is only
+    /// one message per sequence)
+    pub fn generate_update(messages: Vec<Message>, slot: Slot, sequence: u64) -> Vec<Update> {
+        generate_update_with_vaa_timestamp(messages, slot, sequence, 0)
+    }
+
+    /// Same as `generate_update` with the given timestamp set on the Vaa.
+    pub fn generate_update_with_vaa_timestamp(
+        messages: Vec<Message>,
+        slot: Slot,
+        sequence: u64,
+        vaa_timestamp: u32,
+    ) -> Vec<Update> {
+        let mut updates = Vec::new();
+
+        // Accumulator messages
+        let accumulator_messages = AccumulatorMessages {
+            slot,
+            raw_messages: messages
+                .iter()
+                .map(|message| pythnet_sdk::wire::to_vec::<_, byteorder::BE>(message).unwrap())
+                .collect(),
+            magic: [0; 4],
+            ring_size: 100,
+        };
+        updates.push(Update::AccumulatorMessages(accumulator_messages.clone()));
+
+        // Wormhole merkle update
+        let merkle_tree = MerkleTree::<Keccak160>::from_set(
+            accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
+        )
+        .unwrap();
+
+        let wormhole_message = WormholeMessage::new(WormholePayload::Merkle(WormholeMerkleRoot {
+            slot,
+            ring_size: 100,
+            root: merkle_tree.root.as_bytes().try_into().unwrap(),
+        }));
+
+        let wormhole_message =
+            pythnet_sdk::wire::to_vec::<_, byteorder::BE>(&wormhole_message).unwrap();
+
+        let vaa = Vaa {
+            nonce: 0,
+            version: 0,
+            sequence,
+            timestamp: vaa_timestamp,
+            signatures: vec![],    // We are bypassing signature check now
+            guardian_set_index: 0, // We are bypassing signature check now
+            emitter_chain: Chain::Pythnet,
+            emitter_address: Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
+            consistency_level: 0,
+            payload: serde_wormhole::RawMessage::new(wormhole_message.as_ref()),
+        };
+
+        updates.push(Update::Vaa(serde_wormhole::to_vec(&vaa).unwrap()));
+
+        updates
+    }
+
+    /// Walks a structured proof up to its root as an external verifier does:
+    /// each parent is the Keccak160 hash of the node prefix followed by the
+    /// two children concatenated in the order given by the directions.
+    pub fn concatenate_structured_proof(structured_proof: &StructuredProof) -> [u8; 20] {
+        const NODE_PREFIX: &[u8] = &[1];
+        structured_proof.siblings.iter().fold(
+            structured_proof.leaf,
+            |node, (sibling, direction)| match direction {
+                Direction::Left => {
+                    Keccak160::hashv(&[NODE_PREFIX, sibling.as_slice(), node.as_slice()])
+                }
+                Direction::Right => {
+                    Keccak160::hashv(&[NODE_PREFIX, node.as_slice(), sibling.as_slice()])
+                }
+            },
+        )
+    }
+
+    /// Create a dummy price feed base on the given seed for all the fields except
+    /// `publish_time` and `prev_publish_time`. Those are set to the given value.
+    pub fn create_dummy_price_feed_message(
+        seed: u8,
+        publish_time: i64,
+        prev_publish_time: i64,
+    ) -> PriceFeedMessage {
+        PriceFeedMessage {
+            feed_id: [seed; 32],
+            price: seed as _,
+            conf: seed as _,
+            exponent: 0,
+            ema_conf: seed as _,
+            ema_price: seed as _,
+            publish_time,
+            prev_publish_time,
+        }
+    }
+
+    pub async fn setup_store(cache_size: u64) -> (Arc<Store>, Receiver<()>) {
+        setup_store_with_config(cache_size, StoreConfig::default()).await
+    }
+
+    pub async fn setup_store_with_config(
+        cache_size: u64,
+        config: StoreConfig,
+    ) -> (Arc<Store>, Receiver<()>) {
+        let (update_tx, update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new_with_config(update_tx, cache_size, config);
+
+        // Add an initial guardian set with public key 0
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        (store, update_rx)
+    }
+
+    pub async fn store_multiple_concurrent_valid_updates(store: Arc<Store>, updates: Vec<Update>) {
+        let res = join_all(updates.into_iter().map(|u| store.store_update(u))).await;
+        // Check that all store_update calls succeeded
+        assert!(res.into_iter().all(|r| r.is_ok()));
+    }
+
+    #[tokio::test]
+    pub async fn test_store_works() {
+        let (store, mut update_rx) = setup_store(10).await;
+
+        let price_feed_message = create_dummy_price_feed_message(100, 10, 9);
+
+        // Populate the store
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(vec![Message::PriceFeedMessage(price_feed_message)], 10, 20),
+        )
+        .await;
+
+        // Check that the update_rx channel has received a message
+        assert_eq!(update_rx.recv().await, Some(()));
+
+        // Check the price ids are stored correctly
+        assert_eq!(
+            store.get_price_feed_ids().await,
+            vec![PriceIdentifier::new([100; 32])].into_iter().collect()
+        );
+
+        // Check get_price_feeds_with_update_data retrieves the correct
+        // price feed with correct update data.
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            price_feeds_with_update_data.price_feeds,
+            vec![PriceFeedUpdate {
+                price_feed:                  price_feed_message,
+                slot:                        10,
+                received_at:                 price_feeds_with_update_data.price_feeds[0]
+                    .received_at, // Ignore checking this field.
+                wormhole_merkle_update_data: price_feeds_with_update_data.price_feeds[0]
+                    .wormhole_merkle_update_data
+                    .clone(), // Ignore checking this field.
+                is_stale:                    false,
+                merkle_leaf_index:           0,
+                suspect:                     false,
+                links_previous:              true,
+                unverified:                  false,
+                source:                      None,
+            }]
+        );
+
+        // Check the update data is correct.
+        assert_eq!(
+            price_feeds_with_update_data
+                .wormhole_merkle_update_data
+                .len(),
+            1
+        );
+        let update_data = price_feeds_with_update_data
+            .wormhole_merkle_update_data
+            .get(0)
+            .unwrap();
+        let update_data = AccumulatorUpdateData::try_from_slice(update_data.as_ref()).unwrap();
+        match update_data.proof {
+            Proof::WormholeMerkle { vaa, updates } => {
+                // Check the vaa and get the root
+                let vaa: Vec<u8> = vaa.into();
+                let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+                assert_eq!(
+                    vaa,
+                    Vaa {
+                        nonce:              0,
+                        version:            0,
+                        sequence:           20,
+                        timestamp:          0,
+                        signatures:         vec![],
+                        guardian_set_index: 0,
+                        emitter_chain:      Chain::Pythnet,
+                        emitter_address:    Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
+                        consistency_level:  0,
+                        payload:            vaa.payload, // Ignore checking this field.
+                    }
+                );
+                let merkle_root = WormholeMessage::try_from_bytes(vaa.payload.as_ref()).unwrap();
+                let WormholePayload::Merkle(merkle_root) = merkle_root.payload;
+                assert_eq!(
+                    merkle_root,
+                    WormholeMerkleRoot {
+                        slot:      10,
+                        ring_size: 100,
+                        root:      merkle_root.root, // Ignore checking this field.
+                    }
+                );
+
+                // Check the updates
+                assert_eq!(updates.len(), 1);
+                let update = updates.get(0).unwrap();
+                let message: Vec<u8> = update.message.clone().into();
+                // Check the serialized message is the price feed message generated above.
+                assert_eq!(
+                    pythnet_sdk::wire::from_slice::<byteorder::BE, Message>(message.as_ref())
+                        .unwrap(),
+                    Message::PriceFeedMessage(price_feed_message)
+                );
+
+                // Check the proof is correct with the Vaa root
+                let merkle_root = MerkleRoot::<Keccak160>::new(merkle_root.root);
+                assert!(merkle_root.check(update.proof.clone(), message.as_ref()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_metadata_times_and_readiness_work() {
+        // The receiver channel should stay open for the store to work
+        // properly. That is why we don't use _ here as it drops the channel
+        // immediately.
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let price_feed_message = create_dummy_price_feed_message(100, 10, 9);
+
+        // Advance the clock
+        MockClock::advance_system_time(Duration::from_secs(1));
+        MockClock::advance(Duration::from_secs(1));
+
+        // Get the current unix timestamp. It is mocked using
+        // mock-instance module. So it should remain the same
+        // on the next call.
+        let unix_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        // Populate the store
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(vec![Message::PriceFeedMessage(price_feed_message)], 10, 20),
+        )
+        .await;
+
+        // Advance the clock again
+        MockClock::advance_system_time(Duration::from_secs(1));
+        MockClock::advance(Duration::from_secs(1));
+
+        // Get the price feeds with update data
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap();
+
+        // check received_at is correct
+        assert_eq!(price_feeds_with_update_data.price_feeds.len(), 1);
+        assert_eq!(
+            price_feeds_with_update_data.price_feeds[0].received_at,
+            unix_timestamp as i64
+        );
+
+        // Check the store is ready
+        assert!(store.is_ready().await);
+
+        // Advance the clock to make the prices stale
+        MockClock::advance_system_time(READINESS_STALENESS_THRESHOLD);
+        MockClock::advance(READINESS_STALENESS_THRESHOLD);
+        // Check the store is not ready
+        assert!(!store.is_ready().await);
+    }
+
+    /// Test that the store retains the latest slots upon cache eviction.
+    ///
+    /// Store is set up with cache size of 100 and 1000 slot updates will
+    /// be stored all at the same time with random order.
+    /// After the cache eviction, the store should retain the latest 100
+    /// slots regardless of the order.
+    #[tokio::test]
+    pub async fn test_store_retains_latest_slots_upon_cache_eviction() {
+        // The receiver channel should stay open for the store to work
+        // properly. That is why we don't use _ here as it drops the channel
+        // immediately.
+        let (store, _receiver_tx) = setup_store(100).await;
+
+        let mut updates: Vec<Update> = (0..1000)
+            .flat_map(|slot| {
+                let messages = vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64,
+                    )),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        200,
+                        slot as i64,
+                        slot as i64,
+                    )),
+                ];
+                generate_update(messages, slot, slot)
+            })
+            .collect();
+
+        // Shuffle the updates
+        let mut rng = rand::thread_rng();
+        updates.shuffle(&mut rng);
+
+        // Store the updates
+        store_multiple_concurrent_valid_updates(store.clone(), updates).await;
+
+        // Check the last 100 slots are retained
+        for slot in 900..1000 {
+            let price_feeds_with_update_data = store
+                .get_price_feeds_with_update_data(
+                    vec![
+                        PriceIdentifier::new([100; 32]),
+                        PriceIdentifier::new([200; 32]),
+                    ],
+                    RequestTime::FirstAfter(slot as i64),
+                )
+                .await
+                .unwrap();
+            assert_eq!(price_feeds_with_update_data.price_feeds.len(), 2);
+            assert_eq!(price_feeds_with_update_data.price_feeds[0].slot, slot);
+            assert_eq!(price_feeds_with_update_data.price_feeds[1].slot, slot);
+        }
+
+        // Check nothing else is retained
+        for slot in 0..900 {
+            assert!(store
+                .get_price_feeds_with_update_data(
+                    vec![
+                        PriceIdentifier::new([100; 32]),
+                        PriceIdentifier::new([200; 32]),
+                    ],
+                    RequestTime::FirstAfter(slot as i64),
+                )
+                .await
+                .is_err());
+        }
+    }
+
+    /// A tracing layer that records the name and fields of every created span
+    /// and the level and fields of every event.
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans:  Arc<Mutex<Vec<(String, HashMap<String, String>)>>>,
+        events: Arc<Mutex<Vec<(tracing::Level, HashMap<String, String>)>>>,
+    }
+
+    #[derive(Default)]
+    struct SpanFieldVisitor(HashMap<String, String>);
+
+    impl tracing::field::Visit for SpanFieldVisitor {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for SpanRecorder {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: Context<'_, S>,
+        ) {
+            let mut visitor = SpanFieldVisitor::default();
+            attrs.record(&mut visitor);
+            self.spans
+                .lock()
+                .unwrap()
+                .push((attrs.metadata().name().to_string(), visitor.0));
+        }
+
+        fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            let mut visitor = SpanFieldVisitor::default();
+            event.record(&mut visitor);
+            self.events
+                .lock()
+                .unwrap()
+                .push((*event.metadata().level(), visitor.0));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_store_update_emits_tracing_spans() {
+        let recorder = SpanRecorder::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // Store the updates one by one so the slot is completed exactly once.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let spans = recorder.spans.lock().unwrap();
+
+        // Every update passed to the store gets its own span.
+        assert_eq!(
+            spans
+                .iter()
+                .filter(|(name, _)| name == "store_update")
+                .count(),
+            2
+        );
+
+        // The slot is completed once, so there is a single build span with its slot and
+        // message count.
+        let build_spans = spans
+            .iter()
+            .filter(|(name, _)| name == "build_message_states")
+            .collect::<Vec<_>>();
+        assert_eq!(build_spans.len(), 1);
+        assert_eq!(build_spans[0].1.get("slot"), Some(&"10".to_string()));
+        assert_eq!(build_spans[0].1.get("num_messages"), Some(&"2".to_string()));
+    }
+
+    #[tokio::test]
+    pub async fn test_subscribe_batched_delivers_single_batch_per_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut batch_rx = store
+            .subscribe_batched(
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                ],
+                Duration::from_secs(1),
+            )
+            .await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        // Both subscribed feeds arrive in a single batch and the unsubscribed
+        // feed is not included.
+        let batch = batch_rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(
+            batch
+                .iter()
+                .map(|update| PriceIdentifier::new(update.price_feed.feed_id))
+                .collect::<HashSet<_>>(),
+            vec![
+                PriceIdentifier::new([100; 32]),
+                PriceIdentifier::new([200; 32]),
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert!(batch.iter().all(|update| update.slot == 10));
+    }
+
+    #[tokio::test]
+    pub async fn test_lagging_batched_subscriber_does_not_hold_up_slots() {
+        let (store, mut update_rx) = setup_store(10).await;
+        // More slots than the capacity of the signal channel are stored.
+        tokio::spawn(async move { while update_rx.recv().await.is_some() {} });
+        let mut batch_rx = store
+            .subscribe_batched(
+                vec![PriceIdentifier::new([100; 32])],
+                Duration::from_millis(100),
+            )
+            .await;
+        let store_slot = |slot: Slot| {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
+            )
+        };
+
+        // Slots keep completing while the buffer of the subscriber is full,
+        // and the batches that do not fit are dropped.
+        for slot in 1..=(BATCHED_SUBSCRIPTION_CHAN_LEN as u64 + 5) {
+            store_slot(slot).await;
+        }
+
+        // Once the buffer stays full for longer than the maximum latency,
+        // the subscriber is dropped and its stream ends after the buffered
+        // batches.
+        MockClock::advance(Duration::from_secs(1));
+        store_slot(BATCHED_SUBSCRIPTION_CHAN_LEN as u64 + 6).await;
+
+        let mut num_batches = 0;
+        while batch_rx.recv().await.is_some() {
+            num_batches += 1;
+        }
+        assert_eq!(num_batches, BATCHED_SUBSCRIPTION_CHAN_LEN);
+        assert!(store.batched_subscribers.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_guardian_set_info_reflects_stored_sets() {
+        // The store is set up with guardian set 0 holding a single key.
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store
+            .update_guardian_set(
+                1,
+                GuardianSet {
+                    keys: vec![[1; 20], [2; 20], [3; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.guardian_set_info().await,
+            vec![
+                GuardianSetInfo {
+                    index:    0,
+                    num_keys: 1,
+                },
+                GuardianSetInfo {
+                    index:    1,
+                    num_keys: 3,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_multiproof_update_data_verifies_and_is_smaller() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let messages = (1..=5)
+            .map(|seed| {
+                Message::PriceFeedMessage(create_dummy_price_feed_message(seed * 10, 10, 9))
+            })
+            .collect();
+
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(messages, 10, 20))
+            .await;
+
+        let price_ids = vec![
+            PriceIdentifier::new([10; 32]),
+            PriceIdentifier::new([20; 32]),
+            PriceIdentifier::new([40; 32]),
+        ];
+
+        let per_leaf = store
+            .get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest)
+            .await
+            .unwrap();
+
+        let multiproof = store
+            .get_price_feeds_with_options(
+                price_ids,
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    multiproof: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // All the feeds are in the same slot so a single multiproof covers them.
+        assert_eq!(multiproof.wormhole_merkle_update_data.len(), 1);
+        assert!(multiproof.wormhole_merkle_update_data[0].starts_with(b"PNAM"));
+        let update_data = WormholeMerkleMultiProofUpdateData::try_from_slice(
+            multiproof.wormhole_merkle_update_data[0].as_ref(),
+        )
+        .unwrap();
+        assert_eq!(update_data.messages.len(), 3);
+
+        // Check the multiproof against the root signed in the Vaa.
+        let vaa: Vec<u8> = update_data.vaa.clone().into();
+        let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+        let WormholePayload::Merkle(merkle_root) =
+            WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                .unwrap()
+                .payload;
+        let messages: Vec<Vec<u8>> = update_data
+            .messages
+            .iter()
+            .map(|message| message.clone().into())
+            .collect();
+        assert!(MerkleRoot::<Keccak160>::new(merkle_root.root).check_multi(
+            update_data.proof.clone(),
+            &messages.iter().map(|m| m.as_slice()).collect::<Vec<_>>(),
+        ));
+
+        // The multiproof is smaller than the three separate proofs combined.
+        let per_leaf_update_data =
+            AccumulatorUpdateData::try_from_slice(per_leaf.wormhole_merkle_update_data[0].as_ref())
+                .unwrap();
+        let Proof::WormholeMerkle { updates, .. } = per_leaf_update_data.proof;
+        let separate_proofs_len: usize = updates
+            .iter()
+            .map(|update| update.proof.to_bytes().len() / 20)
+            .sum();
+        assert_eq!(separate_proofs_len, 9);
+        assert!(update_data.proof.hashes.len() < separate_proofs_len);
+        assert_eq!(
+            multiproof.cost_estimate().unwrap().total_proof_nodes,
+            update_data.proof.hashes.len()
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_requesting_more_feeds_than_limit_fails_early() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_feeds_per_request: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        // Requesting up to the limit works.
+        assert!(store
+            .get_price_feeds_with_update_data(
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .is_ok());
+
+        // Requesting more than the limit fails with a typed error.
+        let err = store
+            .get_price_feeds_with_update_data(
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                    PriceIdentifier::new([150; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::TooManyFeeds {
+                requested: 3,
+                limit:     2,
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_allow_stale_serves_latest_update_flagged_as_stale() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                latest_staleness_threshold: Some(Duration::from_secs(10)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // The only update of the feed is published well before the staleness threshold.
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let price_feed_message =
+            create_dummy_price_feed_message(100, current_time - 100, current_time - 101);
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(vec![Message::PriceFeedMessage(price_feed_message)], 10, 20),
+        )
+        .await;
+
+        // By default the stale update is not served.
+        let err = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::StalePriceFeed {
+                price_id: PriceIdentifier::new([100; 32]),
+            })
+        );
+
+        // When stale updates are allowed, the update is served and flagged as stale.
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_options(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    allow_stale: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(price_feeds_with_update_data.price_feeds.len(), 1);
+        assert_eq!(
+            price_feeds_with_update_data.price_feeds[0].price_feed,
+            price_feed_message
+        );
+        assert!(price_feeds_with_update_data.price_feeds[0].is_stale);
+    }
+
+    #[tokio::test]
+    pub async fn test_latency_histograms_capture_ingestion_latency() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // The Vaa is signed 5 seconds before the store receives it. The mocked clock
+        // is advanced first so the signing time does not precede the epoch.
+        MockClock::advance_system_time(Duration::from_secs(5));
+        let current_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        for update in generate_update_with_vaa_timestamp(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+            (current_time - 5) as u32,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let encoded = store.metrics().encode().unwrap();
+
+        // A single Vaa is observed with a latency of at least 5 seconds, so it falls
+        // outside of all the buckets below 5 seconds.
+        assert!(encoded.contains("hermes_store_vaa_receive_latency_seconds_count 1\n"));
+        assert!(encoded.contains("hermes_store_vaa_receive_latency_seconds_bucket{le=\"2.5\"} 0\n"));
+        assert!(
+            encoded.contains("hermes_store_vaa_receive_latency_seconds_bucket{le=\"+Inf\"} 1\n")
+        );
+
+        // The slot is completed once, so a single notification latency is observed.
+        assert!(encoded.contains("hermes_store_update_notification_latency_seconds_count 1\n"));
+    }
+
+    #[tokio::test]
+    pub async fn test_pinned_slot_is_not_evicted() {
+        let (store, _receiver_tx) = setup_store(100).await;
+
+        store.pin_slot(0).await.unwrap();
+
+        let updates: Vec<Update> = (0..=200)
+            .flat_map(|slot| {
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64,
+                    ))],
+                    slot,
+                    slot,
+                )
+            })
+            .collect();
+
+        store_multiple_concurrent_valid_updates(store.clone(), updates).await;
+
+        // The pinned slot is still queryable.
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::FirstAfter(0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(price_feeds_with_update_data.price_feeds[0].slot, 0);
+
+        // Unpinned slots older than the latest 100 slots are evicted, so the
+        // first retained update after them is served instead.
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::FirstAfter(50),
+            )
+            .await
+            .unwrap();
+        assert_eq!(price_feeds_with_update_data.price_feeds[0].slot, 101);
+    }
+
+    #[tokio::test]
+    pub async fn test_pinning_more_slots_than_limit_fails() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_pinned_slots: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        store.pin_slot(1).await.unwrap();
+        store.pin_slot(2).await.unwrap();
+        assert_eq!(
+            store
+                .pin_slot(3)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::TooManyPinnedSlots { limit: 2 })
+        );
+
+        // Unpinning frees up room for another slot.
+        store.unpin_slot(1).await;
+        store.pin_slot(3).await.unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_list_feeds_with_metadata_enriches_feed_ids() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let updates = vec![
+            Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+            Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+        ];
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(updates, 10, 20))
+            .await;
+
+        let metadata = FeedMetadata {
+            symbol:      "Crypto.BTC/USD".to_string(),
+            asset_type:  "Crypto".to_string(),
+            description: "BITCOIN / US DOLLAR".to_string(),
+        };
+        let provider = HashMap::from([(PriceIdentifier::new([100; 32]), metadata.clone())]);
+        store.load_feed_metadata(&provider).await.unwrap();
+
+        assert_eq!(
+            store.feed_metadata(PriceIdentifier::new([100; 32])).await,
+            Some(metadata.clone())
+        );
+        assert_eq!(
+            store.list_feeds_with_metadata().await,
+            HashMap::from([
+                (PriceIdentifier::new([100; 32]), Some(metadata)),
+                (PriceIdentifier::new([200; 32]), None),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_latest_returns_max_publish_time_per_feed_across_slots() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // Slot 10 holds the newest update of feed 200.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 20, 19)),
+            ],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        // Slot 11 holds the newest update of feed 100 and an older update of
+        // feed 200.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 11, 10)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 15, 14)),
+            ],
+            11,
+            21,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let price_feeds = store
+            .get_price_feeds_with_update_data(
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap()
+            .price_feeds;
+
+        assert_eq!(price_feeds.len(), 2);
+        assert_eq!(price_feeds[0].price_feed.feed_id, [100; 32]);
+        assert_eq!(price_feeds[0].price_feed.publish_time, 11);
+        assert_eq!(price_feeds[0].slot, 11);
+        assert_eq!(price_feeds[1].price_feed.feed_id, [200; 32]);
+        assert_eq!(price_feeds[1].price_feed.publish_time, 20);
+        assert_eq!(price_feeds[1].slot, 10);
+    }
+
+    #[tokio::test]
+    pub async fn test_persisted_seq_watermark_skips_observed_vaas_after_restart() {
+        let path = std::env::temp_dir().join(format!(
+            "hermes-test-persisted-seq-watermark-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        async fn setup_store_with_watermark(path: &Path) -> (Arc<Store>, Receiver<()>) {
+            let (update_tx, update_rx) = tokio::sync::mpsc::channel(1000);
+            let store = StoreBuilder::new(update_tx, 10)
+                .with_watermark_store(Box::new(FileSequenceWatermarkStore::new(path)))
+                .build()
+                .unwrap();
+            store
+                .update_guardian_set(
+                    0,
+                    GuardianSet {
+                        keys: vec![[0; 20]],
+                    },
+                )
+                .await
+                .unwrap();
+            (store, update_rx)
+        }
+
+        let (store, _update_rx) = setup_store_with_watermark(&path).await;
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+        assert_eq!(*store.seq_watermark.read().await, Some(21));
+
+        // Restart the store. The watermark is loaded from the previous run.
+        let (store, mut update_rx) = setup_store_with_watermark(&path).await;
+        assert_eq!(*store.seq_watermark.read().await, Some(21));
+
+        // Re-delivering a previously observed Vaa does not complete its slot.
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 11, 10,
+            ))],
+            11,
+            21,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert!(update_rx.try_recv().is_err());
+        assert!(store.get_price_feed_ids().await.is_empty());
+
+        // New Vaas are processed and advance the watermark.
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 12, 11,
+            ))],
+            12,
+            22,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert_eq!(update_rx.recv().await, Some(()));
+        assert_eq!(*store.seq_watermark.read().await, Some(22));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_first_after_within_rejects_updates_beyond_tolerance() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // There is a gap in the updates between publish time 10 and 30.
+        for (slot, publish_time) in [(10, 10), (11, 30)] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    publish_time,
+                    publish_time - 1,
+                ))],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        // The first update after 15 is published 15 seconds later.
+        let result = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::FirstAfterWithin {
+                    time:      15,
+                    tolerance: Duration::from_secs(10),
+                },
+            )
+            .await;
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<StoreError>(),
+            Some(&StoreError::NoDataWithinTolerance {
+                price_id:  PriceIdentifier::new([100; 32]),
+                time:      15,
+                tolerance: Duration::from_secs(10),
+            })
+        );
+
+        // The first update after 25 is published within the tolerance.
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::FirstAfterWithin {
+                    time:      25,
+                    tolerance: Duration::from_secs(10),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            price_feeds_with_update_data.price_feeds[0]
+                .price_feed
+                .publish_time,
+            30
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_status_reflects_populated_store() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        assert_eq!(
+            store.status().await,
+            StoreStatus {
+                is_ready:                       false,
+                latest_completed_slot:          None,
+                num_feeds:                      0,
+                last_completed_update_age_secs: None,
+                num_observed_vaas:              0,
+                num_completed_slots:            0,
+            }
+        );
+
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    )),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        200,
+                        slot as i64,
+                        slot as i64 - 1,
+                    )),
+                ],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        let status = store.status().await;
+        assert!(status.is_ready);
+        assert_eq!(status.latest_completed_slot, Some(11));
+        assert_eq!(status.num_feeds, 2);
+        assert!(status.last_completed_update_age_secs.is_some());
+        assert_eq!(status.num_observed_vaas, 2);
+        assert_eq!(status.num_completed_slots, 2);
+
+        let status = serde_json::to_value(&status).unwrap();
+        assert_eq!(status["latest_completed_slot"], 11);
+        assert_eq!(status["num_feeds"], 2);
+    }
+
+    #[tokio::test]
+    pub async fn test_change_filter_only_delivers_large_moves() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut batch_rx = store
+            .subscribe_batched_with_change_filter(
+                vec![PriceIdentifier::new([100; 32])],
+                Duration::from_secs(1),
+                HashMap::from([(PriceIdentifier::new([100; 32]), 0.01)]),
+            )
+            .await;
+
+        // The first update is always delivered. The moves of 0.5% and 0.9%
+        // from the last delivered price are below the ratio while the move
+        // of 2% is above it.
+        for (slot, price) in [(10, 1000), (11, 1005), (12, 1009), (13, 1020)] {
+            let price_feed_message = PriceFeedMessage {
+                price,
+                ..create_dummy_price_feed_message(100, slot as i64, slot as i64 - 1)
+            };
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(price_feed_message)],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        let batch = batch_rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].slot, 10);
+        assert_eq!(batch[0].price_feed.price, 1000);
+
+        let batch = batch_rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].slot, 13);
+        assert_eq!(batch[0].price_feed.price, 1020);
+
+        assert!(batch_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_price_feeds_follow_requested_order() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        let feed_ids = |price_feeds: Vec<PriceFeedUpdate>| {
+            price_feeds
+                .iter()
+                .map(|price_feed| price_feed.price_feed.feed_id)
+                .collect::<Vec<_>>()
+        };
+
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(
+                vec![
+                    PriceIdentifier::new([200; 32]),
+                    PriceIdentifier::new([100; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            feed_ids(price_feeds_with_update_data.price_feeds),
+            vec![[200; 32], [100; 32]]
+        );
+
+        // A missing feed fails the query unless partial results are allowed,
+        // in which case the order of the present feeds is preserved.
+        let price_ids = vec![
+            PriceIdentifier::new([200; 32]),
+            PriceIdentifier::new([150; 32]),
+            PriceIdentifier::new([100; 32]),
+        ];
+        assert!(store
+            .get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest)
+            .await
+            .is_err());
+
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_options(
+                price_ids,
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    partial: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            feed_ids(price_feeds_with_update_data.price_feeds),
+            vec![[200; 32], [100; 32]]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_replay_window_rejects_old_sequences() {
+        let (store, mut update_rx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                replay_window: Some(5),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let store_slot = |slot: Slot, sequence: u64| {
+            let store = store.clone();
+            async move {
+                for update in generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    sequence,
+                ) {
+                    store.store_update(update).await?;
+                }
+                Ok::<_, anyhow::Error>(())
+            }
+        };
+
+        store_slot(10, 20).await.unwrap();
+        assert_eq!(update_rx.recv().await, Some(()));
+
+        // A sequence arriving out of order within the window is accepted.
+        store_slot(9, 17).await.unwrap();
+        assert_eq!(update_rx.recv().await, Some(()));
+
+        // A sequence clearly older than the window is rejected.
+        assert_eq!(
+            store_slot(8, 10)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::ReplayedVaa {
+                sequence:            10,
+                high_water_sequence: 20,
+            })
+        );
+        assert!(update_rx.try_recv().is_err());
+        assert_eq!(store.metrics().replayed_vaas.get(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_is_ready_waits_for_warmup_thresholds() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                warmup_min_feeds: 2,
+                warmup_min_slots: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // A recent update with a single feed is not enough.
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert!(!store.is_ready().await);
+
+        // Both the feed and the slot thresholds are met.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 11, 10)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 11, 10)),
+            ],
+            11,
+            21,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert!(store.is_ready().await);
+    }
+
+    #[tokio::test]
+    pub async fn test_proof_granularity_omits_proof_parts() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let messages = (1..=3)
+            .map(|seed| {
+                Message::PriceFeedMessage(create_dummy_price_feed_message(seed * 10, 10, 9))
+            })
+            .collect();
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(messages, 10, 20))
+            .await;
+
+        let get_update_data = |proof_granularity| {
+            let store = store.clone();
+            async move {
+                let price_feeds_with_update_data = store
+                    .get_price_feeds_with_options(
+                        vec![PriceIdentifier::new([20; 32])],
+                        RequestTime::Latest,
+                        PriceFeedsQueryOptions {
+                            proof_granularity,
+                            ..Default::default()
+                        },
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    price_feeds_with_update_data
+                        .wormhole_merkle_update_data
+                        .len(),
+                    1
+                );
+                AccumulatorUpdateData::try_from_slice(
+                    price_feeds_with_update_data.wormhole_merkle_update_data[0].as_ref(),
+                )
+                .unwrap()
+            }
+        };
+
+        // Leaves only carries all the leaves of the tree without any path.
+        let Proof::WormholeMerkle { vaa, updates } =
+            get_update_data(ProofGranularity::LeavesOnly).await.proof;
+        assert_eq!(updates.len(), 3);
+        assert!(updates.iter().all(|update| update.proof.is_empty()));
+
+        // The client rebuilds the tree from the leaves, checks it against the
+        // root signed in the Vaa and derives the path of its message.
+        let vaa: Vec<u8> = vaa.into();
+        let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+        let WormholePayload::Merkle(merkle_root) =
+            WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                .unwrap()
+                .payload;
+        let leaves: Vec<Vec<u8>> = updates
+            .iter()
+            .map(|update| update.message.clone().into())
+            .collect();
+        let merkle_tree =
+            MerkleTree::<Keccak160>::from_set(leaves.iter().map(|leaf| leaf.as_slice())).unwrap();
+        assert_eq!(merkle_tree.root.as_bytes(), merkle_root.root.as_slice());
+
+        let message = pythnet_sdk::wire::to_vec::<_, byteorder::BE>(&Message::PriceFeedMessage(
+            create_dummy_price_feed_message(20, 10, 9),
+        ))
+        .unwrap();
+        let path = merkle_tree.prove(&message).unwrap();
+        assert!(MerkleRoot::<Keccak160>::new(merkle_root.root).check(path, &message));
+
+        // Root only carries the Vaa alone.
+        let Proof::WormholeMerkle { updates, .. } =
+            get_update_data(ProofGranularity::RootOnly).await.proof;
+        assert!(updates.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_snapshot_round_trip_bootstraps_empty_store() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    )),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        200,
+                        slot as i64,
+                        slot as i64 - 1,
+                    )),
+                ],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        let snapshot = store.export_snapshot().await.unwrap();
+
+        let (imported_store, _imported_receiver_tx) = setup_store(10).await;
+        assert!(!imported_store.is_ready().await);
+        imported_store.import_snapshot(&snapshot).await.unwrap();
+        assert!(imported_store.is_ready().await);
+
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([200; 32]),
+        ];
+        for request_time in [RequestTime::Latest, RequestTime::FirstAfter(10)] {
+            let expected = store
+                .get_price_feeds_with_update_data(price_ids.clone(), request_time.clone())
+                .await
+                .unwrap();
+            let imported = imported_store
+                .get_price_feeds_with_update_data(price_ids.clone(), request_time)
+                .await
+                .unwrap();
+            assert_eq!(
+                imported.wormhole_merkle_update_data,
+                expected.wormhole_merkle_update_data
+            );
+            for (imported, expected) in imported.price_feeds.iter().zip(expected.price_feeds) {
+                assert_eq!(imported.price_feed, expected.price_feed);
+                assert_eq!(imported.slot, expected.slot);
+            }
+        }
+
+        // The Vaas are verified again on import, so a store without the
+        // guardian set rejects the snapshot.
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let untrusting_store = Store::new(update_tx, 10);
+        assert!(untrusting_store.import_snapshot(&snapshot).await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_check_stall_reports_stalled_ingestion() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                stall_threshold: Duration::from_secs(1),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        store.check_stall().await;
+        assert_eq!(store.metrics().stall_seconds.get(), 0);
+
+        // No slot completes past the threshold.
+        MockClock::advance(Duration::from_secs(2));
+        store.check_stall().await;
+        let stall_seconds = store.metrics().stall_seconds.get();
+        assert!(stall_seconds >= 2);
+
+        MockClock::advance(Duration::from_secs(1));
+        store.check_stall().await;
+        assert!(store.metrics().stall_seconds.get() > stall_seconds);
+    }
+
+    struct RecordingSink {
+        emitted: Mutex<Vec<Vec<MessageState>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl UpdateSink for RecordingSink {
+        async fn emit(&self, states: &[MessageState]) -> Result<()> {
+            self.emitted.lock().unwrap().push(states.to_vec());
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    #[async_trait::async_trait]
+    impl UpdateSink for FailingSink {
+        async fn emit(&self, _states: &[MessageState]) -> Result<()> {
+            Err(anyhow!("Sink is down"))
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_update_sinks_observe_completed_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let first_sink = Arc::new(RecordingSink {
+            emitted: Mutex::new(vec![]),
+        });
+        let second_sink = Arc::new(RecordingSink {
+            emitted: Mutex::new(vec![]),
+        });
+        store.add_update_sink(first_sink.clone()).await;
+        store.add_update_sink(Arc::new(FailingSink)).await;
+        store.add_update_sink(second_sink.clone()).await;
+
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        // Both sinks observe the completed slot despite the failing sink.
+        for sink in [first_sink, second_sink] {
+            let emitted = sink.emitted.lock().unwrap();
+            assert_eq!(emitted.len(), 1);
+            assert_eq!(emitted[0].len(), 2);
+            assert!(emitted[0].iter().all(|state| state.slot == 10));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_dropped_update_receiver_degrades_store() {
+        let (store, update_rx) = setup_store(10).await;
+        drop(update_rx);
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        // The store survives and keeps serving the ingested update.
+        assert!(store.is_degraded());
+        assert!(store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn test_get_feeds_by_prefix_matches_shared_prefix() {
+        // The prefix matches more feeds than a request may list.
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_feeds_per_request: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let feed_id = |prefix: [u8; 2]| {
+            let mut feed_id = [0; 32];
+            feed_id[..2].copy_from_slice(&prefix);
+            feed_id
+        };
+        let mut messages: Vec<_> = [[0xab, 0x01], [0xab, 0x02], [0xac, 0x01]]
+            .into_iter()
+            .map(|prefix| {
+                Message::PriceFeedMessage(PriceFeedMessage {
+                    feed_id: feed_id(prefix),
+                    ..create_dummy_price_feed_message(100, 10, 9)
+                })
+            })
+            .collect();
+        // A matching feed with only a Twap message is not a price feed.
+        messages.push(Message::TwapMessage(TwapMessage {
+            feed_id:           feed_id([0xab, 0x03]),
+            cumulative_price:  100,
+            cumulative_conf:   100,
+            num_down_slots:    0,
+            exponent:          0,
+            publish_time:      10,
+            prev_publish_time: 9,
+            publish_slot:      10,
+        }));
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(messages, 10, 20))
+            .await;
+
+        let price_feeds = store
+            .get_feeds_by_prefix(&[0xab], RequestTime::Latest)
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(
+            price_feeds
+                .iter()
+                .map(|price_feed| price_feed.price_feed.feed_id)
+                .collect::<Vec<_>>(),
+            vec![feed_id([0xab, 0x01]), feed_id([0xab, 0x02])]
+        );
+
+        assert!(store
+            .get_feeds_by_prefix(&[0xad], RequestTime::Latest)
+            .await
+            .unwrap()
+            .price_feeds
+            .is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_deadline_bounds_proof_construction() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            ),
+        )
+        .await;
+        store.proof_pool.set_delay(Duration::from_millis(500));
+
+        let query = |deadline, options: PriceFeedsQueryOptions| {
+            store.get_price_feeds_with_options(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    deadline,
+                    ..options
+                },
+            )
+        };
+
+        // The update data of each price feed, the per message proofs, the
+        // multiproofs, the leaves and the roots are all constructed within the
+        // deadline.
+        for options in [
+            PriceFeedsQueryOptions::default(),
+            PriceFeedsQueryOptions {
+                multiproof: true,
+                ..Default::default()
+            },
+            PriceFeedsQueryOptions {
+                proof_granularity: ProofGranularity::LeavesOnly,
+                ..Default::default()
+            },
+            PriceFeedsQueryOptions {
+                proof_granularity: ProofGranularity::RootOnly,
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                query(Some(Duration::from_millis(50)), options.clone())
+                    .await
+                    .unwrap_err()
+                    .downcast_ref::<StoreError>(),
+                Some(&StoreError::Timeout {
+                    deadline: Duration::from_millis(50),
+                })
+            );
+
+            // The slow construction completes when there is enough time.
+            assert!(query(Some(Duration::from_secs(10)), options.clone())
+                .await
+                .is_ok());
+            assert!(query(None, options).await.is_ok());
+        }
+
+        // The price feed updates and the update data are constructed one after
+        // the other, so both count towards the deadline, as does the update
+        // data rebuilt to find the oversized feeds after them.
+        assert_eq!(
+            query(Some(Duration::from_millis(750)), Default::default())
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::Timeout {
+                deadline: Duration::from_millis(750),
+            })
+        );
+        assert!(query(Some(Duration::from_millis(1250)), Default::default())
+            .await
+            .is_ok());
+        let options = PriceFeedsQueryOptions {
+            max_update_bytes: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            query(Some(Duration::from_millis(1250)), options.clone())
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::Timeout {
+                deadline: Duration::from_millis(1250),
+            })
+        );
+        assert_eq!(
+            query(Some(Duration::from_secs(10)), options)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::UpdateTooLarge {
+                price_ids: vec![PriceIdentifier::new([100; 32])],
+                limit:     1,
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_latest_confirmed_serves_behind_newest_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for slot in [10, 11, 12] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        let query = |behind| {
+            store.get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::LatestConfirmed { behind },
+            )
+        };
+
+        let price_feeds = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::latest_confirmed(),
+            )
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(price_feeds[0].slot, 11);
+        assert_eq!(price_feeds[0].price_feed.publish_time, 11);
+
+        assert_eq!(query(0).await.unwrap().price_feeds[0].slot, 12);
+        assert_eq!(query(2).await.unwrap().price_feeds[0].slot, 10);
+
+        assert_eq!(
+            query(3).await.unwrap_err().downcast_ref::<StoreError>(),
+            Some(&StoreError::NotEnoughRetainedSlots {
+                behind:   3,
+                retained: 3,
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_slow_update_listener_does_not_stall_others() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut fast_listener = store.add_update_listener(10).await;
+        let mut slow_listener = store.add_update_listener(1).await;
+
+        for slot in [10, 11, 12] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        // The fast listener gets all the slots although the slow one never
+        // reads its notifications.
+        for slot in [10, 11, 12] {
+            assert_eq!(fast_listener.try_recv().unwrap(), CompletedSlot { slot });
+        }
+        assert!(fast_listener.try_recv().is_err());
+
+        assert_eq!(
+            slow_listener.try_recv().unwrap(),
+            CompletedSlot { slot: 10 }
+        );
+        assert!(slow_listener.try_recv().is_err());
+
+        assert_eq!(
+            store
+                .metrics
+                .dropped_notifications
+                .get_or_create(&StoreMetrics::listener_labels(1))
+                .get(),
+            2
+        );
+        assert_eq!(
+            store
+                .metrics
+                .dropped_notifications
+                .get_or_create(&StoreMetrics::listener_labels(0))
+                .get(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_disabled_message_types_are_skipped() {
+        let (store, _receiver_tx) = setup_store(10).await;
+        store
+            .set_message_type_enabled(MessageType::TwapMessage, false)
+            .await;
+
+        let twap_message = TwapMessage {
+            feed_id:           [100; 32],
+            cumulative_price:  100,
+            cumulative_conf:   100,
+            num_down_slots:    0,
+            exponent:          0,
+            publish_time:      10,
+            prev_publish_time: 9,
+            publish_slot:      10,
+        };
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::TwapMessage(twap_message),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        let fetch = |message_type| {
+            store.storage.fetch_message_states(
+                vec![[100; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(message_type),
+            )
+        };
+
+        assert!(fetch(MessageType::PriceFeedMessage).await.is_ok());
+        assert!(fetch(MessageType::TwapMessage).await.is_err());
+        assert_eq!(store.metrics.skipped_messages.get(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_root_index_covers_retained_slots() {
+        let (store, _receiver_tx) = setup_store(2).await;
+
+        let mut roots: HashMap<Slot, [u8; 20]> = HashMap::new();
+        for slot in [10, 11, 12] {
+            let messages = vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100,
+                slot as i64,
+                slot as i64 - 1,
+            ))];
+            let raw_messages = messages
+                .iter()
+                .map(|message| pythnet_sdk::wire::to_vec::<_, byteorder::BE>(message).unwrap())
+                .collect::<Vec<_>>();
+            let merkle_tree =
+                MerkleTree::<Keccak160>::from_set(raw_messages.iter().map(|m| m.as_ref())).unwrap();
+            roots.insert(slot, merkle_tree.root.as_bytes().try_into().unwrap());
+
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(messages, slot, 20 + slot),
+            )
+            .await;
+        }
+
+        // The oldest slot is evicted from the cache of size 2.
+        assert_eq!(
+            store.root_index().await,
+            vec![(11, roots[&11], 31), (12, roots[&12], 32)]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_slot_is_built_after_finality_confirmations() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                finality_confirmations: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let store_slot = |slot: Slot| {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    20 + slot,
+                ),
+            )
+        };
+        let fetch_latest = || {
+            store.storage.fetch_message_states(
+                vec![[100; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+        };
+
+        store_slot(10).await;
+        store_slot(11).await;
+        assert_eq!(*store.latest_completed_slot.read().await, None);
+        assert!(fetch_latest().await.is_err());
+
+        store_slot(12).await;
+        assert_eq!(*store.latest_completed_slot.read().await, Some(10));
+        assert_eq!(fetch_latest().await.unwrap()[0].slot, 10);
+    }
+
+    #[tokio::test]
+    pub async fn test_deep_health_check_detects_corrupted_proofs() {
+        let (store, _receiver_tx) = setup_store(10).await;
+        assert!(store.deep_health_check().await.is_err());
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        assert!(store.deep_health_check().await.is_ok());
+
+        // Corrupting the price of the checked message makes its proof invalid.
+        store.storage.mutate_message_state(
+            &MessageStateKey {
+                feed_id: [100; 32],
+                type_:   MessageType::PriceFeedMessage,
+            },
+            &MessageStateTime {
+                publish_time: 10,
+                slot:         10,
+            },
+            |message_state| {
+                if let Message::PriceFeedMessage(ref mut price_feed) = message_state.message {
+                    price_feed.price += 1;
+                }
+            },
+        );
+
+        assert!(store.deep_health_check().await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_deep_health_check_checks_a_message_of_the_shard() {
+        // The feed of seed 100 is in shard 0 and the one of seed 101 in shard 1.
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                shard: Some(ShardConfig {
+                    total_shards: 2,
+                    shard_index:  1,
+                }),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(101, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        // The first message of the slot is outside the shard, so the check
+        // falls back to the stored one.
+        assert!(store.deep_health_check().await.is_ok());
+
+        store.storage.mutate_message_state(
+            &MessageStateKey {
+                feed_id: [101; 32],
+                type_:   MessageType::PriceFeedMessage,
+            },
+            &MessageStateTime {
+                publish_time: 10,
+                slot:         10,
+            },
+            |message_state| {
+                if let Message::PriceFeedMessage(ref mut price_feed) = message_state.message {
+                    price_feed.price += 1;
+                }
+            },
+        );
+
+        assert!(store.deep_health_check().await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_deep_health_check_fails_unless_the_config_explains_an_empty_slot() {
+        // The feed of seed 100 is in shard 0 and the one of seed 101 in shard 1.
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                shard: Some(ShardConfig {
+                    total_shards: 2,
+                    shard_index:  1,
+                }),
+                inverted_publish_time_policy: InvertedPublishTimePolicy::Reject,
+                ..Default::default()
+            },
+        )
+        .await;
+        let store_slot = |seed, slot: Slot, prev_publish_time| {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        seed,
+                        slot as i64,
+                        prev_publish_time,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
+            )
+        };
+
+        // The only message of the slot is outside the shard.
+        store_slot(100, 10, 9).await;
+        assert!(store.deep_health_check().await.is_ok());
+
+        // The only message of the slot is in the shard, but it is rejected.
+        store_slot(101, 11, 12).await;
+        assert!(store.deep_health_check().await.is_err());
+
+        // The only message of the slot is of a disabled type.
+        store
+            .set_message_type_enabled(MessageType::PriceFeedMessage, false)
+            .await;
+        store_slot(101, 12, 11).await;
+        assert!(store.deep_health_check().await.is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn test_fragmented_accumulator_messages_are_combined() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                incremental_accumulation: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut updates = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        );
+        let vaa_update = updates.pop().unwrap();
+        let accumulator_messages = match updates.pop().unwrap() {
+            Update::AccumulatorMessages(accumulator_messages) => accumulator_messages,
+            _ => panic!("Expected accumulator messages"),
+        };
+
+        let (first_fragment, second_fragment) = accumulator_messages.raw_messages.split_at(2);
+        for fragment in [first_fragment, second_fragment] {
+            store
+                .store_update(Update::AccumulatorMessages(AccumulatorMessages {
+                    raw_messages: fragment.to_vec(),
+                    ..accumulator_messages.clone()
+                }))
+                .await
+                .unwrap();
+        }
+        store.store_update(vaa_update).await.unwrap();
+
+        let root = MerkleTree::<Keccak160>::from_set(
+            accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
+        )
+        .unwrap()
+        .root;
+
+        let message_states = store
+            .storage
+            .fetch_message_states(
+                vec![[100; 32], [150; 32], [200; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .unwrap();
+        for message_state in message_states {
+            assert!(root.check(
+                message_state.proof_set.wormhole_merkle_proof.proof.clone(),
+                &message_state.raw_message,
+            ));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_read_only_store_ignores_updates() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        store.set_read_only(true);
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 11, 10,
+                ))],
+                11,
+                21,
+            ),
+        )
+        .await;
+
+        assert_eq!(*store.latest_completed_slot.read().await, Some(10));
+        assert_eq!(store.storage.wormhole_merkle_state_slots().await, vec![10]);
+        let price_feeds = store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(price_feeds[0].slot, 10);
+
+        // Updates are ingested again once the read-only mode is off.
+        store.set_read_only(false);
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 11, 10,
+                ))],
+                11,
+                21,
+            ),
+        )
+        .await;
+        assert_eq!(*store.latest_completed_slot.read().await, Some(11));
+    }
+
+    #[tokio::test]
+    pub async fn test_strict_read_only_store_rejects_updates() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                strict_read_only: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        store.set_read_only(true);
+
+        let mut updates = generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        );
+        assert_eq!(
+            store
+                .store_update(updates.remove(0))
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::ReadOnly)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_price_feed_updates_carry_their_merkle_leaf_index() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let updates = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        );
+        let raw_messages = match &updates[0] {
+            Update::AccumulatorMessages(accumulator_messages) => {
+                accumulator_messages.raw_messages.clone()
+            }
+            _ => panic!("Expected accumulator messages"),
+        };
+        store_multiple_concurrent_valid_updates(store.clone(), updates).await;
+
+        let merkle_tree =
+            MerkleTree::<Keccak160>::from_set(raw_messages.iter().map(|m| m.as_ref())).unwrap();
+        let num_leaves = merkle_tree.nodes.len() / 2;
+
+        // The feeds are requested out of the order of the leaves.
+        let price_ids = [200, 100, 150].map(|seed| PriceIdentifier::new([seed; 32]));
+        let price_feeds = store
+            .get_price_feeds_with_update_data(price_ids.to_vec(), RequestTime::Latest)
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(
+            price_feeds
+                .iter()
+                .map(|price_feed| price_feed.merkle_leaf_index)
+                .collect::<Vec<_>>(),
+            vec![2, 0, 1]
+        );
+
+        for price_id in price_ids {
+            let message_state = store
+                .storage
+                .fetch_message_states(
+                    vec![price_id.to_bytes()],
+                    RequestTime::Latest,
+                    MessageStateFilter::Only(MessageType::PriceFeedMessage),
+                )
+                .await
+                .unwrap()
+                .remove(0);
+            let proof = message_state.proof_set.wormhole_merkle_proof;
+            let leaf_index = proof.leaf_index as usize;
+
+            assert_eq!(raw_messages[leaf_index], message_state.raw_message);
+            assert_eq!(proof.proof, merkle_tree.find_path(num_leaves + leaf_index));
+            assert!(merkle_tree
+                .root
+                .check(proof.proof, &raw_messages[leaf_index]));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_stale_feeds_lists_feeds_without_recent_updates() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        MockClock::advance_system_time(Duration::from_secs(10));
+
+        // Only the first feed is updated after the clock moved.
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 11, 10,
+                ))],
+                11,
+                21,
+            ),
+        )
+        .await;
+
+        let stale_feeds = store.stale_feeds(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(stale_feeds.len(), 1);
+        assert_eq!(stale_feeds[0].0, PriceIdentifier::new([150; 32]));
+        assert!(stale_feeds[0].1 >= Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    pub async fn test_consistent_snapshot_serves_feeds_as_of_a_single_time() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // The feed 100 is updated in every slot while the feed 150 lags
+        // behind and is last updated in slot 11.
+        for (slot, seeds) in [(10, vec![100, 150]), (11, vec![100, 150]), (12, vec![100])] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    seeds
+                        .into_iter()
+                        .map(|seed| {
+                            Message::PriceFeedMessage(create_dummy_price_feed_message(
+                                seed,
+                                slot as i64,
+                                slot as i64 - 1,
+                            ))
+                        })
+                        .collect(),
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([150; 32]),
+        ];
+        let price_feeds = store
+            .get_consistent_snapshot(price_ids.clone(), RequestTime::Latest, Default::default())
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(price_feeds.len(), 2);
+        assert!(price_feeds
+            .iter()
+            .all(|price_feed| price_feed.price_feed.publish_time <= 11));
+        assert_eq!(price_feeds[0].slot, 11);
+        assert_eq!(price_feeds[1].slot, 11);
+
+        // A feed without data at the resolved time fails the snapshot unless
+        // it is partial.
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([200; 32]),
+        ];
+        assert!(store
+            .get_consistent_snapshot(price_ids.clone(), RequestTime::Latest, Default::default())
+            .await
+            .is_err());
+        let price_feeds = store
+            .get_consistent_snapshot(
+                price_ids,
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    partial: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .price_feeds;
+        assert_eq!(price_feeds.len(), 1);
+        assert_eq!(price_feeds[0].slot, 12);
+    }
+
+    #[tokio::test]
+    pub async fn test_shards_store_a_partition_of_the_feeds() {
+        let seeds = [100, 101, 102, 103, 150, 200];
+
+        let mut shard_feed_ids = vec![];
+        for shard_index in 0..2 {
+            let (store, _receiver_tx) = setup_store_with_config(
+                10,
+                StoreConfig {
+                    shard: Some(ShardConfig {
+                        total_shards: 2,
+                        shard_index,
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    seeds
+                        .iter()
+                        .map(|seed| {
+                            Message::PriceFeedMessage(create_dummy_price_feed_message(*seed, 10, 9))
+                        })
+                        .collect(),
+                    10,
+                    20,
+                ),
+            )
+            .await;
+
+            let feed_ids = store.get_price_feed_ids().await;
+            assert!(!feed_ids.is_empty());
+            shard_feed_ids.push(feed_ids);
+        }
+
+        assert!(shard_feed_ids[0].is_disjoint(&shard_feed_ids[1]));
+        assert_eq!(
+            shard_feed_ids[0]
+                .union(&shard_feed_ids[1])
+                .copied()
+                .collect::<HashSet<_>>(),
+            seeds
+                .iter()
+                .map(|seed| PriceIdentifier::new([*seed; 32]))
+                .collect::<HashSet<_>>()
+        );
+    }
+
+    /// Generates the updates of a slot whose accumulator message set is
+    /// empty. The Vaa signs the root of an unrelated message set.
+    fn generate_empty_slot_update(slot: Slot, sequence: u64) -> Vec<Update> {
+        let mut updates = generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            slot,
+            sequence,
+        );
+        if let Update::AccumulatorMessages(accumulator_messages) = &mut updates[0] {
+            accumulator_messages.raw_messages.clear();
+        }
+        updates
+    }
+
+    #[tokio::test]
+    pub async fn test_empty_slot_is_stored_without_feeds() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for update in generate_empty_slot_update(10, 20) {
+            store.store_update(update).await.unwrap();
+        }
+
+        assert_eq!(*store.latest_completed_slot.read().await, Some(10));
+        assert!(store.get_price_feed_ids().await.is_empty());
+        assert_eq!(store.metrics.empty_slots.get(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_empty_slot_is_rejected_when_configured() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                reject_empty_slots: true,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut updates = generate_empty_slot_update(10, 20);
+        assert_eq!(
+            store
+                .store_update(updates.remove(0))
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::EmptySlot { slot: 10 })
+        );
+        assert_eq!(store.metrics.empty_slots.get(), 1);
+        assert_eq!(
+            store.storage.fetch_accumulator_messages(10).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_accumulator_conflict_policies() {
+        let accumulator_messages = |seed: u8| match generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                seed, 10, 9,
+            ))],
+            10,
+            20,
+        )
+        .remove(0)
+        {
+            Update::AccumulatorMessages(accumulator_messages) => accumulator_messages,
+            _ => panic!("Expected accumulator messages"),
+        };
+
+        for (policy, expected_seed) in [
+            (AccumulatorConflictPolicy::FirstWins, 100),
+            (AccumulatorConflictPolicy::LastWins, 150),
+            (AccumulatorConflictPolicy::RejectConflict, 100),
+        ] {
+            let (store, _receiver_tx) = setup_store_with_config(
+                10,
+                StoreConfig {
+                    accumulator_conflict_policy: policy,
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            store
+                .store_update(Update::AccumulatorMessages(accumulator_messages(100)))
+                .await
+                .unwrap();
+            // Re-delivering the same set is not a conflict.
+            store
+                .store_update(Update::AccumulatorMessages(accumulator_messages(100)))
+                .await
+                .unwrap();
+
+            let result = store
+                .store_update(Update::AccumulatorMessages(accumulator_messages(150)))
+                .await;
+            if policy == AccumulatorConflictPolicy::RejectConflict {
+                assert_eq!(
+                    result.unwrap_err().downcast_ref::<StoreError>(),
+                    Some(&StoreError::ConflictingAccumulatorMessages { slot: 10 })
+                );
+            } else {
+                assert!(result.is_ok());
+            }
+
+            assert_eq!(
+                store.storage.fetch_accumulator_messages(10).await.unwrap(),
+                Some(accumulator_messages(expected_seed))
+            );
+            assert_eq!(store.metrics.conflicting_accumulator_messages.get(), 1);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_get_price_at_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for (slot, seeds) in [(10, vec![100, 150]), (11, vec![100])] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    seeds
+                        .into_iter()
+                        .map(|seed| {
+                            Message::PriceFeedMessage(create_dummy_price_feed_message(
+                                seed,
+                                slot as i64,
+                                slot as i64 - 1,
+                            ))
+                        })
+                        .collect(),
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        let price_feed = store
+            .get_price_at_slot(PriceIdentifier::new([100; 32]), 10)
+            .await
+            .unwrap();
+        assert_eq!(price_feed.slot, 10);
+        assert_eq!(price_feed.price_feed.publish_time, 10);
+
+        assert_eq!(
+            store
+                .get_price_at_slot(PriceIdentifier::new([100; 32]), 12)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::SlotNotFound { slot: 12 })
+        );
+        assert_eq!(
+            store
+                .get_price_at_slot(PriceIdentifier::new([150; 32]), 11)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::FeedNotInSlot {
+                price_id: PriceIdentifier::new([150; 32]),
+                slot:     11,
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_bundled_update_carries_the_vaas_of_all_slots() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for slot in [10, 11] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            100,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            150,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                    ],
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        let bundle = store
+            .get_bundled_update(vec![
+                (PriceIdentifier::new([100; 32]), RequestTime::FirstAfter(10)),
+                (PriceIdentifier::new([150; 32]), RequestTime::Latest),
+            ])
+            .await
+            .unwrap();
+        let bundle =
+            pythnet_sdk::wire::from_slice::<byteorder::BE, BundledUpdateData>(bundle.as_ref())
+                .unwrap();
+        assert_eq!(bundle.update_data.len(), 2);
+
+        let mut proven = vec![];
+        for update_data in bundle.update_data {
+            let update_data: Vec<u8> = update_data.into();
+            let Proof::WormholeMerkle { vaa, updates } =
+                AccumulatorUpdateData::try_from_slice(update_data.as_ref())
+                    .unwrap()
+                    .proof;
+            let vaa: Vec<u8> = vaa.into();
+            let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+            let WormholePayload::Merkle(merkle_root) =
+                WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                    .unwrap()
+                    .payload;
+
+            assert_eq!(updates.len(), 1);
+            let message: Vec<u8> = updates[0].message.clone().into();
+            assert!(MerkleRoot::<Keccak160>::new(merkle_root.root)
+                .check(updates[0].proof.clone(), &message));
+
+            let message: Message = from_slice::<BigEndian, _>(&message).unwrap();
+            proven.push((merkle_root.slot, message.feed_id()));
+        }
+
+        proven.sort();
+        assert_eq!(proven, vec![(10, [100; 32]), (11, [150; 32])]);
+    }
+
+    #[tokio::test]
+    pub async fn test_missing_sequences_reports_gaps() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let store_sequence = |sequence: u64| {
+            let store = store.clone();
+            async move {
+                for update in generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        sequence as i64,
+                        sequence as i64 - 1,
+                    ))],
+                    sequence + 10,
+                    sequence,
+                ) {
+                    store.store_update(update).await.unwrap();
+                }
+            }
+        };
+
+        assert!(store.missing_sequences().await.is_empty());
+
+        for sequence in [1, 2, 5] {
+            store_sequence(sequence).await;
+        }
+        assert_eq!(store.missing_sequences().await, vec![3..5]);
+        assert_eq!(*store.seq_frontier.read().await, Some(2));
+
+        // Filling part of the gap advances the frontier.
+        store_sequence(3).await;
+        assert_eq!(store.missing_sequences().await, vec![4..5]);
+
+        store_sequence(4).await;
+        assert!(store.missing_sequences().await.is_empty());
+        assert_eq!(*store.seq_frontier.read().await, Some(5));
+    }
+
+    #[tokio::test]
+    pub async fn test_inconsistent_publish_times_are_flagged() {
+        for reject in [false, true] {
+            let (store, _receiver_tx) = setup_store_with_config(
+                10,
+                StoreConfig {
+                    publish_time_window: Some(Duration::from_secs(60)),
+                    reject_inconsistent_publish_times: reject,
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            // The Vaa of the slot is signed at 1000, which only the first
+            // message is consistent with.
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update_with_vaa_timestamp(
+                    vec![
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(100, 990, 989)),
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(150, 10, 9)),
+                    ],
+                    10,
+                    20,
+                    1000,
+                ),
+            )
+            .await;
+
+            assert_eq!(store.metrics.inconsistent_publish_times.get(), 1);
+            let feed_ids = store.get_price_feed_ids().await;
+            assert!(feed_ids.contains(&PriceIdentifier::new([100; 32])));
+            assert_eq!(feed_ids.contains(&PriceIdentifier::new([150; 32])), !reject);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_cancelled_subscription_ends_without_delivering() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let cancellation_token = CancellationToken::new();
+        let mut batch_rx = store
+            .subscribe_batched_cancellable(
+                vec![PriceIdentifier::new([100; 32])],
+                Duration::from_secs(1),
+                cancellation_token.clone(),
+            )
+            .await;
+
+        cancellation_token.cancel();
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        assert_eq!(batch_rx.recv().await, None);
+        assert!(store.batched_subscribers.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_get_vaa_for_request_returns_the_slot_vaa() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for slot in [10, 11] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    20 + slot,
+                ),
+            )
+            .await;
+        }
+
+        for (request_time, slot, sequence) in [
+            (RequestTime::FirstAfter(10), 10, 30),
+            (RequestTime::Latest, 11, 31),
+        ] {
+            let vaa = store
+                .get_vaa_for_request(PriceIdentifier::new([100; 32]), request_time)
+                .await
+                .unwrap();
+            let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+            assert_eq!(vaa.sequence, sequence);
+            assert_eq!(vaa.emitter_chain, Chain::Pythnet);
+            assert_eq!(
+                vaa.emitter_address,
+                Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS)
+            );
+            let WormholePayload::Merkle(merkle_root) =
+                WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                    .unwrap()
+                    .payload;
+            assert_eq!(merkle_root.slot, slot);
+        }
+
+        assert!(store
+            .get_vaa_for_request(PriceIdentifier::new([150; 32]), RequestTime::Latest)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_vaas_without_guardian_set_are_flagged() {
+        let recorder = SpanRecorder::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new(update_tx, 10);
+
+        // Both Vaas are ignored but the warning is only logged once.
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                slot,
+                20 + slot,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        assert_eq!(store.metrics.no_guardian_set.get(), 1);
+        assert!(store.storage.wormhole_merkle_states().await.is_empty());
+
+        let warnings: Vec<_> = recorder
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(level, _)| *level == tracing::Level::WARN)
+            .map(|(_, fields)| fields.get("message").cloned().unwrap_or_default())
+            .collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("no guardian set"));
+
+        // Once a guardian set is loaded the Vaas are verified again.
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 12, 11,
+            ))],
+            12,
+            22,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        assert_eq!(store.metrics.no_guardian_set.get(), 0);
+        assert_eq!(*store.latest_completed_slot.read().await, Some(12));
+    }
+
+    #[tokio::test]
+    pub async fn test_streamed_update_data_matches_the_non_streaming_update_data() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // Feed 100 is last updated in slot 10 and feed 200 in slot 11 so the
+        // update data spans two Vaas.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            30,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                200, 11, 10,
+            ))],
+            11,
+            31,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([200; 32]),
+        ];
+
+        let streamed: Vec<Vec<u8>> = store
+            .stream_price_feeds_update_data(price_ids.clone(), RequestTime::Latest)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_update_data(price_ids, RequestTime::Latest)
+            .await
+            .unwrap();
+
+        assert_eq!(streamed.len(), 2);
+        assert_eq!(
+            streamed,
+            price_feeds_with_update_data.wormhole_merkle_update_data
+        );
+
+        // Errors of the request are yielded by the stream.
+        let result: Result<Vec<Vec<u8>>> = store
+            .stream_price_feeds_update_data(
+                vec![PriceIdentifier::new([255; 32])],
+                RequestTime::Latest,
+            )
+            .try_collect()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_oversized_guardian_sets_are_rejected() {
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new_with_config(
+            update_tx,
+            10,
+            StoreConfig {
+                max_guardian_keys: 19,
+                ..Default::default()
+            },
+        );
+
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]; 19],
+                },
+            )
+            .await
+            .unwrap();
+
+        let err = store
+            .update_guardian_set(
+                1,
+                GuardianSet {
+                    keys: vec![[0; 20]; 20],
+                },
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::TooManyGuardianKeys {
+                index: 1,
+                keys:  20,
+                limit: 19,
+            })
+        );
+        assert_eq!(store.metrics.rejected_guardian_sets.get(), 1);
+        assert_eq!(
+            store.guardian_set.read().await.keys().collect::<Vec<_>>(),
+            vec![&0]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_anomalous_price_jumps_are_flagged_as_suspect() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_deviation_ratio: Some(0.5),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut price_feed_message = create_dummy_price_feed_message(100, 10, 9);
+        for (slot, price) in [(10, 100), (11, 120), (12, 1000)] {
+            price_feed_message.price = price;
+            price_feed_message.publish_time = slot as i64;
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(price_feed_message)],
+                slot,
+                20 + slot,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        let suspect_at = |slot: Slot| {
+            let store = store.clone();
+            async move {
+                store
+                    .get_price_feeds_with_update_data(
+                        vec![PriceIdentifier::new([100; 32])],
+                        RequestTime::FirstAfter(slot as i64),
+                    )
+                    .await
+                    .unwrap()
+                    .price_feeds[0]
+                    .suspect
+            }
+        };
+
+        // The first price has nothing to deviate from and a 20% move is
+        // within the allowed ratio.
+        assert!(!suspect_at(10).await);
+        assert!(!suspect_at(11).await);
+        // A jump from 120 to 1000 is flagged but still served.
+        assert!(suspect_at(12).await);
+    }
+
+    #[tokio::test]
+    pub async fn test_feed_groups_are_fetched_by_name() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(250, 10, 9)),
+            ],
+            10,
+            30,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let price_ids = vec![
+            PriceIdentifier::new([200; 32]),
+            PriceIdentifier::new([100; 32]),
+        ];
+        store
+            .define_feed_group("index".to_string(), price_ids.clone())
+            .await;
+
+        let group = store.get_group("index", RequestTime::Latest).await.unwrap();
+        assert_eq!(
+            group
+                .price_feeds
+                .iter()
+                .map(|price_feed| PriceIdentifier::new(price_feed.price_feed.feed_id))
+                .collect::<Vec<_>>(),
+            price_ids
+        );
+        assert_eq!(
+            group.wormhole_merkle_update_data,
+            store
+                .get_price_feeds_with_update_data(price_ids, RequestTime::Latest)
+                .await
+                .unwrap()
+                .wormhole_merkle_update_data
+        );
+
+        let err = store
+            .get_group("unknown", RequestTime::Latest)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::UnknownFeedGroup {
+                name: "unknown".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_subscription_flags_updates_not_linking_to_the_previous_one() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut batch_rx = store
+            .subscribe_batched(
+                vec![PriceIdentifier::new([100; 32])],
+                Duration::from_secs(1),
+            )
+            .await;
+
+        // The update at 13 follows an update at 12 that is never received.
+        let mut links_previous = Vec::new();
+        for (slot, publish_time, prev_publish_time) in [(10, 10, 9), (11, 11, 10), (13, 13, 12)] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    publish_time,
+                    prev_publish_time,
+                ))],
+                slot,
+                20 + slot,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+
+            let batch = batch_rx.recv().await.unwrap();
+            assert_eq!(batch.len(), 1);
+            links_previous.push(batch[0].links_previous);
+        }
+
+        assert_eq!(links_previous, vec![true, true, false]);
+    }
+
+    #[tokio::test]
+    pub async fn test_slot_summary_lists_the_feed_count_of_each_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // Stored out of order to check the summary is in slot order.
+        for (slot, seeds) in [(12, vec![100]), (10, vec![100, 200, 250])] {
+            for update in generate_update(
+                seeds
+                    .into_iter()
+                    .map(|seed| {
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            seed,
+                            slot as i64,
+                            slot as i64 - 1,
+                        ))
+                    })
+                    .collect(),
+                slot,
+                20 + slot,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+        for update in generate_empty_slot_update(11, 31) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as UnixTimestamp;
+        assert_eq!(
+            store.slot_summary().await,
+            vec![
+                SlotSummary {
+                    slot:        10,
+                    feed_count:  3,
+                    received_at: Some(received_at),
+                },
+                SlotSummary {
+                    slot:        11,
+                    feed_count:  0,
+                    received_at: None,
+                },
+                SlotSummary {
+                    slot:        12,
+                    feed_count:  1,
+                    received_at: Some(received_at),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_vaas_older_than_the_max_age_are_rejected() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_vaa_age: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // Make sure the clock is far enough from the epoch for an old Vaa.
+        MockClock::advance_system_time(Duration::from_secs(10));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+
+        let price_feed_message =
+            Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9));
+
+        let updates = generate_update_with_vaa_timestamp(vec![price_feed_message], 10, 30, now);
+        for update in updates {
+            store.store_update(update).await.unwrap();
+        }
+        assert_eq!(*store.latest_completed_slot.read().await, Some(10));
+
+        let mut updates =
+            generate_update_with_vaa_timestamp(vec![price_feed_message], 11, 31, now - 10);
+        let Update::Vaa(vaa) = updates.remove(1) else {
+            panic!("Expected a Vaa update");
+        };
+        let err = store.store_update(Update::Vaa(vaa)).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::StaleVaa {
+                sequence:  31,
+                timestamp: now - 10,
+                max_age:   Duration::from_secs(5),
+            })
+        );
+        assert_eq!(store.metrics.stale_vaas.get(), 1);
+        assert_eq!(store.storage.wormhole_merkle_state_slots().await, vec![10]);
+    }
+
+    #[tokio::test]
+    pub async fn test_unverified_accumulator_messages_are_only_served_when_allowed() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        // Feed 100 is verified at slot 10 while feed 200 is only archived.
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            30,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        let Update::AccumulatorMessages(accumulator_messages) = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            30,
+        )
+        .remove(0) else {
+            panic!("Expected accumulator messages");
+        };
+        store
+            .store_unverified_accumulator(accumulator_messages)
+            .await
+            .unwrap();
+
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([200; 32]),
+        ];
+
+        // The unverified feed is not served by default.
+        assert!(store
+            .get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest)
+            .await
+            .is_err());
+
+        let price_feeds_with_update_data = store
+            .get_price_feeds_with_options(
+                price_ids,
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    allow_unverified: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // The verified update takes precedence over the archived one.
+        let price_feeds = &price_feeds_with_update_data.price_feeds;
+        assert_eq!(price_feeds.len(), 2);
+        assert!(!price_feeds[0].unverified);
+        assert!(!price_feeds[0].wormhole_merkle_update_data.is_empty());
+        assert!(price_feeds[1].unverified);
+        assert_eq!(
+            price_feeds[1].price_feed,
+            create_dummy_price_feed_message(200, 10, 9)
+        );
+        assert!(price_feeds[1].wormhole_merkle_update_data.is_empty());
+
+        // Only the verified feed is proven by the update data.
+        assert_eq!(
+            price_feeds_with_update_data
+                .wormhole_merkle_update_data
+                .len(),
+            1
+        );
+        assert!(store
+            .unverified_storage
+            .fetch_message_states(
+                vec![[100; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_unverified_accumulator_messages_are_admitted_as_verified_ones() {
+        // The feed of seed 100 is in shard 0 and the ones of seeds 101 and
+        // 103 in shard 1.
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                shard: Some(ShardConfig {
+                    total_shards: 2,
+                    shard_index:  1,
+                }),
+                inverted_publish_time_policy: InvertedPublishTimePolicy::Reject,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let Update::AccumulatorMessages(accumulator_messages) = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(101, 10, 11)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(103, 10, 9)),
+            ],
+            10,
+            30,
+        )
+        .remove(0) else {
+            panic!("Expected accumulator messages");
+        };
+        store
+            .store_unverified_accumulator(accumulator_messages)
+            .await
+            .unwrap();
+
+        // Only the message in the shard with ordered publish times is stored.
+        assert_eq!(
+            store
+                .unverified_storage
+                .fetch_available_message_states(
+                    vec![[100; 32], [101; 32], [103; 32]],
+                    RequestTime::Latest,
+                    MessageStateFilter::Only(MessageType::PriceFeedMessage),
+                )
+                .await
+                .iter()
+                .map(|message_state| message_state.message.feed_id())
+                .collect::<Vec<_>>(),
+            vec![[103; 32]]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_completion_stream_yields_the_completed_slots() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let completion_stream = store.completion_stream();
+
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                20 + slot,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        assert_eq!(
+            completion_stream.take(2).collect::<Vec<_>>().await,
+            vec![CompletedSlot { slot: 10 }, CompletedSlot { slot: 11 }]
+        );
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum RecordedEvent {
+        VaaReceived,
+        VaaIgnored(VaaIgnoreReason),
+        SlotCompleted(Slot, usize),
+    }
+
+    /// A metrics recorder that records the events in order.
+    #[derive(Clone, Default)]
+    struct MockMetricsRecorder {
+        events: Arc<Mutex<Vec<RecordedEvent>>>,
+    }
+
+    impl StoreMetricsRecorder for MockMetricsRecorder {
+        fn record_vaa_received(&self) {
+            self.events.lock().unwrap().push(RecordedEvent::VaaReceived);
+        }
+
+        fn record_vaa_ignored(&self, reason: VaaIgnoreReason) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(RecordedEvent::VaaIgnored(reason));
+        }
+
+        fn record_slot_completed(&self, slot: Slot, num_messages: usize) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(RecordedEvent::SlotCompleted(slot, num_messages));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_metrics_recorder_records_the_ingestion_events() {
+        let recorder = MockMetricsRecorder::default();
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = StoreBuilder::new(update_tx, 10)
+            .with_metrics_recorder(Box::new(recorder.clone()))
+            .build()
+            .unwrap();
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            30,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        // A Vaa signed by an unknown guardian set fails verification.
+        let Update::Vaa(vaa_bytes) = generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 11, 10,
+            ))],
+            11,
+            31,
+        )
+        .remove(1) else {
+            panic!("Expected a Vaa update");
+        };
+        let mut vaa =
+            serde_wormhole::from_slice::<Vaa<&serde_wormhole::RawMessage>>(&vaa_bytes).unwrap();
+        vaa.guardian_set_index = 5;
+        store
+            .store_update(Update::Vaa(serde_wormhole::to_vec(&vaa).unwrap()))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                RecordedEvent::VaaReceived,
+                RecordedEvent::SlotCompleted(10, 2),
+                RecordedEvent::VaaReceived,
+                RecordedEvent::VaaIgnored(VaaIgnoreReason::Invalid),
+            ]
+        );
+
+        // The same events are recorded in the Prometheus metrics.
+        let encoded = store.metrics().encode().unwrap();
+        assert!(encoded.contains("hermes_store_received_vaas_total 2\n"));
+        assert!(encoded.contains("hermes_store_ignored_vaas_total{reason=\"invalid\"} 1\n"));
+        assert_eq!(store.metrics.completed_slots.get(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_store_builder_combines_the_optional_collaborators() {
+        let path = std::env::temp_dir().join(format!(
+            "hermes-test-store-builder-watermark-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let recorder = MockMetricsRecorder::default();
+        let evicted_slots = Arc::new(Mutex::new(Vec::new()));
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = StoreBuilder::new(update_tx, 1)
+            .with_metrics_recorder(Box::new(recorder.clone()))
+            .with_eviction_callback({
+                let evicted_slots = evicted_slots.clone();
+                Box::new(move |slot, _| evicted_slots.lock().unwrap().push(slot))
+            })
+            .with_watermark_store(Box::new(FileSequenceWatermarkStore::new(&path)))
+            .build()
+            .unwrap();
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        for slot in [10, 11] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                slot + 10,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                RecordedEvent::VaaReceived,
+                RecordedEvent::SlotCompleted(10, 1),
+                RecordedEvent::VaaReceived,
+                RecordedEvent::SlotCompleted(11, 1),
+            ]
+        );
+        assert_eq!(*evicted_slots.lock().unwrap(), vec![10]);
+        assert_eq!(
+            FileSequenceWatermarkStore::new(&path).load().unwrap(),
+            Some(21)
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    pub async fn test_slot_sequence_is_the_sequence_of_the_slot_vaa() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        assert_eq!(store.slot_sequence(10).await, Some(20));
+        assert_eq!(store.slot_sequence(11).await, None);
+    }
+
+    #[tokio::test]
+    pub async fn test_vaas_are_used_once_delivered_by_the_source_quorum() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                source_quorum: 2,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let mut updates = generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            30,
+        );
+        let Update::Vaa(vaa) = updates.remove(1) else {
+            panic!("Expected a Vaa update");
+        };
+        store
+            .store_update_from_source(updates.remove(0), "spy-a")
+            .await
+            .unwrap();
+
+        // A single source, even repeating itself, does not reach the quorum.
+        for _ in 0..2 {
+            store
+                .store_update_from_source(Update::Vaa(vaa.clone()), "spy-a")
+                .await
+                .unwrap();
+            assert_eq!(*store.latest_completed_slot.read().await, None);
+            assert!(store.get_price_feed_ids().await.is_empty());
+        }
+
+        store
+            .store_update_from_source(Update::Vaa(vaa), "spy-b")
+            .await
+            .unwrap();
+        assert_eq!(*store.latest_completed_slot.read().await, Some(10));
+        assert!(store.vaa_sources.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_get_changes_since_only_returns_feeds_updated_after_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    200, 11, 10,
+                ))],
+                15,
+                21,
+            ),
+        )
+        .await;
+
+        let changed_feed_ids = |price_feeds: PriceFeedsWithUpdateData| {
+            price_feeds
+                .price_feeds
+                .iter()
+                .map(|price_feed| price_feed.price_feed.feed_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            changed_feed_ids(store.get_changes_since(5, None).await.unwrap()),
+            vec![[100; 32], [200; 32]]
+        );
+        assert_eq!(
+            changed_feed_ids(store.get_changes_since(10, None).await.unwrap()),
+            vec![[200; 32]]
+        );
+        assert!(changed_feed_ids(store.get_changes_since(15, None).await.unwrap()).is_empty());
+        assert!(changed_feed_ids(
+            store
+                .get_changes_since(10, Some(vec![PriceIdentifier::new([100; 32])]))
+                .await
+                .unwrap()
+        )
+        .is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn test_serving_data_verified_by_retired_guardian_set_is_configurable() {
+        for serve_under_retired_set in [true, false] {
+            let (store, _receiver_tx) = setup_store_with_config(
+                10,
+                StoreConfig {
+                    retired_set_grace_period: Duration::from_secs(10),
+                    serve_under_retired_set,
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            // The update is signed by the guardian set 0.
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100, 10, 9,
+                    ))],
+                    10,
+                    20,
+                ),
+            )
+            .await;
+
+            store
+                .update_guardian_set(
+                    1,
+                    GuardianSet {
+                        keys: vec![[1; 20]],
+                    },
+                )
+                .await
+                .unwrap();
+            let price_ids = vec![PriceIdentifier::new([100; 32])];
+
+            // The retired set is still within its grace period.
+            assert!(store
+                .get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest)
+                .await
+                .is_ok());
+
+            MockClock::advance_system_time(Duration::from_secs(11));
+
+            let result = store
+                .get_price_feeds_with_update_data(price_ids, RequestTime::Latest)
+                .await;
+            if serve_under_retired_set {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(
+                    result.unwrap_err().downcast_ref::<StoreError>(),
+                    Some(&StoreError::RetiredGuardianSet {
+                        price_id:           PriceIdentifier::new([100; 32]),
+                        guardian_set_index: 0,
+                    })
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_guardian_set_retirement_follows_the_recorded_retirement_time() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                retired_set_grace_period: Duration::from_secs(10),
+                serve_under_retired_set: false,
+                ..Default::default()
             },
-            hashers::keccak256_160::Keccak160,
-            messages::{
-                Message,
-                PriceFeedMessage,
+        )
+        .await;
+
+        // The update is signed by the guardian set 0.
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            ),
+        )
+        .await;
+        let price_ids = vec![PriceIdentifier::new([100; 32])];
+        let query =
+            || store.get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest);
+        let guardian_set_0 = store.guardian_set.read().await.get(&0).unwrap().clone();
+        let guardian_set_1 = GuardianSet {
+            keys: vec![[1; 20]],
+        };
+
+        // The set 0 is inserted again with the set 1 as on a restart, after
+        // being replaced for longer than the grace period.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as UnixTimestamp;
+        store
+            .update_guardian_set(1, guardian_set_1.clone())
+            .await
+            .unwrap();
+        store
+            .update_retired_guardian_set(0, guardian_set_0.clone(), now - 11)
+            .await
+            .unwrap();
+        assert_eq!(
+            query().await.unwrap_err().downcast_ref::<StoreError>(),
+            Some(&StoreError::RetiredGuardianSet {
+                price_id:           PriceIdentifier::new([100; 32]),
+                guardian_set_index: 0,
+            })
+        );
+
+        // Updating a set doesn't restart the retirement of the others.
+        store
+            .update_guardian_set(1, guardian_set_1.clone())
+            .await
+            .unwrap();
+        assert!(query().await.is_err());
+
+        // The set 0 is active again once it is the newest set.
+        store
+            .replace_guardian_sets(BTreeMap::from([(0, guardian_set_0.clone())]))
+            .await
+            .unwrap();
+        assert!(query().await.is_ok());
+
+        // And it is only retired again from its next replacement on.
+        store
+            .replace_guardian_sets(BTreeMap::from([(0, guardian_set_0), (1, guardian_set_1)]))
+            .await
+            .unwrap();
+        assert!(query().await.is_ok());
+        MockClock::advance_system_time(Duration::from_secs(11));
+        assert!(query().await.is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_reordered_accumulator_messages_are_rejected() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut updates = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        );
+        let Update::AccumulatorMessages(accumulator_messages) = &mut updates[0] else {
+            panic!("Expected the accumulator messages first");
+        };
+        accumulator_messages.raw_messages.reverse();
+
+        let mut updates = updates.into_iter();
+        store.store_update(updates.next().unwrap()).await.unwrap();
+        let err = store
+            .store_update(updates.next().unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::MerkleRootMismatch { slot: 10 })
+        );
+
+        assert!(store
+            .get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_time_since_start_ready_records_cold_start_time() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        MockClock::advance(Duration::from_secs(5));
+        assert_eq!(store.time_since_start_ready(), None);
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert_eq!(store.time_since_start_ready(), Some(Duration::from_secs(5)));
+
+        // Later slots do not move the first ready time.
+        MockClock::advance(Duration::from_secs(1));
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 11, 10,
+            ))],
+            11,
+            21,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+        assert_eq!(store.time_since_start_ready(), Some(Duration::from_secs(5)));
+    }
+
+    #[tokio::test]
+    pub async fn test_slot_dedup_ignores_vaas_of_finalized_slots() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            2,
+            StoreConfig {
+                vaa_dedup_strategy: VaaDedupStrategy::Slot,
+                ..Default::default()
             },
-            wire::v1::{
-                AccumulatorUpdateData,
-                Proof,
-                WormholeMerkleRoot,
+        )
+        .await;
+
+        for (slot, sequence) in [(10, 20), (11, 21), (12, 22)] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                sequence,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+        }
+        assert_eq!(store.metrics.completed_slots.get(), 3);
+        assert_eq!(store.metrics.observed_vaas.get(), 3);
+
+        // The Vaas are resigned with new sequences, for the evicted slot 10
+        // and for the retained slot 12.
+        for (slot, sequence) in [(10, 30), (12, 31)] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                sequence,
+            )
+            .into_iter()
+            .rev()
+            {
+                store.store_update(update).await.unwrap();
+            }
+        }
+
+        assert_eq!(store.metrics.completed_slots.get(), 3);
+        assert_eq!(store.metrics.observed_vaas.get(), 3);
+
+        // A Vaa whose payload is not a wormhole message is ignored.
+        let vaa = Vaa {
+            nonce:              0,
+            version:            0,
+            sequence:           32,
+            timestamp:          0,
+            signatures:         vec![],
+            guardian_set_index: 0,
+            emitter_chain:      Chain::Pythnet,
+            emitter_address:    Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
+            consistency_level:  0,
+            payload:            RawMessage::new(&[0xff; 4]),
+        };
+        store
+            .store_update(Update::Vaa(serde_wormhole::to_vec(&vaa).unwrap()))
+            .await
+            .unwrap();
+        assert_eq!(store.metrics.observed_vaas.get(), 3);
+    }
+
+    #[tokio::test]
+    pub async fn test_merkle_tree_of_slot_is_reused_across_queries() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let messages = (1..=5)
+            .map(|seed| {
+                Message::PriceFeedMessage(create_dummy_price_feed_message(seed * 10, 10, 9))
+            })
+            .collect();
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(messages, 10, 20))
+            .await;
+        assert_eq!(store.metrics.merkle_tree_builds.get(), 1);
+
+        for price_id in [[10; 32], [40; 32]] {
+            store
+                .get_price_feeds_with_options(
+                    vec![PriceIdentifier::new(price_id)],
+                    RequestTime::Latest,
+                    PriceFeedsQueryOptions {
+                        multiproof: true,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        // Only the tree built when the slot was completed is used.
+        assert_eq!(store.metrics.merkle_tree_builds.get(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn test_notify_on_feed_resolves_with_first_update() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut receiver = store.notify_on_feed(PriceIdentifier::new([100; 32])).await;
+        assert!(receiver.try_recv().is_err());
+
+        for update in generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let price_feed_update = receiver.await.unwrap();
+        assert_eq!(
+            price_feed_update.price_feed,
+            create_dummy_price_feed_message(100, 10, 9)
+        );
+        assert_eq!(price_feed_update.slot, 10);
+
+        // A feed that is already stored resolves right away.
+        let mut receiver = store.notify_on_feed(PriceIdentifier::new([100; 32])).await;
+        assert_eq!(receiver.try_recv().unwrap().slot, 10);
+    }
+
+    #[tokio::test]
+    pub async fn test_sinks_only_receive_feeds_with_changed_trigger_fields() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                sink_change_triggers: ChangeTriggers {
+                    price: true,
+                    publish_time: false,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        let sink = Arc::new(RecordingSink {
+            emitted: Mutex::new(vec![]),
+        });
+        store.add_update_sink(sink.clone()).await;
+
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+            ],
+            10,
+            20,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        // Only the price of the feed 200 changes in the next slot.
+        for update in generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 11, 10)),
+                Message::PriceFeedMessage(PriceFeedMessage {
+                    price: 201,
+                    ..create_dummy_price_feed_message(200, 11, 10)
+                }),
+            ],
+            11,
+            21,
+        ) {
+            store.store_update(update).await.unwrap();
+        }
+
+        let emitted = sink.emitted.lock().unwrap();
+        assert_eq!(emitted.len(), 2);
+        // Feeds without a previous update are all part of the changed set.
+        assert_eq!(emitted[0].len(), 2);
+        assert_eq!(
+            emitted[1]
+                .iter()
+                .map(|state| state.message.feed_id())
+                .collect::<Vec<_>>(),
+            vec![[200; 32]]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_update_data_larger_than_limit_lists_the_feeds() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+        let price_ids = vec![
+            PriceIdentifier::new([100; 32]),
+            PriceIdentifier::new([200; 32]),
+        ];
+
+        let update_data_len = store
+            .get_price_feeds_with_update_data(price_ids.clone(), RequestTime::Latest)
+            .await
+            .unwrap()
+            .wormhole_merkle_update_data[0]
+            .len();
+
+        let query_with_limit = |max_update_bytes| {
+            store.get_price_feeds_with_options(
+                price_ids.clone(),
+                RequestTime::Latest,
+                PriceFeedsQueryOptions {
+                    max_update_bytes: Some(max_update_bytes),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(query_with_limit(update_data_len).await.is_ok());
+        let err = query_with_limit(update_data_len - 1).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::UpdateTooLarge {
+                price_ids: price_ids.clone(),
+                limit:     update_data_len - 1,
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_split_vaa_of_slot_recombines_into_stored_vaa() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let updates = generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                100, 10, 9,
+            ))],
+            10,
+            20,
+        );
+        let Update::Vaa(vaa_bytes) = &updates[1] else {
+            panic!("Expected the Vaa last");
+        };
+        let vaa_bytes = vaa_bytes.clone();
+        store_multiple_concurrent_valid_updates(store.clone(), updates).await;
+
+        let (header, body) = store.get_split_vaa(10).await.unwrap();
+        assert_eq!(header.guardian_set_index, 0);
+        assert_eq!(crate::store::wormhole::join_vaa(&header, &body), vaa_bytes);
+
+        assert_eq!(
+            store
+                .get_split_vaa(11)
+                .await
+                .unwrap_err()
+                .downcast_ref::<StoreError>(),
+            Some(&StoreError::SlotNotFound { slot: 11 })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_update_data_by_slot_keys_each_blob_with_its_slot() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    250, 11, 10,
+                ))],
+                11,
+                21,
+            ),
+        )
+        .await;
+
+        let update_data = store
+            .get_price_feeds_with_update_data_by_slot(
+                vec![
+                    PriceIdentifier::new([250; 32]),
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            update_data
+                .iter()
+                .map(|(slot, _)| *slot)
+                .collect::<Vec<_>>(),
+            vec![10, 11]
+        );
+        for (slot, update_data) in update_data {
+            let update_data = AccumulatorUpdateData::try_from_slice(update_data.as_ref()).unwrap();
+            let Proof::WormholeMerkle { updates, .. } = update_data.proof;
+            assert_eq!(updates.len(), if slot == 10 { 2 } else { 1 });
+        }
+
+        // The messages of a slot proven by different Vaas are not silently
+        // reduced to the update data of one of them.
+        store.storage.mutate_message_state(
+            &MessageStateKey {
+                feed_id: [200; 32],
+                type_:   MessageType::PriceFeedMessage,
+            },
+            &MessageStateTime {
+                publish_time: 10,
+                slot:         10,
             },
-        },
-        rand::seq::SliceRandom,
-        serde_wormhole::RawMessage,
-        tokio::sync::mpsc::Receiver,
-    };
+            |message_state| {
+                message_state.proof_set.wormhole_merkle_proof.vaa.push(0);
+            },
+        );
+        assert!(store
+            .get_price_feeds_with_update_data_by_slot(
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .is_err());
+    }
 
-    /// Generate list of updates for the given list of messages at a given slot with given sequence
-    ///
-    /// Sequence in Vaas is used to filter duplicate messages (as by wormhole design there is only
-    /// one message per sequence)
-    pub fn generate_update(messages: Vec<Message>, slot: Slot, sequence: u64) -> Vec<Update> {
-        let mut updates = Vec::new();
+    #[tokio::test]
+    pub async fn test_prime_feeds_reports_available_slots_and_feeds() {
+        let (store, _receiver_tx) = setup_store(10).await;
 
-        // Accumulator messages
-        let accumulator_messages = AccumulatorMessages {
-            slot,
-            raw_messages: messages
-                .iter()
-                .map(|message| pythnet_sdk::wire::to_vec::<_, byteorder::BE>(message).unwrap())
-                .collect(),
-            magic: [0; 4],
-            ring_size: 100,
+        let mut updates = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+            ],
+            10,
+            20,
+        );
+        updates.extend(generate_update(
+            vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                150, 11, 10,
+            ))],
+            11,
+            21,
+        ));
+        // The Vaa of slot 12 is missing, so the slot is not available.
+        updates.push(
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    50, 12, 11,
+                ))],
+                12,
+                22,
+            )
+            .remove(0),
+        );
+
+        let report = store.prime_feeds(updates).await.unwrap();
+        assert_eq!(report.slots, vec![10, 11]);
+        assert_eq!(
+            report.price_ids,
+            vec![
+                PriceIdentifier::new([100; 32]),
+                PriceIdentifier::new([150; 32]),
+                PriceIdentifier::new([200; 32]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_updates_beyond_concurrency_limit_are_overloaded() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                max_concurrent_updates: Some(2),
+                update_permit_timeout: Duration::from_millis(10),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // Saturate the limit as if two updates were in flight.
+        let permits = store
+            .update_permits
+            .as_ref()
+            .unwrap()
+            .acquire_many(2)
+            .await
+            .unwrap();
+
+        let updates = || {
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            )
         };
-        updates.push(Update::AccumulatorMessages(accumulator_messages.clone()));
+        for update in updates() {
+            let err = store.store_update(update).await.unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<StoreError>(),
+                Some(&StoreError::Overloaded {
+                    max_concurrent_updates: 2,
+                })
+            );
+        }
 
-        // Wormhole merkle update
-        let merkle_tree = MerkleTree::<Keccak160>::from_set(
-            accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
+        drop(permits);
+        store_multiple_concurrent_valid_updates(store.clone(), updates()).await;
+    }
+
+    #[tokio::test]
+    pub async fn test_structured_proof_walks_from_leaf_to_root() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let messages = (1..=5)
+            .map(|seed| {
+                Message::PriceFeedMessage(create_dummy_price_feed_message(seed * 10, 10, 9))
+            })
+            .collect();
+        store_multiple_concurrent_valid_updates(store.clone(), generate_update(messages, 10, 20))
+            .await;
+
+        let structured_proof = store
+            .get_structured_proof(PriceIdentifier::new([40; 32]), RequestTime::Latest)
+            .await
+            .unwrap();
+
+        let root = concatenate_structured_proof(&structured_proof);
+        assert_eq!(root, structured_proof.root);
+        assert_eq!(
+            Some(structured_proof.root),
+            store
+                .storage
+                .fetch_wormhole_merkle_state(10)
+                .await
+                .unwrap()
+                .map(|wormhole_merkle_state| wormhole_merkle_state.root.root)
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_inverted_publish_times_follow_configured_policy() {
+        for policy in [
+            InvertedPublishTimePolicy::Accept,
+            InvertedPublishTimePolicy::Reject,
+            InvertedPublishTimePolicy::Clamp,
+        ] {
+            let (store, _receiver_tx) = setup_store_with_config(
+                10,
+                StoreConfig {
+                    inverted_publish_time_policy: policy,
+                    ..Default::default()
+                },
+            )
+            .await;
+
+            for update in generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 12)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ) {
+                store.store_update(update).await.unwrap();
+            }
+            assert_eq!(store.metrics.inverted_publish_times.get(), 1);
+
+            let price_feed = store
+                .get_price_feeds_with_options(
+                    vec![PriceIdentifier::new([100; 32])],
+                    RequestTime::Latest,
+                    PriceFeedsQueryOptions {
+                        partial: true,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap()
+                .price_feeds
+                .pop();
+            match policy {
+                InvertedPublishTimePolicy::Accept => {
+                    let price_feed = price_feed.unwrap();
+                    assert_eq!(price_feed.price_feed.prev_publish_time, 12);
+                    assert!(!price_feed.suspect);
+                }
+                InvertedPublishTimePolicy::Reject => assert!(price_feed.is_none()),
+                InvertedPublishTimePolicy::Clamp => {
+                    let price_feed = price_feed.unwrap();
+                    assert_eq!(price_feed.price_feed.prev_publish_time, 10);
+                    assert!(!price_feed.suspect);
+                    assert_eq!(store.metrics.clamped_publish_times.get(), 1);
+
+                    // The update data carries the message as proven, so it
+                    // still verifies against the root signed in the Vaa.
+                    let update_data = AccumulatorUpdateData::try_from_slice(
+                        price_feed.wormhole_merkle_update_data.as_ref(),
+                    )
+                    .unwrap();
+                    let Proof::WormholeMerkle { vaa, updates } = update_data.proof;
+                    let vaa: Vec<u8> = vaa.into();
+                    let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+                    let WormholePayload::Merkle(merkle_root) =
+                        WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                            .unwrap()
+                            .payload;
+                    let root = MerkleRoot::<Keccak160>::new(merkle_root.root);
+                    for update in updates {
+                        let message: Vec<u8> = update.message.into();
+                        assert!(root.check(update.proof, &message));
+                    }
+                    assert!(store.deep_health_check().await.is_ok());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_price_feed_updates_are_cached_until_new_slot_or_ttl() {
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                price_feed_update_cache_ttl: Some(Duration::from_secs(10)),
+                ..Default::default()
+            },
         )
-        .unwrap();
+        .await;
+        let store_slot = |slot: Slot| {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
+            )
+        };
+        let query = || {
+            store.get_price_feeds_with_update_data(
+                vec![PriceIdentifier::new([100; 32])],
+                RequestTime::Latest,
+            )
+        };
 
-        let wormhole_message = WormholeMessage::new(WormholePayload::Merkle(WormholeMerkleRoot {
-            slot,
-            ring_size: 100,
-            root: merkle_tree.root.as_bytes().try_into().unwrap(),
+        store_slot(10).await;
+        let first = query().await.unwrap();
+        let second = query().await.unwrap();
+        assert_eq!(first.price_feeds, second.price_feeds);
+        assert_eq!(store.metrics.price_feed_update_builds.get(), 1);
+
+        // A new slot of the feed invalidates its cached update.
+        store_slot(11).await;
+        assert_eq!(query().await.unwrap().price_feeds[0].slot, 11);
+        assert_eq!(store.metrics.price_feed_update_builds.get(), 2);
+
+        // So does the expiry of the TTL.
+        MockClock::advance(Duration::from_secs(11));
+        query().await.unwrap();
+        assert_eq!(store.metrics.price_feed_update_builds.get(), 3);
+    }
+
+    #[tokio::test]
+    pub async fn test_layered_price_feeds_verify_layer_by_layer() {
+        let (store, _receiver_tx) = setup_store(10).await;
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(10, 20, 19)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(20, 20, 19)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(30, 20, 19)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+
+        let layered_price_feeds = store
+            .get_price_feeds_layered(
+                vec![
+                    PriceIdentifier::new([10; 32]),
+                    PriceIdentifier::new([30; 32]),
+                ],
+                RequestTime::Latest,
+            )
+            .await
+            .unwrap();
+        assert_eq!(layered_price_feeds.len(), 2);
+
+        for layered_price_feed in layered_price_feeds {
+            // The accumulator proof takes the message to the wormhole root.
+            assert!(
+                MerkleRoot::<Keccak160>::new(layered_price_feed.wormhole_root).check(
+                    layered_price_feed.accumulator_proof.clone(),
+                    layered_price_feed.message.as_ref(),
+                )
+            );
+
+            // The Vaa signs the wormhole root.
+            let vaa: Vaa<&RawMessage> =
+                serde_wormhole::from_slice(layered_price_feed.vaa.as_ref()).unwrap();
+            let WormholePayload::Merkle(merkle_root) =
+                WormholeMessage::try_from_bytes(vaa.payload.as_ref())
+                    .unwrap()
+                    .payload;
+            assert_eq!(merkle_root.root, layered_price_feed.wormhole_root);
+            assert_eq!(merkle_root.slot, 10);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_bulk_ingestion_signals_once() {
+        let (store, mut update_rx) = setup_store(10).await;
+
+        let updates = (1..=1500)
+            .flat_map(|slot| {
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                )
+            })
+            .collect::<Vec<_>>();
+        // More slots than the capacity of the signal channel are stored
+        // without draining it.
+        store
+            .store_updates_in_bulk(updates, BulkSignalPolicy::Once)
+            .await
+            .unwrap();
+
+        assert_eq!(update_rx.recv().await, Some(()));
+        assert!(update_rx.try_recv().is_err());
+        assert_eq!(
+            store
+                .get_price_feeds_with_update_data(
+                    vec![PriceIdentifier::new([100; 32])],
+                    RequestTime::Latest,
+                )
+                .await
+                .unwrap()
+                .price_feeds[0]
+                .slot,
+            1500
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_feed_history_returns_all_retained_updates_in_slot_order() {
+        let (store, _receiver_tx) = setup_store(100).await;
+
+        for slot in 1..=50 {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            100,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            200,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                    ],
+                    slot,
+                    slot + 10,
+                ),
+            )
+            .await;
+        }
+
+        let history = store
+            .get_feed_history(PriceIdentifier::new([100; 32]))
+            .await
+            .unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|price_feed_update| price_feed_update.slot)
+                .collect::<Vec<_>>(),
+            (1..=50).collect::<Vec<_>>()
+        );
+        assert!(history.iter().all(|price_feed_update| {
+            price_feed_update.price_feed.feed_id == [100; 32]
+                && !price_feed_update.wormhole_merkle_update_data.is_empty()
         }));
 
-        let wormhole_message =
-            pythnet_sdk::wire::to_vec::<_, byteorder::BE>(&wormhole_message).unwrap();
+        assert!(store
+            .get_feed_history(PriceIdentifier::new([50; 32]))
+            .await
+            .unwrap()
+            .is_empty());
+    }
 
-        let vaa = Vaa {
-            nonce: 0,
-            version: 0,
-            sequence,
-            timestamp: 0,
-            signatures: vec![],    // We are bypassing signature check now
-            guardian_set_index: 0, // We are bypassing signature check now
-            emitter_chain: Chain::Pythnet,
-            emitter_address: Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
-            consistency_level: 0,
-            payload: serde_wormhole::RawMessage::new(wormhole_message.as_ref()),
+    #[tokio::test]
+    pub async fn test_messages_without_proof_are_skipped_and_the_rest_stored() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        let mut updates = generate_update(
+            vec![
+                Message::PriceFeedMessage(create_dummy_price_feed_message(10, 20, 19)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(20, 20, 19)),
+                Message::PriceFeedMessage(create_dummy_price_feed_message(30, 20, 19)),
+            ],
+            10,
+            20,
+        );
+        let Some(Update::Vaa(vaa)) = updates.pop() else {
+            panic!("Missing Vaa update");
+        };
+        let Some(Update::AccumulatorMessages(accumulator_messages)) = updates.pop() else {
+            panic!("Missing accumulator messages update");
+        };
+        let vaa_message: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
+        let WormholePayload::Merkle(root) =
+            WormholeMessage::try_from_bytes(vaa_message.payload.as_ref())
+                .unwrap()
+                .payload;
+        let wormhole_merkle_state = WormholeMerkleState {
+            root,
+            vaa,
+            sequence: 20,
+            guardian_set_index: 0,
         };
 
-        updates.push(Update::Vaa(serde_wormhole::to_vec(&vaa).unwrap()));
+        // Simulate a proof construction bug losing the proof of the last
+        // message.
+        let (_, mut proofs) =
+            construct_message_states_proofs(&accumulator_messages, &wormhole_merkle_state).unwrap();
+        proofs.pop();
 
-        updates
+        let message_states = store
+            .build_message_states_with_proofs(accumulator_messages, &wormhole_merkle_state, proofs)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            message_states
+                .iter()
+                .map(|message_state| message_state.message.feed_id())
+                .collect::<Vec<_>>(),
+            vec![[10; 32], [20; 32]]
+        );
+        assert_eq!(store.metrics.missing_proofs.get(), 1);
+        assert!(store
+            .storage
+            .fetch_message_states(
+                vec![[20; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .is_ok());
+        assert!(store
+            .storage
+            .fetch_message_states(
+                vec![[30; 32]],
+                RequestTime::Latest,
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+            .await
+            .is_err());
     }
 
-    /// Create a dummy price feed base on the given seed for all the fields except
-    /// `publish_time` and `prev_publish_time`. Those are set to the given value.
-    pub fn create_dummy_price_feed_message(
-        seed: u8,
-        publish_time: i64,
-        prev_publish_time: i64,
-    ) -> PriceFeedMessage {
-        PriceFeedMessage {
-            feed_id: [seed; 32],
-            price: seed as _,
-            conf: seed as _,
-            exponent: 0,
-            ema_conf: seed as _,
-            ema_price: seed as _,
-            publish_time,
-            prev_publish_time,
+    #[tokio::test]
+    pub async fn test_feed_slots_lists_the_slots_of_the_feed() {
+        let (store, _receiver_tx) = setup_store(10).await;
+
+        for slot in [10, 11, 12, 15] {
+            let mut messages = vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                200,
+                slot as i64,
+                slot as i64 - 1,
+            ))];
+            if slot != 11 {
+                messages.push(Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                )));
+            }
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(messages, slot, slot + 10),
+            )
+            .await;
         }
+
+        assert_eq!(
+            store.feed_slots(PriceIdentifier::new([100; 32])),
+            vec![10, 12, 15]
+        );
+        assert!(store.feed_slots(PriceIdentifier::new([50; 32])).is_empty());
     }
 
-    pub async fn setup_store(cache_size: u64) -> (Arc<Store>, Receiver<()>) {
-        let (update_tx, update_rx) = tokio::sync::mpsc::channel(1000);
-        let store = Store::new(update_tx, cache_size);
+    #[tokio::test]
+    pub async fn test_debounced_signals_coalesce_bursts_of_completed_slots() {
+        let (store, mut update_rx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                signal_coalescing: SignalCoalescing::Debounced(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        )
+        .await;
 
-        // Add an initial guardian set with public key 0
-        store
-            .update_guardian_set(
-                0,
-                GuardianSet {
-                    keys: vec![[0; 20]],
-                },
+        for slot in 1..=20 {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
             )
             .await;
+        }
 
-        (store, update_rx)
+        // The burst is signaled once it is over.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut signals = 0;
+        while update_rx.try_recv().is_ok() {
+            signals += 1;
+        }
+        assert!(signals >= 1);
+        assert!(signals < 20);
     }
 
-    pub async fn store_multiple_concurrent_valid_updates(store: Arc<Store>, updates: Vec<Update>) {
-        let res = join_all(updates.into_iter().map(|u| store.store_update(u))).await;
-        // Check that all store_update calls succeeded
-        assert!(res.into_iter().all(|r| r.is_ok()));
+    #[tokio::test]
+    pub async fn test_debounced_signals_flush_a_steady_stream_of_slots() {
+        let (store, mut update_rx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                signal_coalescing: SignalCoalescing::Debounced(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // The slots complete faster than the window, for several windows.
+        let mut signals = 0;
+        for slot in 1..=20 {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
+            )
+            .await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            while update_rx.try_recv().is_ok() {
+                signals += 1;
+            }
+        }
+
+        // The stream is signaled while it lasts rather than once it is over.
+        assert!(signals >= 2);
+        assert!(signals < 20);
     }
 
     #[tokio::test]
-    pub async fn test_store_works() {
-        let (store, mut update_rx) = setup_store(10).await;
+    pub async fn test_debounced_signal_to_a_dropped_receiver_degrades_store() {
+        let (store, update_rx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                signal_coalescing: SignalCoalescing::Debounced(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        )
+        .await;
+        drop(update_rx);
 
-        let price_feed_message = create_dummy_price_feed_message(100, 10, 9);
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100, 10, 9,
+                ))],
+                10,
+                20,
+            ),
+        )
+        .await;
+        assert!(!store.is_degraded());
 
-        // Populate the store
+        // The debounced signal goes through the same path as the others.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(store.is_degraded());
+        let encoded = store.metrics().encode().unwrap();
+        assert!(encoded.contains("hermes_store_update_notification_latency_seconds_count 1\n"));
+    }
+
+    #[tokio::test]
+    pub async fn test_merkle_tree_of_slot_matches_the_signed_root() {
+        let (store, _receiver_tx) = setup_store(10).await;
         store_multiple_concurrent_valid_updates(
             store.clone(),
-            generate_update(vec![Message::PriceFeedMessage(price_feed_message)], 10, 20),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(10, 20, 19)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(20, 20, 19)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(30, 20, 19)),
+                ],
+                10,
+                20,
+            ),
         )
         .await;
+        let wormhole_merkle_state = store
+            .storage
+            .fetch_wormhole_merkle_state(10)
+            .await
+            .unwrap()
+            .unwrap();
 
-        // Check that the update_rx channel has received a message
-        assert_eq!(update_rx.recv().await, Some(()));
+        let merkle_tree = store.get_merkle_tree(10).await.unwrap();
+        assert_eq!(merkle_tree.root.as_bytes(), wormhole_merkle_state.root.root);
+        // Three leaves padded to four, and their internal nodes.
+        assert_eq!(merkle_tree.nodes.len(), 8);
 
-        // Check the price ids are stored correctly
-        assert_eq!(
-            store.get_price_feed_ids().await,
-            vec![PriceIdentifier::new([100; 32])].into_iter().collect()
-        );
+        assert!(store.get_merkle_tree(11).await.is_none());
+    }
 
-        // Check get_price_feeds_with_update_data retrieves the correct
-        // price feed with correct update data.
-        let price_feeds_with_update_data = store
-            .get_price_feeds_with_update_data(
-                vec![PriceIdentifier::new([100; 32])],
-                RequestTime::Latest,
-            )
+    #[tokio::test]
+    pub async fn test_json_logging_logs_a_json_line_per_completed_slot() {
+        let recorder = SpanRecorder::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (store, _receiver_tx) = setup_store_with_config(
+            10,
+            StoreConfig {
+                json_logging: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        store_multiple_concurrent_valid_updates(
+            store.clone(),
+            generate_update(
+                vec![
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(100, 10, 9)),
+                    Message::PriceFeedMessage(create_dummy_price_feed_message(200, 10, 9)),
+                ],
+                10,
+                20,
+            ),
+        )
+        .await;
+        let root = store
+            .storage
+            .fetch_wormhole_merkle_state(10)
             .await
-            .unwrap();
+            .unwrap()
+            .unwrap()
+            .root
+            .root;
 
+        let slot_logs = recorder
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(_, fields)| fields.get("message"))
+            .filter_map(|message| serde_json::from_str::<serde_json::Value>(message).ok())
+            .collect::<Vec<_>>();
         assert_eq!(
-            price_feeds_with_update_data.price_feeds,
-            vec![PriceFeedUpdate {
-                price_feed:                  price_feed_message,
-                slot:                        10,
-                received_at:                 price_feeds_with_update_data.price_feeds[0]
-                    .received_at, // Ignore checking this field.
-                wormhole_merkle_update_data: price_feeds_with_update_data.price_feeds[0]
-                    .wormhole_merkle_update_data
-                    .clone(), // Ignore checking this field.
-            }]
+            slot_logs,
+            vec![serde_json::json!({
+                "slot": 10,
+                "message_count": 2,
+                "feed_count": 2,
+                "root": hex::encode(root),
+                "sequence": 20,
+            })]
         );
+    }
 
-        // Check the update data is correct.
-        assert_eq!(
-            price_feeds_with_update_data
-                .wormhole_merkle_update_data
-                .len(),
-            1
-        );
-        let update_data = price_feeds_with_update_data
-            .wormhole_merkle_update_data
-            .get(0)
-            .unwrap();
-        let update_data = AccumulatorUpdateData::try_from_slice(update_data.as_ref()).unwrap();
-        match update_data.proof {
-            Proof::WormholeMerkle { vaa, updates } => {
-                // Check the vaa and get the root
-                let vaa: Vec<u8> = vaa.into();
-                let vaa: Vaa<&RawMessage> = serde_wormhole::from_slice(vaa.as_ref()).unwrap();
-                assert_eq!(
-                    vaa,
-                    Vaa {
-                        nonce:              0,
-                        version:            0,
-                        sequence:           20,
-                        timestamp:          0,
-                        signatures:         vec![],
-                        guardian_set_index: 0,
-                        emitter_chain:      Chain::Pythnet,
-                        emitter_address:    Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
-                        consistency_level:  0,
-                        payload:            vaa.payload, // Ignore checking this field.
-                    }
-                );
-                let merkle_root = WormholeMessage::try_from_bytes(vaa.payload.as_ref()).unwrap();
-                let WormholePayload::Merkle(merkle_root) = merkle_root.payload;
-                assert_eq!(
-                    merkle_root,
-                    WormholeMerkleRoot {
-                        slot:      10,
-                        ring_size: 100,
-                        root:      merkle_root.root, // Ignore checking this field.
-                    }
-                );
+    #[tokio::test]
+    pub async fn test_is_slot_retained_until_the_slot_is_evicted() {
+        let (store, _receiver_tx) = setup_store(2).await;
+        let store_slot = |slot: Slot| {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                        100,
+                        slot as i64,
+                        slot as i64 - 1,
+                    ))],
+                    slot,
+                    slot + 10,
+                ),
+            )
+        };
 
-                // Check the updates
-                assert_eq!(updates.len(), 1);
-                let update = updates.get(0).unwrap();
-                let message: Vec<u8> = update.message.clone().into();
-                // Check the serialized message is the price feed message generated above.
-                assert_eq!(
-                    pythnet_sdk::wire::from_slice::<byteorder::BE, Message>(message.as_ref())
-                        .unwrap(),
-                    Message::PriceFeedMessage(price_feed_message)
-                );
+        store_slot(10).await;
+        assert!(store.is_slot_retained(10).await);
+        assert!(!store.is_slot_retained(11).await);
 
-                // Check the proof is correct with the Vaa root
-                let merkle_root = MerkleRoot::<Keccak160>::new(merkle_root.root);
-                assert!(merkle_root.check(update.proof.clone(), message.as_ref()));
-            }
-        }
+        store_slot(11).await;
+        store_slot(12).await;
+        assert!(!store.is_slot_retained(10).await);
+        assert!(store.is_slot_retained(11).await);
+        assert!(store.is_slot_retained(12).await);
     }
 
     #[tokio::test]
-    pub async fn test_metadata_times_and_readiness_work() {
-        // The receiver channel should stay open for the store to work
-        // properly. That is why we don't use _ here as it drops the channel
-        // immediately.
+    pub async fn test_served_updates_carry_the_source_of_their_messages() {
         let (store, _receiver_tx) = setup_store(10).await;
 
-        let price_feed_message = create_dummy_price_feed_message(100, 10, 9);
-
-        // Advance the clock
-        MockClock::advance_system_time(Duration::from_secs(1));
-        MockClock::advance(Duration::from_secs(1));
-
-        // Get the current unix timestamp. It is mocked using
-        // mock-instance module. So it should remain the same
-        // on the next call.
-        let unix_timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        // Populate the store
+        for (seed, slot, source) in [(100, 10, "spy-a"), (200, 11, "spy-b")] {
+            for update in generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    seed, 10, 9,
+                ))],
+                slot,
+                slot + 10,
+            ) {
+                store
+                    .store_update_from_source(update, source)
+                    .await
+                    .unwrap();
+            }
+        }
         store_multiple_concurrent_valid_updates(
             store.clone(),
-            generate_update(vec![Message::PriceFeedMessage(price_feed_message)], 10, 20),
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    50, 10, 9,
+                ))],
+                12,
+                22,
+            ),
         )
         .await;
 
-        // Advance the clock again
-        MockClock::advance_system_time(Duration::from_secs(1));
-        MockClock::advance(Duration::from_secs(1));
-
-        // Get the price feeds with update data
-        let price_feeds_with_update_data = store
+        let price_feeds = store
             .get_price_feeds_with_update_data(
-                vec![PriceIdentifier::new([100; 32])],
+                vec![
+                    PriceIdentifier::new([100; 32]),
+                    PriceIdentifier::new([200; 32]),
+                    PriceIdentifier::new([50; 32]),
+                ],
                 RequestTime::Latest,
             )
             .await
-            .unwrap();
-
-        // check received_at is correct
-        assert_eq!(price_feeds_with_update_data.price_feeds.len(), 1);
+            .unwrap()
+            .price_feeds;
         assert_eq!(
-            price_feeds_with_update_data.price_feeds[0].received_at,
-            unix_timestamp as i64
+            price_feeds
+                .iter()
+                .map(|price_feed_update| price_feed_update.source.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("spy-a"), Some("spy-b"), None]
         );
-
-        // Check the store is ready
-        assert!(store.is_ready().await);
-
-        // Advance the clock to make the prices stale
-        MockClock::advance_system_time(READINESS_STALENESS_THRESHOLD);
-        MockClock::advance(READINESS_STALENESS_THRESHOLD);
-        // Check the store is not ready
-        assert!(!store.is_ready().await);
     }
 
-    /// Test that the store retains the latest slots upon cache eviction.
-    ///
-    /// Store is set up with cache size of 100 and 1000 slot updates will
-    /// be stored all at the same time with random order.
-    /// After the cache eviction, the store should retain the latest 100
-    /// slots regardless of the order.
     #[tokio::test]
-    pub async fn test_store_retains_latest_slots_upon_cache_eviction() {
-        // The receiver channel should stay open for the store to work
-        // properly. That is why we don't use _ here as it drops the channel
-        // immediately.
-        let (store, _receiver_tx) = setup_store(100).await;
+    pub async fn test_proof_against_historical_root_verifies() {
+        let (store, _receiver_tx) = setup_store(10).await;
+        for slot in [10, 11] {
+            store_multiple_concurrent_valid_updates(
+                store.clone(),
+                generate_update(
+                    vec![
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            10,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            20,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                        Message::PriceFeedMessage(create_dummy_price_feed_message(
+                            30,
+                            slot as i64,
+                            slot as i64 - 1,
+                        )),
+                    ],
+                    slot,
+                    slot + 10,
+                ),
+            )
+            .await;
+        }
+        let root = store
+            .storage
+            .fetch_wormhole_merkle_state(10)
+            .await
+            .unwrap()
+            .unwrap()
+            .root
+            .root;
 
-        let mut updates: Vec<Update> = (0..1000)
-            .flat_map(|slot| {
-                let messages = vec![
-                    Message::PriceFeedMessage(create_dummy_price_feed_message(
-                        100,
-                        slot as i64,
-                        slot as i64,
-                    )),
-                    Message::PriceFeedMessage(create_dummy_price_feed_message(
-                        200,
-                        slot as i64,
-                        slot as i64,
-                    )),
-                ];
-                generate_update(messages, slot, slot)
-            })
-            .collect();
+        let structured_proof = store
+            .get_proof_against_root(PriceIdentifier::new([20; 32]), root)
+            .await
+            .unwrap();
 
-        // Shuffle the updates
-        let mut rng = rand::thread_rng();
-        updates.shuffle(&mut rng);
+        // The proof is of the message of the older slot, not the latest one.
+        let raw_message = pythnet_sdk::wire::to_vec::<_, byteorder::BE>(
+            &Message::PriceFeedMessage(create_dummy_price_feed_message(20, 10, 9)),
+        )
+        .unwrap();
+        assert_eq!(
+            structured_proof.leaf,
+            MerkleTree::<Keccak160>::hash_leaf(&raw_message)
+        );
+        let proven_root = concatenate_structured_proof(&structured_proof);
+        assert_eq!(proven_root, root);
+        assert_eq!(structured_proof.root, root);
 
-        // Store the updates
-        store_multiple_concurrent_valid_updates(store.clone(), updates).await;
+        let err = store
+            .get_proof_against_root(PriceIdentifier::new([20; 32]), [7; 20])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StoreError>(),
+            Some(&StoreError::RootNotRetained { root: [7; 20] })
+        );
+    }
 
-        // Check the last 100 slots are retained
-        for slot in 900..1000 {
-            let price_feeds_with_update_data = store
-                .get_price_feeds_with_update_data(
-                    vec![
-                        PriceIdentifier::new([100; 32]),
-                        PriceIdentifier::new([200; 32]),
-                    ],
-                    RequestTime::FirstAfter(slot as i64),
-                )
-                .await
-                .unwrap();
-            assert_eq!(price_feeds_with_update_data.price_feeds.len(), 2);
-            assert_eq!(price_feeds_with_update_data.price_feeds[0].slot, slot);
-            assert_eq!(price_feeds_with_update_data.price_feeds[1].slot, slot);
-        }
+    #[tokio::test]
+    pub async fn test_bulk_signal_policy_does_not_mute_concurrent_updates() {
+        let (store, mut update_rx) = setup_store(10).await;
+        let generate_slot_update = |slot: Slot| {
+            generate_update(
+                vec![Message::PriceFeedMessage(create_dummy_price_feed_message(
+                    100,
+                    slot as i64,
+                    slot as i64 - 1,
+                ))],
+                slot,
+                slot + 10,
+            )
+        };
 
-        // Check nothing else is retained
-        for slot in 0..900 {
-            assert!(store
-                .get_price_feeds_with_update_data(
-                    vec![
-                        PriceIdentifier::new([100; 32]),
-                        PriceIdentifier::new([200; 32]),
-                    ],
-                    RequestTime::FirstAfter(slot as i64),
-                )
-                .await
-                .is_err());
+        let (bulk_result, _) = tokio::join!(
+            store.store_updates_in_bulk(
+                (1..=5).flat_map(generate_slot_update).collect(),
+                BulkSignalPolicy::Once,
+            ),
+            store_multiple_concurrent_valid_updates(store.clone(), generate_slot_update(100)),
+        );
+        bulk_result.unwrap();
+
+        // One signal for the whole batch and one for the concurrent slot.
+        let mut signals = 0;
+        while update_rx.try_recv().is_ok() {
+            signals += 1;
         }
+        assert_eq!(signals, 2);
     }
 }
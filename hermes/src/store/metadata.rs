@@ -0,0 +1,29 @@
+use {
+    anyhow::Result,
+    pyth_sdk::PriceIdentifier,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::collections::HashMap,
+};
+
+/// Operator supplied information about a price feed. It is not part of the
+/// on-chain data and only serves to give context to the raw identifiers.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct FeedMetadata {
+    pub symbol:      String,
+    pub asset_type:  String,
+    pub description: String,
+}
+
+/// A source of feed metadata, such as a configuration file.
+pub trait FeedMetadataProvider: Send + Sync {
+    fn load(&self) -> Result<HashMap<PriceIdentifier, FeedMetadata>>;
+}
+
+impl FeedMetadataProvider for HashMap<PriceIdentifier, FeedMetadata> {
+    fn load(&self) -> Result<HashMap<PriceIdentifier, FeedMetadata>> {
+        Ok(self.clone())
+    }
+}
@@ -0,0 +1,378 @@
+use {
+    super::types::Slot,
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    prometheus_client::{
+        encoding::text::encode,
+        metrics::{
+            counter::Counter,
+            family::Family,
+            gauge::Gauge,
+            histogram::Histogram,
+        },
+        registry::Registry,
+    },
+};
+
+/// Labels identifying an update listener.
+pub type ListenerLabels = Vec<(String, String)>;
+
+/// Labels identifying why a Vaa is ignored.
+pub type VaaIgnoreLabels = Vec<(String, String)>;
+
+/// Upper bounds (in seconds) of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0];
+
+/// Metrics collected by the store while ingesting updates.
+///
+/// All the metrics are registered in a Prometheus registry under the
+/// `hermes_store` prefix and can be exported with [`StoreMetrics::encode`].
+#[derive(Debug)]
+pub struct StoreMetrics {
+    registry:                             Registry,
+    /// Time between the timestamp of a Vaa and the moment the store
+    /// received it. It shows how far behind Wormhole the store is.
+    pub vaa_receive_latency:              Histogram,
+    /// Time between the completion of a slot and the moment the update
+    /// notification is sent to the api.
+    pub update_notification_latency:      Histogram,
+    /// Number of received Vaas, before any check.
+    pub received_vaas:                    Counter,
+    /// Number of received Vaas not used to update the store, per reason.
+    pub ignored_vaas:                     Family<VaaIgnoreLabels, Counter>,
+    /// Number of Vaas that passed the verification.
+    pub observed_vaas:                    Counter,
+    /// Number of slots for which the store built the message states.
+    pub completed_slots:                  Counter,
+    /// Number of Vaas rejected for being too far behind the highest
+    /// processed sequence of their emitter.
+    pub replayed_vaas:                    Counter,
+    /// Seconds since the last completed slot once it exceeds the stall
+    /// threshold, zero otherwise.
+    pub stall_seconds:                    Gauge,
+    /// Number of completed slot notifications dropped because the buffer
+    /// of a listener was full, per listener.
+    pub dropped_notifications:            Family<ListenerLabels, Counter>,
+    /// Number of messages skipped because their type is disabled.
+    pub skipped_messages:                 Counter,
+    /// Number of received accumulator message sets without any message.
+    pub empty_slots:                      Counter,
+    /// Number of accumulator message sets that differ from the set already
+    /// stored for their slot.
+    pub conflicting_accumulator_messages: Counter,
+    /// Number of messages whose publish time is outside the configured
+    /// window around the time of their slot.
+    pub inconsistent_publish_times:       Counter,
+    /// Number of price feed messages whose previous publish time is after
+    /// their publish time.
+    pub inverted_publish_times:           Counter,
+    /// Number of price feed messages served with their previous publish time
+    /// clamped to their publish time.
+    pub clamped_publish_times:            Counter,
+    /// One while Vaas are received but no guardian set is loaded to verify
+    /// them, zero otherwise.
+    pub no_guardian_set:                  Gauge,
+    /// Number of guardian sets rejected for having too many keys.
+    pub rejected_guardian_sets:           Counter,
+    /// Number of Vaas rejected for being signed longer than the maximum age
+    /// ago.
+    pub stale_vaas:                       Counter,
+    /// Number of merkle trees built from the accumulator messages of a slot.
+    pub merkle_tree_builds:               Counter,
+    /// Number of Vaas rejected for a consistency level below the minimum.
+    pub low_consistency_vaas:             Counter,
+    /// Number of price feed updates built for the price feed queries.
+    pub price_feed_update_builds:         Counter,
+    /// Number of messages skipped because their merkle proof is missing.
+    pub missing_proofs:                   Counter,
+}
+
+impl StoreMetrics {
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("hermes_store");
+
+        let vaa_receive_latency = Histogram::new(LATENCY_BUCKETS.into_iter());
+        registry.register(
+            "vaa_receive_latency_seconds",
+            "Time between the Vaa timestamp and the moment it is received",
+            vaa_receive_latency.clone(),
+        );
+
+        let update_notification_latency = Histogram::new(LATENCY_BUCKETS.into_iter());
+        registry.register(
+            "update_notification_latency_seconds",
+            "Time between a slot completion and the update notification",
+            update_notification_latency.clone(),
+        );
+
+        let received_vaas = Counter::default();
+        registry.register(
+            "received_vaas",
+            "Number of received Vaas, before any check",
+            received_vaas.clone(),
+        );
+
+        let ignored_vaas = Family::<VaaIgnoreLabels, Counter>::default();
+        registry.register(
+            "ignored_vaas",
+            "Number of received Vaas not used to update the store, per reason",
+            ignored_vaas.clone(),
+        );
+
+        let observed_vaas = Counter::default();
+        registry.register(
+            "observed_vaas",
+            "Number of Vaas that passed the verification",
+            observed_vaas.clone(),
+        );
+
+        let completed_slots = Counter::default();
+        registry.register(
+            "completed_slots",
+            "Number of slots for which the message states are built",
+            completed_slots.clone(),
+        );
+
+        let replayed_vaas = Counter::default();
+        registry.register(
+            "replayed_vaas",
+            "Number of Vaas rejected as replays of old sequences",
+            replayed_vaas.clone(),
+        );
+
+        let stall_seconds = Gauge::default();
+        registry.register(
+            "stall_seconds",
+            "Seconds since the last completed slot when ingestion is stalled",
+            stall_seconds.clone(),
+        );
+
+        let dropped_notifications = Family::<ListenerLabels, Counter>::default();
+        registry.register(
+            "dropped_notifications",
+            "Number of completed slot notifications dropped for a full listener",
+            dropped_notifications.clone(),
+        );
+
+        let skipped_messages = Counter::default();
+        registry.register(
+            "skipped_messages",
+            "Number of messages skipped because their type is disabled",
+            skipped_messages.clone(),
+        );
+
+        let empty_slots = Counter::default();
+        registry.register(
+            "empty_slots",
+            "Number of received accumulator message sets without any message",
+            empty_slots.clone(),
+        );
+
+        let conflicting_accumulator_messages = Counter::default();
+        registry.register(
+            "conflicting_accumulator_messages",
+            "Number of accumulator message sets differing from the stored set of their slot",
+            conflicting_accumulator_messages.clone(),
+        );
+
+        let inconsistent_publish_times = Counter::default();
+        registry.register(
+            "inconsistent_publish_times",
+            "Number of messages whose publish time is inconsistent with their slot",
+            inconsistent_publish_times.clone(),
+        );
+
+        let inverted_publish_times = Counter::default();
+        registry.register(
+            "inverted_publish_times",
+            "Number of price feed messages whose previous publish time is after their publish time",
+            inverted_publish_times.clone(),
+        );
+
+        let clamped_publish_times = Counter::default();
+        registry.register(
+            "clamped_publish_times",
+            "Number of price feed messages served with their previous publish time clamped",
+            clamped_publish_times.clone(),
+        );
+
+        let no_guardian_set = Gauge::default();
+        registry.register(
+            "no_guardian_set",
+            "Whether Vaas are received while no guardian set is loaded",
+            no_guardian_set.clone(),
+        );
+
+        let rejected_guardian_sets = Counter::default();
+        registry.register(
+            "rejected_guardian_sets",
+            "Number of guardian sets rejected for having too many keys",
+            rejected_guardian_sets.clone(),
+        );
+
+        let stale_vaas = Counter::default();
+        registry.register(
+            "stale_vaas",
+            "Number of Vaas rejected for being signed longer than the maximum age ago",
+            stale_vaas.clone(),
+        );
+
+        let merkle_tree_builds = Counter::default();
+        registry.register(
+            "merkle_tree_builds",
+            "Number of merkle trees built from the accumulator messages of a slot",
+            merkle_tree_builds.clone(),
+        );
+
+        let low_consistency_vaas = Counter::default();
+        registry.register(
+            "low_consistency_vaas",
+            "Number of Vaas rejected for a consistency level below the minimum",
+            low_consistency_vaas.clone(),
+        );
+
+        let price_feed_update_builds = Counter::default();
+        registry.register(
+            "price_feed_update_builds",
+            "Number of price feed updates built for the price feed queries",
+            price_feed_update_builds.clone(),
+        );
+
+        let missing_proofs = Counter::default();
+        registry.register(
+            "missing_proofs",
+            "Number of messages skipped because their merkle proof is missing",
+            missing_proofs.clone(),
+        );
+
+        Self {
+            registry,
+            vaa_receive_latency,
+            update_notification_latency,
+            received_vaas,
+            ignored_vaas,
+            observed_vaas,
+            completed_slots,
+            replayed_vaas,
+            stall_seconds,
+            dropped_notifications,
+            skipped_messages,
+            empty_slots,
+            conflicting_accumulator_messages,
+            inconsistent_publish_times,
+            inverted_publish_times,
+            clamped_publish_times,
+            no_guardian_set,
+            rejected_guardian_sets,
+            stale_vaas,
+            merkle_tree_builds,
+            low_consistency_vaas,
+            price_feed_update_builds,
+            missing_proofs,
+        }
+    }
+
+    /// Returns the labels of the listener with the given id.
+    pub fn listener_labels(id: usize) -> ListenerLabels {
+        vec![("listener".to_string(), id.to_string())]
+    }
+
+    /// Encodes all the metrics in the OpenMetrics text format.
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = String::new();
+        encode(&mut buffer, &self.registry)
+            .map_err(|e| anyhow!("Failed to encode metrics: {:?}", e))?;
+        Ok(buffer)
+    }
+}
+
+impl Default for StoreMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why a received Vaa is not used to update the store.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VaaIgnoreReason {
+    /// The Vaa is not emitted by the Pythnet accumulator.
+    OtherEmitter,
+    /// The Vaa is at or below the persisted sequence watermark.
+    BelowWatermark,
+    /// The Vaa is already observed.
+    AlreadyObserved,
+    /// The Vaa is too far behind the newest sequence of its emitter.
+    Replayed,
+    /// No guardian set is loaded to verify the Vaa.
+    NoGuardianSet,
+    /// The Vaa fails verification.
+    Invalid,
+    /// The Vaa is signed longer than the maximum age ago.
+    Stale,
+}
+
+impl VaaIgnoreReason {
+    /// Returns the labels of the reason in the `ignored_vaas` metric.
+    pub fn labels(&self) -> VaaIgnoreLabels {
+        let reason = match self {
+            VaaIgnoreReason::OtherEmitter => "other_emitter",
+            VaaIgnoreReason::BelowWatermark => "below_watermark",
+            VaaIgnoreReason::AlreadyObserved => "already_observed",
+            VaaIgnoreReason::Replayed => "replayed",
+            VaaIgnoreReason::NoGuardianSet => "no_guardian_set",
+            VaaIgnoreReason::Invalid => "invalid",
+            VaaIgnoreReason::Stale => "stale",
+        };
+        vec![("reason".to_string(), reason.to_string())]
+    }
+}
+
+/// A backend recording the ingestion events of the store, so they can be
+/// exported to any metrics system, such as StatsD. The store records every
+/// event in its built-in Prometheus metrics, which implement this trait,
+/// before the pluggable recorder. All the methods do nothing by default.
+pub trait StoreMetricsRecorder: Send + Sync {
+    /// A Vaa is received, before any check.
+    fn record_vaa_received(&self) {
+    }
+
+    /// A received Vaa is not used to update the store.
+    fn record_vaa_ignored(&self, _reason: VaaIgnoreReason) {
+    }
+
+    /// A slot is completed with the given number of message states.
+    fn record_slot_completed(&self, _slot: Slot, _num_messages: usize) {
+    }
+}
+
+/// A recorder that discards all the events.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopStoreMetricsRecorder;
+
+impl StoreMetricsRecorder for NoopStoreMetricsRecorder {
+}
+
+impl StoreMetricsRecorder for StoreMetrics {
+    fn record_vaa_received(&self) {
+        self.received_vaas.inc();
+    }
+
+    fn record_vaa_ignored(&self, reason: VaaIgnoreReason) {
+        self.ignored_vaas.get_or_create(&reason.labels()).inc();
+        match reason {
+            VaaIgnoreReason::Replayed => {
+                self.replayed_vaas.inc();
+            }
+            VaaIgnoreReason::Stale => {
+                self.stale_vaas.inc();
+            }
+            _ => {}
+        }
+    }
+
+    fn record_slot_completed(&self, _slot: Slot, _num_messages: usize) {
+        self.completed_slots.inc();
+    }
+}
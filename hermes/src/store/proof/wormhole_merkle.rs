@@ -1,16 +1,21 @@
 use {
     crate::store::{
         storage::MessageState,
-        types::AccumulatorMessages,
+        types::{
+            AccumulatorMessages,
+            StoreError,
+        },
         Store,
     },
     anyhow::{
         anyhow,
         Result,
     },
+    futures::future::join_all,
     pythnet_sdk::{
         accumulators::{
             merkle::{
+                MerkleMultiProof,
                 MerklePath,
                 MerkleTree,
             },
@@ -18,6 +23,7 @@ use {
         },
         hashers::keccak256_160::Keccak160,
         wire::{
+            from_slice,
             to_vec,
             v1::{
                 AccumulatorUpdateData,
@@ -25,65 +31,281 @@ use {
                 Proof,
                 WormholeMerkleRoot,
             },
+            PrefixedVec,
         },
     },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    std::{
+        collections::BTreeMap,
+        ops::Range,
+        sync::Arc,
+    },
+    tokio::sync::Semaphore,
 };
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct WormholeMerkleState {
-    pub root: WormholeMerkleRoot,
-    pub vaa:  Vec<u8>,
+    pub root:               WormholeMerkleRoot,
+    pub vaa:                Vec<u8>,
+    /// Sequence of the Vaa signing the root.
+    pub sequence:           u64,
+    /// Index of the guardian set that signed the Vaa.
+    pub guardian_set_index: u32,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct WormholeMerkleMessageProof {
-    pub vaa:   Vec<u8>,
-    pub proof: MerklePath<Keccak160>,
+    pub vaa:        Vec<u8>,
+    pub proof:      MerklePath<Keccak160>,
+    /// Position of the message among the leaves of the merkle tree.
+    pub leaf_index: u32,
+}
+
+/// Magic of `WormholeMerkleMultiProofUpdateData`, which tells it apart from
+/// an `AccumulatorUpdateData` starting with `PNAU`.
+pub const WORMHOLE_MERKLE_MULTIPROOF_MAGIC: &[u8; 4] = b"PNAM";
+
+/// Update data that proves all the updated messages of a slot with a single
+/// merkle multiproof instead of a merkle path per message.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct WormholeMerkleMultiProofUpdateData {
+    magic:         [u8; 4],
+    major_version: u8,
+    minor_version: u8,
+    pub vaa:       PrefixedVec<u16, u8>,
+    /// Prefixed with a u16 length, as a query may prove more than 255 feeds.
+    pub messages:  PrefixedVec<u16, PrefixedVec<u16, u8>>,
+    pub proof:     MerkleMultiProof<Keccak160>,
+}
+
+impl WormholeMerkleMultiProofUpdateData {
+    pub fn new(
+        vaa: PrefixedVec<u16, u8>,
+        messages: Vec<PrefixedVec<u16, u8>>,
+        proof: MerkleMultiProof<Keccak160>,
+    ) -> Self {
+        Self {
+            magic: *WORMHOLE_MERKLE_MULTIPROOF_MAGIC,
+            major_version: 1,
+            minor_version: 0,
+            vaa,
+            messages: messages.into(),
+            proof,
+        }
+    }
+
+    /// Parses multiproof update data, checking its magic and version.
+    pub fn try_from_slice(bytes: &[u8]) -> Result<Self> {
+        let update_data = from_slice::<byteorder::BE, Self>(bytes)
+            .map_err(|e| anyhow!("Failed to parse multiproof update data: {:?}", e))?;
+        if &update_data.magic != WORMHOLE_MERKLE_MULTIPROOF_MAGIC {
+            return Err(anyhow!("Invalid multiproof update data magic"));
+        }
+        if update_data.major_version != 1 {
+            return Err(anyhow!(
+                "Unsupported multiproof update data version {}",
+                update_data.major_version
+            ));
+        }
+        Ok(update_data)
+    }
+}
+
+/// Several `AccumulatorUpdateData` blobs, typically of different slots,
+/// bundled so a batch-capable contract can process them in one transaction.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct BundledUpdateData {
+    pub update_data: Vec<PrefixedVec<u16, u8>>,
 }
 
 pub async fn store_wormhole_merkle_verified_message(
     store: &Store,
     root: WormholeMerkleRoot,
     vaa_bytes: Vec<u8>,
+    sequence: u64,
+    guardian_set_index: u32,
 ) -> Result<()> {
     store
         .storage
         .store_wormhole_merkle_state(WormholeMerkleState {
             root,
             vaa: vaa_bytes,
+            sequence,
+            guardian_set_index,
         })
         .await?;
     Ok(())
 }
 
-pub fn construct_message_states_proofs(
+/// Bounded pool of blocking threads constructing proofs. Proof construction
+/// is CPU bound, so it runs off the async workers, and the pool is shared by
+/// all the slots and requests so that at most `workers` threads construct
+/// proofs at once.
+pub struct ProofPool {
+    workers: usize,
+    permits: Arc<Semaphore>,
+    /// Delay added to each job, to test the deadlines of the queries.
+    #[cfg(test)]
+    delay:   std::sync::Mutex<std::time::Duration>,
+}
+
+impl ProofPool {
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        Self {
+            workers,
+            permits: Arc::new(Semaphore::new(workers)),
+            #[cfg(test)]
+            delay: Default::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn set_delay(&self, delay: std::time::Duration) {
+        *self.delay.lock().unwrap() = delay;
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Runs `f` on a blocking thread once a worker of the pool is free. The
+    /// worker is only released when `f` returns, even if the caller stopped
+    /// waiting for it.
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await?;
+        #[cfg(test)]
+        let delay = *self.delay.lock().unwrap();
+        Ok(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            #[cfg(test)]
+            std::thread::sleep(delay);
+            f()
+        })
+        .await?)
+    }
+}
+
+/// Builds the merkle tree of the accumulator messages and checks it against
+/// the root signed in the Vaa. Returns `None` when there are no messages.
+fn construct_merkle_tree(
     accumulator_messages: &AccumulatorMessages,
     wormhole_merkle_state: &WormholeMerkleState,
-) -> Result<Vec<WormholeMerkleMessageProof>> {
-    // Check whether the state is valid
+) -> Result<Option<MerkleTree<Keccak160>>> {
+    // The tree is built from the messages in the exact order of the
+    // accumulator, which is the order the root signed in the Vaa commits to.
+    // Any reordering of the messages during ingestion changes the root.
     let merkle_acc = match MerkleTree::<Keccak160>::from_set(
         accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
     ) {
         Some(merkle_acc) => merkle_acc,
-        None => return Ok(vec![]), // It only happens when the message set is empty
+        None => return Ok(None), // It only happens when the message set is empty
     };
 
     if merkle_acc.root.as_bytes() != wormhole_merkle_state.root.root {
-        return Err(anyhow!("Invalid merkle root"));
+        tracing::error!(
+            "Merkle root of the accumulator messages of slot {} does not match the root signed in its Vaa",
+            accumulator_messages.slot
+        );
+        return Err(StoreError::MerkleRootMismatch {
+            slot: accumulator_messages.slot,
+        }
+        .into());
     }
 
-    accumulator_messages
-        .raw_messages
-        .iter()
-        .map(|m| {
+    Ok(Some(merkle_acc))
+}
+
+/// Constructs the proofs of the messages at `leaf_indices` in the tree.
+fn construct_proofs(
+    merkle_acc: &MerkleTree<Keccak160>,
+    vaa: &[u8],
+    leaf_indices: Range<usize>,
+) -> Result<Vec<WormholeMerkleMessageProof>> {
+    // The leaves are the messages in order, so the path of a message is
+    // found by its position instead of searching its hash in the tree.
+    let num_leaves = merkle_acc.nodes.len() / 2;
+    leaf_indices
+        .map(|leaf_index| {
             Ok(WormholeMerkleMessageProof {
-                vaa:   wormhole_merkle_state.vaa.clone(),
-                proof: merkle_acc
-                    .prove(m.as_ref())
-                    .ok_or(anyhow!("Failed to prove message"))?,
+                vaa:        vaa.to_vec(),
+                proof:      merkle_acc.find_path(num_leaves + leaf_index),
+                leaf_index: leaf_index.try_into()?,
             })
         })
-        .collect::<Result<Vec<WormholeMerkleMessageProof>>>()
+        .collect()
+}
+
+/// Builds the merkle tree of the accumulator messages and the proof of each
+/// message. The tree is returned so the proofs of other subsets of the
+/// messages can be constructed without rebuilding it.
+pub fn construct_message_states_proofs(
+    accumulator_messages: &AccumulatorMessages,
+    wormhole_merkle_state: &WormholeMerkleState,
+) -> Result<(
+    Option<MerkleTree<Keccak160>>,
+    Vec<WormholeMerkleMessageProof>,
+)> {
+    let merkle_acc = match construct_merkle_tree(accumulator_messages, wormhole_merkle_state)? {
+        Some(merkle_acc) => merkle_acc,
+        None => return Ok((None, vec![])),
+    };
+    let proofs = construct_proofs(
+        &merkle_acc,
+        &wormhole_merkle_state.vaa,
+        0..accumulator_messages.raw_messages.len(),
+    )?;
+    Ok((Some(merkle_acc), proofs))
+}
+
+/// Same as `construct_message_states_proofs`, with the tree built on `pool`
+/// and the proofs split in contiguous ranges of messages constructed by its
+/// workers. The proofs are returned in the order of the messages regardless.
+pub async fn construct_message_states_proofs_pooled(
+    pool: &ProofPool,
+    accumulator_messages: Arc<AccumulatorMessages>,
+    wormhole_merkle_state: Arc<WormholeMerkleState>,
+) -> Result<(
+    Option<MerkleTree<Keccak160>>,
+    Vec<WormholeMerkleMessageProof>,
+)> {
+    let merkle_acc = {
+        let accumulator_messages = accumulator_messages.clone();
+        let wormhole_merkle_state = wormhole_merkle_state.clone();
+        pool.run(move || construct_merkle_tree(&accumulator_messages, &wormhole_merkle_state))
+            .await??
+    };
+    let merkle_acc = match merkle_acc {
+        Some(merkle_acc) => Arc::new(merkle_acc),
+        None => return Ok((None, vec![])),
+    };
+
+    let num_messages = accumulator_messages.raw_messages.len();
+    let chunk_size = (num_messages + pool.workers() - 1) / pool.workers();
+    let proofs = join_all((0..num_messages).step_by(chunk_size).map(|start| {
+        let merkle_acc = merkle_acc.clone();
+        let wormhole_merkle_state = wormhole_merkle_state.clone();
+        let end = (start + chunk_size).min(num_messages);
+        pool.run(move || construct_proofs(&merkle_acc, &wormhole_merkle_state.vaa, start..end))
+    }))
+    .await
+    .into_iter()
+    .map(|proofs| proofs?)
+    .collect::<Result<Vec<_>>>()?
+    .into_iter()
+    .flatten()
+    .collect();
+
+    // The workers dropped their handle of the tree once done with it.
+    let merkle_acc = Arc::try_unwrap(merkle_acc).unwrap_or_else(|merkle_acc| (*merkle_acc).clone());
+    Ok((Some(merkle_acc), proofs))
 }
 
 pub fn construct_update_data(mut message_states: Vec<&MessageState>) -> Result<Vec<Vec<u8>>> {
@@ -95,31 +317,423 @@ pub fn construct_update_data(mut message_states: Vec<&MessageState>) -> Result<V
         .group_by(|a, b| {
             a.proof_set.wormhole_merkle_proof.vaa == b.proof_set.wormhole_merkle_proof.vaa
         })
-        .map(|messages| {
-            let vaa = messages
-                .get(0)
-                .ok_or(anyhow!("Empty message set"))?
-                .proof_set
-                .wormhole_merkle_proof
-                .vaa
-                .clone();
+        .map(construct_vaa_update_data)
+        .collect::<Result<Vec<Vec<u8>>>>()
+}
+
+/// Same as `construct_update_data`, with the update data of each Vaa
+/// constructed on a worker of `pool`, in the same order.
+pub async fn construct_update_data_pooled(
+    pool: &ProofPool,
+    message_states: Vec<MessageState>,
+) -> Result<Vec<Vec<u8>>> {
+    let mut messages_by_vaa: BTreeMap<Vec<u8>, Vec<MessageState>> = BTreeMap::new();
+    for message_state in message_states {
+        messages_by_vaa
+            .entry(message_state.proof_set.wormhole_merkle_proof.vaa.clone())
+            .or_default()
+            .push(message_state);
+    }
+
+    join_all(messages_by_vaa.into_values().map(|messages| {
+        pool.run(move || construct_vaa_update_data(&messages.iter().collect::<Vec<_>>()))
+    }))
+    .await
+    .into_iter()
+    .map(|update_data| update_data?)
+    .collect()
+}
+
+/// Constructs the update data of message states sharing the same Vaa.
+pub fn construct_vaa_update_data(messages: &[&MessageState]) -> Result<Vec<u8>> {
+    let vaa = messages
+        .get(0)
+        .ok_or(anyhow!("Empty message set"))?
+        .proof_set
+        .wormhole_merkle_proof
+        .vaa
+        .clone();
+
+    Ok(to_vec::<_, byteorder::BE>(&AccumulatorUpdateData::new(
+        Proof::WormholeMerkle {
+            vaa:     vaa.into(),
+            updates: messages
+                .iter()
+                .map(|message| {
+                    Ok(MerklePriceUpdate {
+                        message: to_vec::<_, byteorder::BE>(&message.message)
+                            .map_err(|e| anyhow!("Failed to serialize message: {}", e))?
+                            .into(),
+                        proof:   message.proof_set.wormhole_merkle_proof.proof.clone(),
+                    })
+                })
+                .collect::<Result<_>>()?,
+        },
+    ))?)
+}
+
+/// Constructs a single bundle of the update data of the message states, with
+/// one `AccumulatorUpdateData` per Vaa.
+pub fn construct_bundled_update_data(message_states: Vec<&MessageState>) -> Result<Vec<u8>> {
+    let bundle = BundledUpdateData {
+        update_data: construct_update_data(message_states)?
+            .into_iter()
+            .map(PrefixedVec::from)
+            .collect(),
+    };
+    to_vec::<_, byteorder::BE>(&bundle).map_err(|e| anyhow!("Failed to serialize bundle: {}", e))
+}
+
+/// Constructs one update data per slot carrying the Vaa and all the leaf
+/// messages of the slot's merkle tree, in tree order, without merkle paths.
+/// Clients can rebuild the tree from the leaves and check it against the
+/// root signed in the Vaa.
+pub async fn construct_leaves_only_update_data(
+    store: &Store,
+    mut message_states: Vec<&MessageState>,
+) -> Result<Vec<Vec<u8>>> {
+    message_states.sort_by_key(|m| m.slot);
+
+    let mut slots = Vec::new();
+    for messages in message_states.group_by(|a, b| a.slot == b.slot) {
+        let first = messages.get(0).ok_or(anyhow!("Empty message set"))?;
+
+        let accumulator_messages = store
+            .storage
+            .fetch_accumulator_messages(first.slot)
+            .await?
+            .ok_or(anyhow!(
+                "Missing accumulator messages for slot {}",
+                first.slot
+            ))?;
+        slots.push((
+            first.proof_set.wormhole_merkle_proof.vaa.clone(),
+            accumulator_messages,
+        ));
+    }
+
+    store
+        .proof_pool
+        .run(move || {
+            slots
+                .into_iter()
+                .map(|(vaa, accumulator_messages)| {
+                    Ok(to_vec::<_, byteorder::BE>(&AccumulatorUpdateData::new(
+                        Proof::WormholeMerkle {
+                            vaa:     vaa.into(),
+                            updates: accumulator_messages
+                                .raw_messages
+                                .into_iter()
+                                .map(|raw_message| MerklePriceUpdate {
+                                    message: raw_message.into(),
+                                    proof:   MerklePath::new(vec![]),
+                                })
+                                .collect(),
+                        },
+                    ))?)
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .await?
+}
 
+/// Constructs one update data per Vaa carrying only the Vaa, which signs the
+/// merkle root, without any message.
+pub fn construct_root_only_update_data(
+    mut message_states: Vec<&MessageState>,
+) -> Result<Vec<Vec<u8>>> {
+    message_states.sort_by_key(|m| m.proof_set.wormhole_merkle_proof.vaa.clone());
+    message_states.dedup_by(|a, b| {
+        a.proof_set.wormhole_merkle_proof.vaa == b.proof_set.wormhole_merkle_proof.vaa
+    });
+
+    message_states
+        .into_iter()
+        .map(|message| {
             Ok(to_vec::<_, byteorder::BE>(&AccumulatorUpdateData::new(
                 Proof::WormholeMerkle {
-                    vaa:     vaa.into(),
-                    updates: messages
-                        .iter()
-                        .map(|message| {
-                            Ok(MerklePriceUpdate {
-                                message: to_vec::<_, byteorder::BE>(&message.message)
-                                    .map_err(|e| anyhow!("Failed to serialize message: {}", e))?
-                                    .into(),
-                                proof:   message.proof_set.wormhole_merkle_proof.proof.clone(),
-                            })
-                        })
-                        .collect::<Result<_>>()?,
+                    vaa:     message.proof_set.wormhole_merkle_proof.vaa.clone().into(),
+                    updates: vec![],
                 },
             ))?)
         })
-        .collect::<Result<Vec<Vec<u8>>>>()
+        .collect()
+}
+
+/// Merkle tree of a slot, or the accumulator messages to rebuild it from.
+enum SlotTree {
+    Built(Arc<MerkleTree<Keccak160>>),
+    Messages(AccumulatorMessages),
+}
+
+/// Constructs one multiproof update data per slot covering all the given
+/// message states of that slot.
+///
+/// Unlike the per message proofs, a multiproof depends on the position of
+/// all the proven messages in the tree, so the tree is rebuilt from the
+/// stored accumulator messages of the slot.
+pub async fn construct_multiproof_update_data(
+    store: &Store,
+    mut message_states: Vec<&MessageState>,
+) -> Result<Vec<Vec<u8>>> {
+    message_states.sort_by_key(|m| m.slot);
+
+    let mut slots = Vec::new();
+    for messages in message_states.group_by(|a, b| a.slot == b.slot) {
+        let first = messages.get(0).ok_or(anyhow!("Empty message set"))?;
+
+        // The tree is kept from when the message states of the slot were
+        // built, so it is only rebuilt for the slots completed without it.
+        let slot_tree = match store.storage.fetch_merkle_tree(first.slot).await {
+            Some(merkle_tree) => SlotTree::Built(merkle_tree),
+            None => {
+                let accumulator_messages = store
+                    .storage
+                    .fetch_accumulator_messages(first.slot)
+                    .await?
+                    .ok_or(anyhow!(
+                        "Missing accumulator messages for slot {}",
+                        first.slot
+                    ))?;
+                store.metrics.merkle_tree_builds.inc();
+                SlotTree::Messages(accumulator_messages)
+            }
+        };
+        slots.push((
+            first.proof_set.wormhole_merkle_proof.vaa.clone(),
+            messages
+                .iter()
+                .map(|message| message.raw_message.clone())
+                .collect::<Vec<_>>(),
+            // The leaves are located by the positions stored with their
+            // proofs instead of searching their hashes in the tree.
+            messages
+                .iter()
+                .map(|message| message.proof_set.wormhole_merkle_proof.leaf_index as usize)
+                .collect::<Vec<_>>(),
+            slot_tree,
+        ));
+    }
+
+    store
+        .proof_pool
+        .run(move || {
+            slots
+                .into_iter()
+                .map(|(vaa, messages, leaf_indices, slot_tree)| {
+                    let merkle_tree = match slot_tree {
+                        SlotTree::Built(merkle_tree) => merkle_tree,
+                        SlotTree::Messages(accumulator_messages) => Arc::new(
+                            MerkleTree::<Keccak160>::from_set(
+                                accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
+                            )
+                            .ok_or(anyhow!("Empty accumulator message set"))?,
+                        ),
+                    };
+                    let proof = merkle_tree
+                        .find_multi_path(&leaf_indices)
+                        .ok_or(anyhow!("Failed to prove messages"))?;
+
+                    Ok(to_vec::<_, byteorder::BE>(
+                        &WormholeMerkleMultiProofUpdateData::new(
+                            vaa.into(),
+                            messages.into_iter().map(|message| message.into()).collect(),
+                            proof,
+                        ),
+                    )?)
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .await?
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::store::types::ProofSet,
+        pythnet_sdk::messages::{
+            Message,
+            PriceFeedMessage,
+        },
+    };
+
+    /// Update data of `create_golden_message_states`, serialized once and
+    /// committed. The bytes are the wire format consumed by the receiver
+    /// contracts and by downstream golden-file tests, so this must only be
+    /// updated for a deliberate change of the serialization.
+    const GOLDEN_UPDATE_DATA: &str = concat!(
+        "504e4155010000000004010203040300550001010101010101010101010101010101010101010101",
+        "0101010101010101010100000000000000640000000000000001fffffffe00000000000003e90000",
+        "0000000003e7000000000000006300000000000000020246f2ccc6900a19fa31571ae2632b8566e0",
+        "5a0fe1a539218c7f4d3ff8135c6ad0ec356e4de00a008b0055000202020202020202020202020202",
+        "02020202020202020202020202020202020200000000000000c80000000000000002fffffffe0000",
+        "0000000003ea00000000000003e700000000000000c6000000000000000402da3f2bd1731952f362",
+        "53e0db45c1cdb49b175767a539218c7f4d3ff8135c6ad0ec356e4de00a008b005500030303030303",
+        "0303030303030303030303030303030303030303030303030303000000000000012c000000000000",
+        "0003fffffffe00000000000003eb00000000000003e70000000000000129000000000000000602f2",
+        "ee15ea639b73fa3db9b34a245bdfa015c260c59ff94557fd53e2a1a18a6a9107d2cc237a9a7d19",
+    );
+
+    /// Three price feed messages of the same slot, whose merkle tree has a
+    /// null leaf, all proven by a fixed fake Vaa.
+    fn create_golden_message_states() -> Vec<MessageState> {
+        let messages = [1u8, 2, 3].map(|seed| {
+            Message::PriceFeedMessage(PriceFeedMessage {
+                feed_id:           [seed; 32],
+                price:             seed as i64 * 100,
+                conf:              seed as u64,
+                exponent:          -2,
+                publish_time:      1000 + seed as i64,
+                prev_publish_time: 999,
+                ema_price:         seed as i64 * 99,
+                ema_conf:          seed as u64 * 2,
+            })
+        });
+        let raw_messages: Vec<Vec<u8>> = messages
+            .iter()
+            .map(|message| to_vec::<_, byteorder::BE>(message).unwrap())
+            .collect();
+
+        let merkle_tree =
+            MerkleTree::<Keccak160>::from_set(raw_messages.iter().map(|m| m.as_ref())).unwrap();
+        let num_leaves = merkle_tree.nodes.len() / 2;
+
+        messages
+            .into_iter()
+            .zip(raw_messages)
+            .enumerate()
+            .map(|(leaf_index, (message, raw_message))| {
+                MessageState::new(
+                    message,
+                    raw_message,
+                    ProofSet {
+                        wormhole_merkle_proof: WormholeMerkleMessageProof {
+                            vaa:        vec![1, 2, 3, 4],
+                            proof:      merkle_tree.find_path(num_leaves + leaf_index),
+                            leaf_index: leaf_index as u32,
+                        },
+                    },
+                    10,
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn test_construct_update_data_is_deterministic() {
+        let message_states = create_golden_message_states();
+        let update_data = construct_update_data(message_states.iter().collect()).unwrap();
+
+        for _ in 0..10 {
+            let message_states = create_golden_message_states();
+            assert_eq!(
+                construct_update_data(message_states.iter().collect()).unwrap(),
+                update_data
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_construct_update_data_matches_golden_bytes() {
+        let message_states = create_golden_message_states();
+        let update_data = construct_update_data(message_states.iter().collect()).unwrap();
+
+        assert_eq!(
+            update_data,
+            vec![hex::decode(GOLDEN_UPDATE_DATA).unwrap()],
+            "The serialization of the update data changed. If this is deliberate, update \
+             GOLDEN_UPDATE_DATA with the new bytes."
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_pooled_proofs_are_identical_to_serial_proofs() {
+        let accumulator_messages = AccumulatorMessages {
+            magic:        [0; 4],
+            slot:         10,
+            ring_size:    100,
+            raw_messages: (0..10_000u32)
+                .map(|seed| seed.to_be_bytes().to_vec())
+                .collect(),
+        };
+        let merkle_tree = MerkleTree::<Keccak160>::from_set(
+            accumulator_messages.raw_messages.iter().map(|m| m.as_ref()),
+        )
+        .unwrap();
+        let wormhole_merkle_state = WormholeMerkleState {
+            root:               WormholeMerkleRoot {
+                slot:      10,
+                ring_size: 100,
+                root:      merkle_tree.root.as_bytes().try_into().unwrap(),
+            },
+            vaa:                vec![1, 2, 3],
+            sequence:           20,
+            guardian_set_index: 0,
+        };
+
+        let (_, serial_proofs) =
+            construct_message_states_proofs(&accumulator_messages, &wormhole_merkle_state).unwrap();
+        let (_, pooled_proofs) = construct_message_states_proofs_pooled(
+            &ProofPool::new(3),
+            Arc::new(accumulator_messages),
+            Arc::new(wormhole_merkle_state),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(serial_proofs.len(), 10_000);
+        assert_eq!(pooled_proofs, serial_proofs);
+    }
+
+    #[tokio::test]
+    pub async fn test_pooled_update_data_is_identical_to_serial_update_data() {
+        let mut message_states = create_golden_message_states();
+        // A second Vaa, so the update data is split in two.
+        message_states[1].proof_set.wormhole_merkle_proof.vaa = vec![5, 6, 7];
+
+        let serial_update_data = construct_update_data(message_states.iter().collect()).unwrap();
+        let pooled_update_data = construct_update_data_pooled(&ProofPool::new(2), message_states)
+            .await
+            .unwrap();
+
+        assert_eq!(serial_update_data.len(), 2);
+        assert_eq!(pooled_update_data, serial_update_data);
+    }
+
+    #[test]
+    pub fn test_multiproof_with_hundreds_of_hashes_round_trips() {
+        let raw_messages = (0..1024u32)
+            .map(|seed| seed.to_be_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let merkle_tree =
+            MerkleTree::<Keccak160>::from_set(raw_messages.iter().map(|m| m.as_ref())).unwrap();
+
+        // Every 16th leaf, so the proven leaves share no sibling below the
+        // fifth level and each of the four levels below needs 64 hashes.
+        let leaf_indices = (0..1024).step_by(16).collect::<Vec<usize>>();
+        let proof = merkle_tree.find_multi_path(&leaf_indices).unwrap();
+        assert_eq!(proof.hashes.len(), 256);
+
+        let messages = leaf_indices
+            .iter()
+            .map(|leaf_index| raw_messages[*leaf_index].clone())
+            .collect::<Vec<_>>();
+        let update_data = to_vec::<_, byteorder::BE>(&WormholeMerkleMultiProofUpdateData::new(
+            vec![1, 2, 3].into(),
+            messages
+                .iter()
+                .map(|message| message.clone().into())
+                .collect(),
+            proof,
+        ))
+        .unwrap();
+
+        let update_data = WormholeMerkleMultiProofUpdateData::try_from_slice(&update_data).unwrap();
+        assert!(merkle_tree.root.check_multi(
+            update_data.proof,
+            &messages.iter().map(|m| m.as_slice()).collect::<Vec<_>>(),
+        ));
+    }
 }
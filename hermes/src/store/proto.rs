@@ -0,0 +1,190 @@
+//! Protobuf representation of the store types, so a gRPC server can emit
+//! the store data without converting it by hand.
+//!
+//! The messages are generated by `build.rs` from `proto/store.proto`. This
+//! module converts them from and to the store types.
+
+use {
+    super::types,
+    anyhow::{
+        anyhow,
+        Result,
+    },
+};
+
+include!(concat!(env!("OUT_DIR"), "/hermes.store.rs"));
+
+impl From<pythnet_sdk::messages::PriceFeedMessage> for PriceFeedMessage {
+    fn from(price_feed: pythnet_sdk::messages::PriceFeedMessage) -> Self {
+        Self {
+            feed_id:           price_feed.feed_id.to_vec(),
+            price:             price_feed.price,
+            conf:              price_feed.conf,
+            exponent:          price_feed.exponent,
+            publish_time:      price_feed.publish_time,
+            prev_publish_time: price_feed.prev_publish_time,
+            ema_price:         price_feed.ema_price,
+            ema_conf:          price_feed.ema_conf,
+        }
+    }
+}
+
+impl TryFrom<PriceFeedMessage> for pythnet_sdk::messages::PriceFeedMessage {
+    type Error = anyhow::Error;
+
+    fn try_from(price_feed: PriceFeedMessage) -> Result<Self> {
+        Ok(Self {
+            feed_id:           price_feed
+                .feed_id
+                .try_into()
+                .map_err(|feed_id: Vec<u8>| anyhow!("Invalid feed id length: {}", feed_id.len()))?,
+            price:             price_feed.price,
+            conf:              price_feed.conf,
+            exponent:          price_feed.exponent,
+            publish_time:      price_feed.publish_time,
+            prev_publish_time: price_feed.prev_publish_time,
+            ema_price:         price_feed.ema_price,
+            ema_conf:          price_feed.ema_conf,
+        })
+    }
+}
+
+impl From<types::PriceFeedUpdate> for PriceFeedUpdate {
+    fn from(price_feed_update: types::PriceFeedUpdate) -> Self {
+        Self {
+            price_feed:                  Some(price_feed_update.price_feed.into()),
+            slot:                        price_feed_update.slot,
+            received_at:                 price_feed_update.received_at,
+            wormhole_merkle_update_data: price_feed_update.wormhole_merkle_update_data,
+            is_stale:                    price_feed_update.is_stale,
+            merkle_leaf_index:           price_feed_update.merkle_leaf_index,
+            suspect:                     price_feed_update.suspect,
+            links_previous:              price_feed_update.links_previous,
+            unverified:                  price_feed_update.unverified,
+            source:                      price_feed_update.source,
+        }
+    }
+}
+
+impl TryFrom<PriceFeedUpdate> for types::PriceFeedUpdate {
+    type Error = anyhow::Error;
+
+    fn try_from(price_feed_update: PriceFeedUpdate) -> Result<Self> {
+        Ok(Self {
+            price_feed:                  price_feed_update
+                .price_feed
+                .ok_or(anyhow!("Missing price feed"))?
+                .try_into()?,
+            slot:                        price_feed_update.slot,
+            received_at:                 price_feed_update.received_at,
+            wormhole_merkle_update_data: price_feed_update.wormhole_merkle_update_data,
+            is_stale:                    price_feed_update.is_stale,
+            merkle_leaf_index:           price_feed_update.merkle_leaf_index,
+            suspect:                     price_feed_update.suspect,
+            links_previous:              price_feed_update.links_previous,
+            unverified:                  price_feed_update.unverified,
+            source:                      price_feed_update.source,
+        })
+    }
+}
+
+impl From<types::PriceFeedsWithUpdateData> for PriceFeedsWithUpdateData {
+    fn from(price_feeds_with_update_data: types::PriceFeedsWithUpdateData) -> Self {
+        Self {
+            price_feeds:                 price_feeds_with_update_data
+                .price_feeds
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            wormhole_merkle_update_data: price_feeds_with_update_data.wormhole_merkle_update_data,
+        }
+    }
+}
+
+impl TryFrom<PriceFeedsWithUpdateData> for types::PriceFeedsWithUpdateData {
+    type Error = anyhow::Error;
+
+    fn try_from(price_feeds_with_update_data: PriceFeedsWithUpdateData) -> Result<Self> {
+        Ok(Self {
+            price_feeds:                 price_feeds_with_update_data
+                .price_feeds
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+            wormhole_merkle_update_data: price_feeds_with_update_data.wormhole_merkle_update_data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        prost::Message,
+    };
+
+    fn create_dummy_price_feed_update() -> types::PriceFeedUpdate {
+        types::PriceFeedUpdate {
+            price_feed:                  pythnet_sdk::messages::PriceFeedMessage {
+                feed_id:           [1; 32],
+                price:             -100,
+                conf:              5,
+                exponent:          -8,
+                publish_time:      10,
+                prev_publish_time: 9,
+                ema_price:         -90,
+                ema_conf:          4,
+            },
+            slot:                        100,
+            received_at:                 11,
+            wormhole_merkle_update_data: vec![1, 2, 3],
+            is_stale:                    true,
+            merkle_leaf_index:           7,
+            suspect:                     true,
+            links_previous:              false,
+            unverified:                  false,
+            source:                      Some("spy-a".to_string()),
+        }
+    }
+
+    #[test]
+    pub fn test_price_feed_update_round_trips_through_protobuf() {
+        let price_feed_update = create_dummy_price_feed_update();
+
+        let bytes = PriceFeedUpdate::from(create_dummy_price_feed_update()).encode_to_vec();
+        let decoded: types::PriceFeedUpdate = PriceFeedUpdate::decode(bytes.as_slice())
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        assert_eq!(decoded, price_feed_update);
+    }
+
+    #[test]
+    pub fn test_price_feeds_with_update_data_round_trips_through_protobuf() {
+        let bytes = PriceFeedsWithUpdateData::from(types::PriceFeedsWithUpdateData {
+            price_feeds:                 vec![create_dummy_price_feed_update()],
+            wormhole_merkle_update_data: vec![vec![4, 5], vec![6]],
+        })
+        .encode_to_vec();
+        let decoded: types::PriceFeedsWithUpdateData =
+            PriceFeedsWithUpdateData::decode(bytes.as_slice())
+                .unwrap()
+                .try_into()
+                .unwrap();
+
+        assert_eq!(decoded.price_feeds, vec![create_dummy_price_feed_update()]);
+        assert_eq!(
+            decoded.wormhole_merkle_update_data,
+            vec![vec![4, 5], vec![6]]
+        );
+    }
+
+    #[test]
+    pub fn test_invalid_feed_id_is_rejected() {
+        let mut price_feed_update = PriceFeedUpdate::from(create_dummy_price_feed_update());
+        price_feed_update.price_feed.as_mut().unwrap().feed_id = vec![1; 31];
+
+        assert!(types::PriceFeedUpdate::try_from(price_feed_update).is_err());
+    }
+}
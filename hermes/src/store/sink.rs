@@ -0,0 +1,13 @@
+use {
+    super::storage::MessageState,
+    anyhow::Result,
+    async_trait::async_trait,
+};
+
+/// A consumer of the message states of each completed slot, such as a
+/// websocket broadcaster or a database writer. The store emits to all its
+/// sinks concurrently and a failing sink does not affect the others.
+#[async_trait]
+pub trait UpdateSink: Send + Sync {
+    async fn emit(&self, states: &[MessageState]) -> Result<()>;
+}
@@ -0,0 +1,26 @@
+use {
+    super::types::AccumulatorMessages,
+    borsh::{
+        BorshDeserialize,
+        BorshSerialize,
+    },
+};
+
+/// Version of the snapshot format. It is bumped on incompatible changes.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+/// The data of a retained slot needed to rebuild its state: the
+/// accumulator messages and the Vaa signing their merkle root.
+#[derive(Clone, PartialEq, Debug, BorshSerialize, BorshDeserialize)]
+pub struct SnapshotSlot {
+    pub accumulator_messages: AccumulatorMessages,
+    pub vaa:                  Vec<u8>,
+}
+
+/// A snapshot of all the retained slots of a store, used to bootstrap a
+/// fresh store without waiting for the live stream to fill its cache.
+#[derive(Clone, PartialEq, Debug, BorshSerialize, BorshDeserialize)]
+pub struct Snapshot {
+    pub version: u8,
+    pub slots:   Vec<SnapshotSlot>,
+}
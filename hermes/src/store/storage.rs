@@ -7,6 +7,7 @@ use {
             RawMessage,
             RequestTime,
             Slot,
+            StoreError,
             UnixTimestamp,
         },
     },
@@ -15,20 +16,40 @@ use {
         Result,
     },
     dashmap::DashMap,
-    pythnet_sdk::messages::{
-        FeedId,
-        Message,
-        MessageType,
+    pythnet_sdk::{
+        accumulators::merkle::MerkleTree,
+        hashers::keccak256_160::Keccak160,
+        messages::{
+            FeedId,
+            Message,
+            MessageType,
+        },
+    },
+    sha3::{
+        Digest,
+        Keccak256,
     },
     std::{
-        collections::BTreeMap,
+        collections::{
+            btree_map::Entry,
+            BTreeMap,
+            BTreeSet,
+            HashSet,
+        },
         ops::Bound,
         sync::Arc,
     },
     strum::IntoEnumIterator,
-    tokio::sync::RwLock,
+    tokio::sync::{
+        broadcast,
+        RwLock,
+    },
 };
 
+/// Capacity of the eviction notification channel. Receivers that fall
+/// further behind miss the oldest notifications.
+pub const EVICTION_CHAN_LEN: usize = 1000;
+
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub struct MessageStateKey {
     pub feed_id: FeedId,
@@ -53,6 +74,24 @@ pub struct MessageState {
     pub raw_message: RawMessage,
     pub proof_set:   ProofSet,
     pub received_at: UnixTimestamp,
+    /// Checksum of the raw message computed when the state is stored. It is
+    /// used to detect corruption of the cache in long-running processes.
+    pub checksum:    Option<[u8; 32]>,
+    /// Whether the price deviates from the previous retained price of the
+    /// feed by more than the configured ratio. Suspect states are still
+    /// stored and served.
+    pub suspect:     bool,
+    /// Whether the previous publish time of the served price feed is clamped
+    /// to its publish time. The message itself stays as proven by the merkle
+    /// root.
+    pub clamped:     bool,
+    /// Whether the state is backfilled from archived accumulator messages
+    /// without their Vaa. Such states carry no proof and are only served to
+    /// queries explicitly allowing unverified data.
+    pub unverified:  bool,
+    /// Source of the accumulator messages of the state, when stored from an
+    /// explicit source. It is metadata only and not part of the proof.
+    pub source:      Option<String>,
 }
 
 impl MessageState {
@@ -83,8 +122,55 @@ impl MessageState {
             raw_message,
             proof_set,
             received_at,
+            checksum: None,
+            suspect: false,
+            clamped: false,
+            unverified: false,
+            source: None,
         }
     }
+
+    /// Attaches the checksum of the raw message to the state.
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = Some(compute_checksum(&self.raw_message));
+        self
+    }
+
+    /// Returns whether the state still matches its checksum. States without a
+    /// checksum are always considered intact.
+    pub fn is_intact(&self) -> bool {
+        match self.checksum {
+            Some(checksum) => compute_checksum(&self.raw_message) == checksum,
+            None => true,
+        }
+    }
+}
+
+fn compute_checksum(raw_message: &[u8]) -> [u8; 32] {
+    let mut keccak = Keccak256::new();
+    keccak.update(raw_message);
+    keccak.finalize().into()
+}
+
+/// A message state whose raw message does not match its checksum.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct IntegrityMismatch {
+    pub key:  MessageStateKey,
+    pub time: MessageStateTime,
+}
+
+/// The result of recomputing the checksums of the cached message states.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct IntegrityReport {
+    /// Number of message states that carry a checksum and were verified.
+    pub num_checked: usize,
+    pub mismatches:  Vec<IntegrityMismatch>,
+}
+
+impl IntegrityReport {
+    pub fn is_intact(&self) -> bool {
+        self.mismatches.is_empty()
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -96,6 +182,10 @@ pub enum MessageStateFilter {
 
 pub struct Storage {
     message_cache: Arc<DashMap<MessageStateKey, BTreeMap<MessageStateTime, MessageState>>>,
+    /// Ids of the price feeds in the message cache, ordered so the feeds
+    /// sharing an id prefix are found by range. The message cache never
+    /// drops a key, so neither does this index.
+    price_feed_ids:              Arc<RwLock<BTreeSet<FeedId>>>,
     /// Accumulator messages cache
     ///
     /// We do not write to this cache much, so we can use a simple RwLock instead of a DashMap.
@@ -104,19 +194,78 @@ pub struct Storage {
     ///
     /// We do not write to this cache much, so we can use a simple RwLock instead of a DashMap.
     wormhole_merkle_state_cache: Arc<RwLock<BTreeMap<Slot, WormholeMerkleState>>>,
+    /// Merkle trees of the complete slots, kept so the proofs of any subset
+    /// of the messages of a slot are constructed without rebuilding its tree.
+    merkle_tree_cache:           Arc<RwLock<BTreeMap<Slot, Arc<MerkleTree<Keccak160>>>>>,
+    /// Slots that are excluded from the cache eviction.
+    pinned_slots:                Arc<RwLock<BTreeSet<Slot>>>,
+    /// Notifies the slots whose message states are evicted.
+    eviction_tx:                 broadcast::Sender<Slot>,
+    /// Called with the message states of each evicted slot before they are
+    /// dropped, so they can be archived to a colder storage.
+    on_evict:                    Option<EvictionCallback>,
     cache_size:                  u64,
 }
 
+/// Callback receiving the message states of an evicted slot.
+pub type EvictionCallback = Box<dyn Fn(Slot, &[MessageState]) + Send + Sync>;
+
+/// Removes the oldest entries of a cache until at most `cache_size` unpinned
+/// entries remain and returns the removed entries with their slots. Entries
+/// of pinned slots are neither evicted nor counted towards the cache size.
+fn evict_oldest_unpinned<K: Ord + Clone, V>(
+    cache: &mut BTreeMap<K, V>,
+    cache_size: usize,
+    pinned_slots: &BTreeSet<Slot>,
+    slot_of: impl Fn(&K) -> Slot,
+) -> Vec<(Slot, V)> {
+    let mut evicted = Vec::new();
+
+    if pinned_slots.is_empty() {
+        while cache.len() > cache_size {
+            if let Some((key, value)) = cache.pop_first() {
+                evicted.push((slot_of(&key), value));
+            }
+        }
+        return evicted;
+    }
+
+    let is_unpinned = |key: &&K| !pinned_slots.contains(&slot_of(key));
+    let mut num_unpinned = cache.keys().filter(is_unpinned).count();
+    while num_unpinned > cache_size {
+        let oldest_unpinned = match cache.keys().find(is_unpinned) {
+            Some(key) => key.clone(),
+            None => break,
+        };
+        if let Some(value) = cache.remove(&oldest_unpinned) {
+            evicted.push((slot_of(&oldest_unpinned), value));
+        }
+        num_unpinned -= 1;
+    }
+    evicted
+}
+
 impl Storage {
     pub fn new(cache_size: u64) -> Self {
         Self {
             message_cache: Arc::new(DashMap::new()),
+            price_feed_ids: Arc::new(RwLock::new(BTreeSet::new())),
             accumulator_messages_cache: Arc::new(RwLock::new(BTreeMap::new())),
             wormhole_merkle_state_cache: Arc::new(RwLock::new(BTreeMap::new())),
+            merkle_tree_cache: Arc::new(RwLock::new(BTreeMap::new())),
+            pinned_slots: Arc::new(RwLock::new(BTreeSet::new())),
+            eviction_tx: broadcast::channel(EVICTION_CHAN_LEN).0,
+            on_evict: None,
             cache_size,
         }
     }
 
+    /// Sets the callback receiving the message states of each evicted slot.
+    pub fn with_on_evict(mut self, on_evict: EvictionCallback) -> Self {
+        self.on_evict = Some(on_evict);
+        self
+    }
+
     pub async fn message_state_keys(&self) -> Vec<MessageStateKey> {
         self.message_cache
             .iter()
@@ -124,32 +273,247 @@ impl Storage {
             .collect::<Vec<_>>()
     }
 
+    /// Returns the ids of the price feeds starting with the prefix, in order.
+    pub async fn price_feed_ids_with_prefix(&self, prefix: &[u8]) -> Vec<FeedId> {
+        let mut first: FeedId = [0; 32];
+        let len = prefix.len().min(first.len());
+        first[..len].copy_from_slice(&prefix[..len]);
+
+        self.price_feed_ids
+            .read()
+            .await
+            .range(first..)
+            .take_while(|feed_id| feed_id.starts_with(prefix))
+            .copied()
+            .collect()
+    }
+
+    /// Returns the latest time an update of each price feed was received.
+    pub async fn price_feed_received_times(&self) -> Vec<(FeedId, UnixTimestamp)> {
+        self.message_cache
+            .iter()
+            .filter(|entry| entry.key().type_ == MessageType::PriceFeedMessage)
+            .filter_map(|entry| {
+                entry
+                    .value()
+                    .values()
+                    .map(|message_state| message_state.received_at)
+                    .max()
+                    .map(|received_at| (entry.key().feed_id, received_at))
+            })
+            .collect()
+    }
+
+    /// Returns, for each slot with retained price feed message states, the
+    /// number of feeds updated in the slot and the latest time the states
+    /// were received.
+    pub async fn price_feed_counts_by_slot(&self) -> BTreeMap<Slot, (usize, UnixTimestamp)> {
+        let mut counts: BTreeMap<Slot, (usize, UnixTimestamp)> = BTreeMap::new();
+        for entry in self
+            .message_cache
+            .iter()
+            .filter(|entry| entry.key().type_ == MessageType::PriceFeedMessage)
+        {
+            for message_state in entry.value().values() {
+                let (feed_count, received_at) = counts
+                    .entry(message_state.slot)
+                    .or_insert((0, message_state.received_at));
+                *feed_count += 1;
+                *received_at = (*received_at).max(message_state.received_at);
+            }
+        }
+        counts
+    }
+
+    /// Excludes the slot from the cache eviction. Fails if `max_pinned_slots`
+    /// slots are already pinned.
+    pub async fn pin_slot(&self, slot: Slot, max_pinned_slots: usize) -> Result<()> {
+        let mut pinned_slots = self.pinned_slots.write().await;
+        if !pinned_slots.contains(&slot) && pinned_slots.len() >= max_pinned_slots {
+            return Err(StoreError::TooManyPinnedSlots {
+                limit: max_pinned_slots,
+            }
+            .into());
+        }
+        pinned_slots.insert(slot);
+        Ok(())
+    }
+
+    /// Makes the slot subject to the cache eviction again.
+    pub async fn unpin_slot(&self, slot: Slot) {
+        self.pinned_slots.write().await.remove(&slot);
+    }
+
+    /// Subscribes to the slots whose message states are evicted. Each slot
+    /// is notified once per batch of stored message states, in ascending
+    /// order.
+    pub fn subscribe_evictions(&self) -> broadcast::Receiver<Slot> {
+        self.eviction_tx.subscribe()
+    }
+
     pub async fn store_message_states(&self, message_states: Vec<MessageState>) -> Result<()> {
+        let pinned_slots = self.pinned_slots.read().await;
+        let mut evicted_states: BTreeMap<Slot, Vec<MessageState>> = BTreeMap::new();
+        let mut new_price_feed_ids = Vec::new();
         for message_state in message_states {
             let key = message_state.key();
             let time = message_state.time();
+            if key.type_ == MessageType::PriceFeedMessage && !self.message_cache.contains_key(&key)
+            {
+                new_price_feed_ids.push(key.feed_id);
+            }
             let mut cache = self.message_cache.entry(key).or_insert_with(BTreeMap::new);
 
             cache.insert(time, message_state);
 
             // Remove the earliest message states if the cache size is exceeded
-            while cache.len() > self.cache_size as usize {
-                cache.pop_first();
+            for (slot, evicted_state) in evict_oldest_unpinned(
+                &mut cache,
+                self.cache_size as usize,
+                &pinned_slots,
+                |time| time.slot,
+            ) {
+                evicted_states.entry(slot).or_default().push(evicted_state);
             }
         }
+
+        if !new_price_feed_ids.is_empty() {
+            self.price_feed_ids.write().await.extend(new_price_feed_ids);
+        }
+
+        for (slot, states) in evicted_states {
+            if let Some(on_evict) = &self.on_evict {
+                on_evict(slot, &states);
+            }
+            // Sending only fails when there are no subscribers.
+            let _ = self.eviction_tx.send(slot);
+        }
         Ok(())
     }
 
+    /// Recomputes the checksums of the cached message states and reports the
+    /// states that do not match theirs.
+    pub async fn verify_integrity(&self) -> IntegrityReport {
+        let mut report = IntegrityReport::default();
+        for entry in self.message_cache.iter() {
+            for (time, message_state) in entry.value().iter() {
+                if message_state.checksum.is_none() {
+                    continue;
+                }
+                report.num_checked += 1;
+                if !message_state.is_intact() {
+                    report.mismatches.push(IntegrityMismatch {
+                        key:  entry.key().clone(),
+                        time: time.clone(),
+                    });
+                }
+            }
+        }
+        report
+    }
+
+    /// Fetches the message state with exactly the given key and time.
+    pub fn fetch_message_state(
+        &self,
+        key: &MessageStateKey,
+        time: &MessageStateTime,
+    ) -> Option<MessageState> {
+        self.message_cache
+            .get(key)
+            .and_then(|key_cache| key_cache.get(time).cloned())
+    }
+
+    /// Fetches the latest message state of the key that is older than the
+    /// given time.
+    pub fn fetch_message_state_before(
+        &self,
+        key: &MessageStateKey,
+        time: &MessageStateTime,
+    ) -> Option<MessageState> {
+        self.message_cache.get(key).and_then(|key_cache| {
+            key_cache
+                .range(..time)
+                .next_back()
+                .map(|(_, message_state)| message_state.clone())
+        })
+    }
+
+    /// Fetches all the retained message states of the key, in slot order.
+    pub fn fetch_all_message_states(&self, key: &MessageStateKey) -> Vec<MessageState> {
+        let mut message_states = self
+            .message_cache
+            .get(key)
+            .map(|key_cache| key_cache.values().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        message_states.sort_by_key(|message_state| message_state.slot);
+        message_states
+    }
+
+    /// Returns the sorted slots with a retained message state of the feed, of
+    /// any message type.
+    pub fn feed_slots(&self, feed_id: FeedId) -> Vec<Slot> {
+        MessageType::iter()
+            .filter_map(|type_| self.message_cache.get(&MessageStateKey { feed_id, type_ }))
+            .flat_map(|key_cache| key_cache.keys().map(|time| time.slot).collect::<Vec<_>>())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Fetches the message state of the key that was updated in the slot.
+    pub fn fetch_message_state_at_slot(
+        &self,
+        key: &MessageStateKey,
+        slot: Slot,
+    ) -> Option<MessageState> {
+        self.message_cache.get(key).and_then(|key_cache| {
+            key_cache
+                .values()
+                .find(|message_state| message_state.slot == slot)
+                .cloned()
+        })
+    }
+
+    /// Mutates a cached message state in place, bypassing all the checks.
+    #[cfg(test)]
+    pub fn mutate_message_state(
+        &self,
+        key: &MessageStateKey,
+        time: &MessageStateTime,
+        mutate: impl FnOnce(&mut MessageState),
+    ) {
+        if let Some(mut key_cache) = self.message_cache.get_mut(key) {
+            if let Some(message_state) = key_cache.get_mut(time) {
+                mutate(message_state);
+            }
+        }
+    }
+
+    /// Returns the slot that is `behind` slots back from the newest retained
+    /// slot, if that many slots are retained.
+    pub async fn confirmed_slot(&self, behind: usize) -> Option<Slot> {
+        self.wormhole_merkle_state_cache
+            .read()
+            .await
+            .keys()
+            .rev()
+            .nth(behind)
+            .copied()
+    }
+
+    /// Retrieves the message state of the key matching the request time.
+    /// `confirmed_slot` is the resolved slot of a `LatestConfirmed` request.
     fn retrieve_message_state(
         &self,
         key: MessageStateKey,
         request_time: RequestTime,
+        confirmed_slot: Option<Slot>,
     ) -> Option<MessageState> {
         match self.message_cache.get(&key) {
             Some(key_cache) => {
                 match request_time {
                     RequestTime::Latest => key_cache.last_key_value().map(|(_, v)| v).cloned(),
-                    RequestTime::FirstAfter(time) => {
+                    RequestTime::FirstAfter(time) | RequestTime::FirstAfterWithin { time, .. } => {
                         // If the requested time is before the first element in the vector, we are
                         // not sure that the first element is the closest one.
                         if let Some((_, oldest_record_value)) = key_cache.first_key_value() {
@@ -169,46 +533,164 @@ impl Storage {
                             .value()
                             .cloned()
                     }
+                    RequestTime::ReceivedAfter(time) => {
+                        // The cache is ordered by publish time, so it is scanned to find
+                        // the states by their receive time. As for `FirstAfter`, if the
+                        // requested time is before the first received state we are not
+                        // sure that the first one is the closest one.
+                        let oldest_received_at =
+                            key_cache.values().map(|value| value.received_at).min()?;
+                        if time < oldest_received_at {
+                            return None;
+                        }
+
+                        key_cache
+                            .values()
+                            .filter(|value| value.received_at >= time)
+                            .min_by_key(|value| (value.received_at, value.time()))
+                            .cloned()
+                    }
+                    RequestTime::AtOrBefore(time) => {
+                        let lookup_time = MessageStateTime {
+                            publish_time: time,
+                            slot:         Slot::MAX,
+                        };
+
+                        // Get the last element that is less than or equal to the lookup time.
+                        key_cache
+                            .range(..=lookup_time)
+                            .next_back()
+                            .map(|(_, v)| v)
+                            .cloned()
+                    }
+                    RequestTime::LatestConfirmed { .. } => {
+                        let confirmed_slot = confirmed_slot?;
+                        key_cache
+                            .iter()
+                            .rev()
+                            .find(|(time, _)| time.slot <= confirmed_slot)
+                            .map(|(_, v)| v)
+                            .cloned()
+                    }
                 }
             }
             None => None,
         }
     }
 
+    /// Looks up the message states of the given feeds in the order of the
+    /// feeds. The lookup of a missing message state yields `None`.
+    fn lookup_message_states(
+        &self,
+        ids: Vec<FeedId>,
+        request_time: RequestTime,
+        filter: MessageStateFilter,
+        confirmed_slot: Option<Slot>,
+    ) -> impl Iterator<Item = Option<MessageState>> + '_ {
+        ids.into_iter().flat_map(move |id| {
+            let request_time = request_time.clone();
+            let message_types: Vec<MessageType> = match filter {
+                MessageStateFilter::All => MessageType::iter().collect(),
+                MessageStateFilter::Only(t) => vec![t],
+            };
+
+            message_types.into_iter().map(move |message_type| {
+                let key = MessageStateKey {
+                    feed_id: id,
+                    type_:   message_type,
+                };
+                self.retrieve_message_state(key, request_time.clone(), confirmed_slot)
+            })
+        })
+    }
+
+    /// Fetches the message states of the given feeds in the order of the
+    /// feeds. Fails if any of them is missing.
     pub async fn fetch_message_states(
         &self,
         ids: Vec<FeedId>,
         request_time: RequestTime,
         filter: MessageStateFilter,
     ) -> Result<Vec<MessageState>> {
-        ids.into_iter()
-            .flat_map(|id| {
-                let request_time = request_time.clone();
-                let message_types: Vec<MessageType> = match filter {
-                    MessageStateFilter::All => MessageType::iter().collect(),
-                    MessageStateFilter::Only(t) => vec![t],
-                };
+        let confirmed_slot = self.resolve_confirmed_slot(&request_time).await;
+        self.lookup_message_states(ids, request_time, filter, confirmed_slot)
+            .map(|message_state| message_state.ok_or(anyhow!("Message not found")))
+            .collect()
+    }
 
-                message_types.into_iter().map(move |message_type| {
-                    let key = MessageStateKey {
-                        feed_id: id,
-                        type_:   message_type,
-                    };
-                    self.retrieve_message_state(key, request_time.clone())
-                        .ok_or(anyhow!("Message not found"))
-                })
-            })
+    /// Like `fetch_message_states`, but missing message states are omitted
+    /// instead of failing the fetch.
+    pub async fn fetch_available_message_states(
+        &self,
+        ids: Vec<FeedId>,
+        request_time: RequestTime,
+        filter: MessageStateFilter,
+    ) -> Vec<MessageState> {
+        let confirmed_slot = self.resolve_confirmed_slot(&request_time).await;
+        self.lookup_message_states(ids, request_time, filter, confirmed_slot)
+            .flatten()
             .collect()
     }
 
+    async fn resolve_confirmed_slot(&self, request_time: &RequestTime) -> Option<Slot> {
+        match request_time {
+            RequestTime::LatestConfirmed { behind } => self.confirmed_slot(*behind).await,
+            _ => None,
+        }
+    }
+
     pub async fn store_accumulator_messages(
         &self,
         accumulator_messages: AccumulatorMessages,
     ) -> Result<()> {
+        let pinned_slots = self.pinned_slots.read().await;
         let mut cache = self.accumulator_messages_cache.write().await;
         cache.insert(accumulator_messages.slot, accumulator_messages);
-        while cache.len() > self.cache_size as usize {
-            cache.pop_first();
+        evict_oldest_unpinned(
+            &mut cache,
+            self.cache_size as usize,
+            &pinned_slots,
+            |slot| *slot,
+        );
+        Ok(())
+    }
+
+    /// Stores the accumulator messages as a fragment of the slot's message
+    /// set: the messages not yet stored for the slot are appended to the
+    /// stored ones instead of replacing them.
+    pub async fn extend_accumulator_messages(
+        &self,
+        accumulator_messages: AccumulatorMessages,
+    ) -> Result<()> {
+        // The lookup and the insertion happen under the same lock, so
+        // concurrent fragments of a new slot do not overwrite each other.
+        let pinned_slots = self.pinned_slots.read().await;
+        let mut cache = self.accumulator_messages_cache.write().await;
+        let (stored, is_new_slot) = match cache.entry(accumulator_messages.slot) {
+            Entry::Occupied(entry) => (entry.into_mut(), false),
+            Entry::Vacant(entry) => (
+                entry.insert(AccumulatorMessages {
+                    raw_messages: vec![],
+                    ..accumulator_messages
+                }),
+                true,
+            ),
+        };
+        let mut stored_messages: HashSet<RawMessage> =
+            stored.raw_messages.iter().cloned().collect();
+        for raw_message in accumulator_messages.raw_messages {
+            if stored_messages.insert(raw_message.clone()) {
+                stored.raw_messages.push(raw_message);
+            }
+        }
+
+        if is_new_slot {
+            evict_oldest_unpinned(
+                &mut cache,
+                self.cache_size as usize,
+                &pinned_slots,
+                |slot| *slot,
+            );
         }
         Ok(())
     }
@@ -225,14 +707,91 @@ impl Storage {
         &self,
         wormhole_merkle_state: WormholeMerkleState,
     ) -> Result<()> {
+        let pinned_slots = self.pinned_slots.read().await;
         let mut cache = self.wormhole_merkle_state_cache.write().await;
         cache.insert(wormhole_merkle_state.root.slot, wormhole_merkle_state);
-        while cache.len() > self.cache_size as usize {
-            cache.pop_first();
-        }
+        evict_oldest_unpinned(
+            &mut cache,
+            self.cache_size as usize,
+            &pinned_slots,
+            |slot| *slot,
+        );
         Ok(())
     }
 
+    /// Returns the slots with a retained wormhole merkle state in ascending
+    /// order.
+    pub async fn wormhole_merkle_state_slots(&self) -> Vec<Slot> {
+        self.wormhole_merkle_state_cache
+            .read()
+            .await
+            .keys()
+            .copied()
+            .collect()
+    }
+
+    /// Returns the retained wormhole merkle states in ascending order of
+    /// their slot.
+    pub async fn wormhole_merkle_states(&self) -> Vec<WormholeMerkleState> {
+        self.wormhole_merkle_state_cache
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the retained slot whose wormhole merkle state has the root.
+    pub async fn find_slot_by_root(&self, root: [u8; 20]) -> Option<Slot> {
+        self.wormhole_merkle_state_cache
+            .read()
+            .await
+            .iter()
+            .find(|(_, wormhole_merkle_state)| wormhole_merkle_state.root.root == root)
+            .map(|(slot, _)| *slot)
+    }
+
+    /// Returns whether the wormhole merkle state of the slot is retained.
+    pub async fn is_slot_retained(&self, slot: Slot) -> bool {
+        self.wormhole_merkle_state_cache
+            .read()
+            .await
+            .contains_key(&slot)
+    }
+
+    /// Returns whether the slot is older than all the retained wormhole
+    /// merkle states while their cache is full, so it is already evicted or
+    /// would be evicted right away.
+    pub async fn is_older_than_retained_slots(&self, slot: Slot) -> bool {
+        let cache = self.wormhole_merkle_state_cache.read().await;
+        cache.len() as u64 >= self.cache_size
+            && cache
+                .keys()
+                .next()
+                .is_some_and(|oldest_slot| slot < *oldest_slot)
+    }
+
+    pub async fn store_merkle_tree(&self, slot: Slot, merkle_tree: MerkleTree<Keccak160>) {
+        let pinned_slots = self.pinned_slots.read().await;
+        let mut cache = self.merkle_tree_cache.write().await;
+        cache.insert(slot, Arc::new(merkle_tree));
+        evict_oldest_unpinned(
+            &mut cache,
+            self.cache_size as usize,
+            &pinned_slots,
+            |slot| *slot,
+        );
+    }
+
+    pub async fn fetch_merkle_tree(&self, slot: Slot) -> Option<Arc<MerkleTree<Keccak160>>> {
+        self.merkle_tree_cache.read().await.get(&slot).cloned()
+    }
+
+    /// Returns the number of slots with a retained wormhole merkle state.
+    pub async fn num_wormhole_merkle_states(&self) -> usize {
+        self.wormhole_merkle_state_cache.read().await.len()
+    }
+
     pub async fn fetch_wormhole_merkle_state(
         &self,
         slot: Slot,
@@ -289,10 +848,16 @@ mod test {
             received_at: publish_time,
             proof_set: ProofSet {
                 wormhole_merkle_proof: WormholeMerkleMessageProof {
-                    vaa:   vec![],
-                    proof: MerklePath::<Keccak160>::new(vec![]),
+                    vaa:        vec![],
+                    proof:      MerklePath::<Keccak160>::new(vec![]),
+                    leaf_index: 0,
                 },
             },
+            checksum: None,
+            suspect: false,
+            clamped: false,
+            unverified: false,
+            source: None,
         }
     }
 
@@ -665,10 +1230,41 @@ mod test {
             .is_none());
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    pub async fn test_concurrent_fragments_of_a_new_slot_are_all_kept() {
+        let storage = Arc::new(Storage::new(2));
+
+        // Overlapping fragments of the same slot, none of them stored yet.
+        let fragments = (0..32u8).map(|i| AccumulatorMessages {
+            raw_messages: vec![vec![i], vec![i + 1], vec![i + 1]],
+            ..create_empty_accumulator_messages_at_slot(10)
+        });
+        let tasks = fragments
+            .map(|fragment| {
+                let storage = storage.clone();
+                tokio::spawn(async move { storage.extend_accumulator_messages(fragment).await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let mut raw_messages = storage
+            .fetch_accumulator_messages(10)
+            .await
+            .unwrap()
+            .unwrap()
+            .raw_messages;
+        raw_messages.sort();
+        assert_eq!(raw_messages, (0..33u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
     pub fn create_empty_wormhole_merkle_state_at_slot(slot: Slot) -> WormholeMerkleState {
         WormholeMerkleState {
-            vaa:  vec![],
-            root: WormholeMerkleRoot {
+            vaa:                vec![],
+            sequence:           0,
+            guardian_set_index: 0,
+            root:               WormholeMerkleRoot {
                 slot,
                 root: [0; 20],
                 ring_size: 3,
@@ -746,4 +1342,141 @@ mod test {
             .unwrap()
             .is_none());
     }
+
+    #[tokio::test]
+    pub async fn test_verify_integrity_flags_corrupted_message_states() {
+        let storage = Storage::new(2);
+
+        let mut message_state = create_dummy_price_feed_message_state([1; 32], 10, 5);
+        message_state.raw_message = vec![1, 2, 3];
+        let intact_message_state = create_dummy_price_feed_message_state([2; 32], 10, 5);
+        storage
+            .store_message_states(vec![
+                message_state.clone().with_checksum(),
+                intact_message_state.with_checksum(),
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            storage.verify_integrity().await,
+            IntegrityReport {
+                num_checked: 2,
+                mismatches:  vec![],
+            }
+        );
+
+        // Flip a bit of the raw message of the first state.
+        storage.mutate_message_state(&message_state.key(), &message_state.time(), |state| {
+            state.raw_message[0] ^= 1;
+        });
+
+        let report = storage.verify_integrity().await;
+        assert!(!report.is_intact());
+        assert_eq!(
+            report.mismatches,
+            vec![IntegrityMismatch {
+                key:  message_state.key(),
+                time: message_state.time(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_store_message_states_notifies_evicted_slots() {
+        // Initialize a storage with a cache size of 2 per key.
+        let storage = Storage::new(2);
+        let mut eviction_rx = storage.subscribe_evictions();
+
+        for slot in 10..15 {
+            storage
+                .store_message_states(vec![
+                    create_dummy_price_feed_message_state([1; 32], slot as i64, slot),
+                    create_dummy_price_feed_message_state([2; 32], slot as i64, slot),
+                ])
+                .await
+                .unwrap();
+        }
+
+        // The slots displaced by the 3 newest slots are notified once each
+        // even though they are evicted from the caches of both feeds.
+        assert_eq!(eviction_rx.try_recv().unwrap(), 10);
+        assert_eq!(eviction_rx.try_recv().unwrap(), 11);
+        assert_eq!(eviction_rx.try_recv().unwrap(), 12);
+        assert!(eviction_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_eviction_callback_receives_evicted_states() {
+        let evicted = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let storage = Storage::new(2).with_on_evict({
+            let evicted = evicted.clone();
+            Box::new(move |slot, states| {
+                evicted.lock().unwrap().push((slot, states.to_vec()));
+            })
+        });
+
+        for slot in 10..14 {
+            storage
+                .store_message_states(vec![
+                    create_dummy_price_feed_message_state([1; 32], slot as i64, slot),
+                    create_dummy_price_feed_message_state([2; 32], slot as i64, slot),
+                ])
+                .await
+                .unwrap();
+        }
+
+        // The slots displaced by the 2 newest slots are passed with the
+        // states of both feeds.
+        let evicted = evicted.lock().unwrap();
+        assert_eq!(
+            evicted
+                .iter()
+                .map(|(slot, states)| (*slot, states.len()))
+                .collect::<Vec<_>>(),
+            vec![(10, 2), (11, 2)]
+        );
+        assert_eq!(
+            evicted[0].1,
+            vec![
+                create_dummy_price_feed_message_state([1; 32], 10, 10),
+                create_dummy_price_feed_message_state([2; 32], 10, 10),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_retrieve_message_state_received_after_works() {
+        let storage = Storage::new(10);
+
+        // The states are received out of the order of their publish time.
+        let mut message_states = vec![];
+        for (publish_time, received_at) in [(10, 100), (20, 130), (30, 115)] {
+            let mut message_state =
+                create_dummy_price_feed_message_state([1; 32], publish_time, publish_time as Slot);
+            message_state.received_at = received_at;
+            message_states.push(message_state);
+        }
+        storage
+            .store_message_states(message_states.clone())
+            .await
+            .unwrap();
+
+        let fetch = |time| {
+            storage.fetch_message_states(
+                vec![[1; 32]],
+                RequestTime::ReceivedAfter(time),
+                MessageStateFilter::Only(MessageType::PriceFeedMessage),
+            )
+        };
+
+        assert_eq!(fetch(100).await.unwrap(), vec![message_states[0].clone()]);
+        assert_eq!(fetch(110).await.unwrap(), vec![message_states[2].clone()]);
+        assert_eq!(fetch(120).await.unwrap(), vec![message_states[1].clone()]);
+
+        // Nothing is received after 130 and the states received before 100
+        // might have been evicted.
+        assert!(fetch(140).await.is_err());
+        assert!(fetch(50).await.is_err());
+    }
 }
@@ -0,0 +1,150 @@
+#[cfg(test)]
+use mock_instant::Instant;
+#[cfg(not(test))]
+use std::time::Instant;
+use {
+    super::types::{
+        CompletedSlot,
+        PriceFeedUpdate,
+        UnixTimestamp,
+    },
+    pyth_sdk::PriceIdentifier,
+    pythnet_sdk::messages::PriceFeedMessage,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        time::Duration,
+    },
+    tokio::sync::mpsc::Sender,
+    tokio_util::sync::CancellationToken,
+};
+
+/// Capacity of the channel between the store and a batched subscriber.
+pub const BATCHED_SUBSCRIPTION_CHAN_LEN: usize = 1000;
+
+/// A listener notified of each completed slot through its own buffered
+/// channel. A listener with a full buffer misses the notifications instead
+/// of holding up the other listeners.
+pub struct UpdateListener {
+    /// Identifier of the listener in the metrics.
+    pub id:     usize,
+    pub sender: Sender<CompletedSlot>,
+}
+
+/// A subscriber that receives all the updates of its price feeds within a
+/// completed slot as a single batch instead of one message per feed.
+pub struct BatchedSubscriber {
+    pub price_ids:                HashSet<PriceIdentifier>,
+    /// The maximum time the buffer of the subscriber may stay full. Batches
+    /// are never waited for: those arriving on a full buffer are dropped,
+    /// and a subscriber lagging for longer than this is dropped too.
+    pub max_latency:              Duration,
+    pub sender:                   Sender<Vec<PriceFeedUpdate>>,
+    /// The minimum relative price change since the last delivered update
+    /// for an update of a feed to be delivered. Feeds without a ratio get
+    /// all their updates.
+    pub min_change_ratios:        HashMap<PriceIdentifier, f64>,
+    /// The price, with the exponent applied, of the last delivered update
+    /// of each feed with a change ratio.
+    last_delivered_prices:        HashMap<PriceIdentifier, f64>,
+    /// The publish time of the last delivered update of each feed.
+    last_delivered_publish_times: HashMap<PriceIdentifier, UnixTimestamp>,
+    /// Token to drop the subscriber from the server side, e.g. when its
+    /// client is gone but the receiver is still held.
+    pub cancellation_token:       Option<CancellationToken>,
+    /// Since when the buffer of the subscriber is full, if it is.
+    lagging_since:                Option<Instant>,
+}
+
+impl BatchedSubscriber {
+    pub fn new(
+        price_ids: HashSet<PriceIdentifier>,
+        max_latency: Duration,
+        sender: Sender<Vec<PriceFeedUpdate>>,
+        min_change_ratios: HashMap<PriceIdentifier, f64>,
+    ) -> Self {
+        Self {
+            price_ids,
+            max_latency,
+            sender,
+            min_change_ratios,
+            last_delivered_prices: HashMap::new(),
+            last_delivered_publish_times: HashMap::new(),
+            cancellation_token: None,
+            lagging_since: None,
+        }
+    }
+
+    pub fn with_cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Returns whether the subscriber can no longer receive batches, either
+    /// because its receiver is dropped or because it is cancelled.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+            || self
+                .cancellation_token
+                .as_ref()
+                .is_some_and(|cancellation_token| cancellation_token.is_cancelled())
+    }
+
+    /// Returns whether the buffer of the subscriber has been full for longer
+    /// than its maximum latency, tracking since when it is full.
+    pub fn is_lagging(&mut self) -> bool {
+        if self.sender.capacity() > 0 {
+            self.lagging_since = None;
+            return false;
+        }
+
+        let lagging_since = *self.lagging_since.get_or_insert_with(Instant::now);
+        lagging_since.elapsed() > self.max_latency
+    }
+
+    /// Returns whether the update should be delivered to the subscriber,
+    /// recording its price as the last delivered one if so.
+    pub fn accepts(&mut self, price_feed: &PriceFeedMessage) -> bool {
+        let price_id = PriceIdentifier::new(price_feed.feed_id);
+        if !self.price_ids.contains(&price_id) {
+            return false;
+        }
+
+        let min_change_ratio = match self.min_change_ratios.get(&price_id) {
+            Some(min_change_ratio) => *min_change_ratio,
+            None => return true,
+        };
+
+        let price = price_feed.price as f64 * 10f64.powi(price_feed.exponent);
+        if let Some(last_delivered_price) = self.last_delivered_prices.get(&price_id) {
+            if *last_delivered_price != 0.0
+                && (price - last_delivered_price).abs() / last_delivered_price.abs()
+                    < min_change_ratio
+            {
+                return false;
+            }
+        }
+
+        self.last_delivered_prices.insert(price_id, price);
+        true
+    }
+
+    /// Returns whether the delivered update links to the last delivered
+    /// update of its feed, recording it as the last delivered one. The first
+    /// delivered update of a feed has nothing to link to and is considered
+    /// linked.
+    pub fn links_previous(&mut self, price_feed: &PriceFeedMessage) -> bool {
+        let price_id = PriceIdentifier::new(price_feed.feed_id);
+        match self
+            .last_delivered_publish_times
+            .insert(price_id, price_feed.publish_time)
+        {
+            Some(last_delivered_publish_time) => {
+                price_feed.prev_publish_time == last_delivered_publish_time
+            }
+            None => true,
+        }
+    }
+}
@@ -1,7 +1,36 @@
 use {
-    super::proof::wormhole_merkle::WormholeMerkleMessageProof,
-    borsh::BorshDeserialize,
-    pythnet_sdk::messages::PriceFeedMessage,
+    super::proof::wormhole_merkle::{
+        WormholeMerkleMessageProof,
+        WormholeMerkleMultiProofUpdateData,
+        WORMHOLE_MERKLE_MULTIPROOF_MAGIC,
+    },
+    anyhow::{
+        anyhow,
+        Result,
+    },
+    borsh::{
+        BorshDeserialize,
+        BorshSerialize,
+    },
+    derive_more::Display,
+    pyth_sdk::PriceIdentifier,
+    pythnet_sdk::{
+        accumulators::merkle::MerklePath,
+        hashers::keccak256_160::Keccak160,
+        messages::PriceFeedMessage,
+        wire::v1::{
+            AccumulatorUpdateData,
+            Proof,
+        },
+    },
+    rust_decimal::Decimal,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    serde_wormhole::RawMessage,
+    std::time::Duration,
+    wormhole_sdk::Vaa,
 };
 
 #[derive(Clone, PartialEq, Debug)]
@@ -18,8 +47,82 @@ pub type UnixTimestamp = i64;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum RequestTime {
+    /// The update with the highest publish time of each feed among the
+    /// retained updates, regardless of the slot it was received in. Feeds in
+    /// the same response can therefore come from different slots.
     Latest,
     FirstAfter(UnixTimestamp),
+    /// The first update at or after `time`, as long as it is published
+    /// within `tolerance` of `time`.
+    FirstAfterWithin {
+        time:      UnixTimestamp,
+        tolerance: Duration,
+    },
+    /// The first update received by the store at or after the given time,
+    /// regardless of its publish time.
+    ReceivedAfter(UnixTimestamp),
+    /// The latest update of each feed as of the slot that is `behind` slots
+    /// back from the newest retained slot. Unlike `Latest`, it does not serve
+    /// updates from the newest slots, which might still be reorged.
+    LatestConfirmed {
+        behind: usize,
+    },
+    /// The last update published at or before the given time.
+    AtOrBefore(UnixTimestamp),
+}
+
+impl RequestTime {
+    /// Number of slots a `LatestConfirmed` request stays behind by default.
+    pub const DEFAULT_CONFIRMATION_DEPTH: usize = 1;
+
+    pub fn latest_confirmed() -> Self {
+        RequestTime::LatestConfirmed {
+            behind: Self::DEFAULT_CONFIRMATION_DEPTH,
+        }
+    }
+}
+
+/// The parts of the merkle proofs included in the update data.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ProofGranularity {
+    /// The Vaa and the requested messages with their merkle paths.
+    #[default]
+    Full,
+    /// The Vaa and all the leaf messages of the merkle tree, without merkle
+    /// paths. Clients re-derive the paths from the leaves.
+    LeavesOnly,
+    /// Only the Vaa, which signs the merkle root.
+    RootOnly,
+}
+
+/// Options controlling how the update data of a price feeds query is
+/// constructed.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PriceFeedsQueryOptions {
+    /// Emit a single merkle multiproof per slot covering all the requested
+    /// feeds instead of a separate merkle path per feed.
+    pub multiproof:        bool,
+    /// Serve the latest retained update of a feed even if it is older than
+    /// the staleness threshold, flagging it as stale instead of failing.
+    pub allow_stale:       bool,
+    /// Omit the feeds without a matching update instead of failing the
+    /// query.
+    pub partial:           bool,
+    /// The parts of the proofs to include. Anything other than `Full`
+    /// takes precedence over `multiproof`.
+    pub proof_granularity: ProofGranularity,
+    /// Maximum time spent constructing the price feed updates and the update
+    /// data, including their size check, before the query fails with a
+    /// timeout. The query stops waiting for the construction
+    /// but work already running on a blocking thread is not interrupted.
+    pub deadline:          Option<Duration>,
+    /// Fall back to the unverified updates backfilled from archived
+    /// accumulator messages for the feeds without a verified update.
+    pub allow_unverified:  bool,
+    /// Maximum size of each update data blob, such as the calldata limit of
+    /// the target chain. Queries producing a larger blob fail with an
+    /// `UpdateTooLarge` error listing the feeds of the oversized blobs.
+    pub max_update_bytes:  Option<usize>,
 }
 
 pub type RawMessage = Vec<u8>;
@@ -30,7 +133,7 @@ pub type RawMessage = Vec<u8>;
 /// the following struct. We cannot directly have messages as Vec<Messages>
 /// because they are serialized using big-endian byte order and Borsh
 /// uses little-endian byte order.
-#[derive(Clone, PartialEq, Debug, BorshDeserialize)]
+#[derive(Clone, PartialEq, Debug, BorshDeserialize, BorshSerialize)]
 pub struct AccumulatorMessages {
     pub magic:        [u8; 4],
     pub slot:         u64,
@@ -49,7 +152,7 @@ pub enum Update {
     AccumulatorMessages(AccumulatorMessages),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PriceFeedUpdate {
     pub price_feed:                  PriceFeedMessage,
     pub slot:                        Slot,
@@ -58,10 +161,662 @@ pub struct PriceFeedUpdate {
     /// This field is available for backward compatibility and will be
     /// removed in the future.
     pub wormhole_merkle_update_data: Vec<u8>,
+    /// Whether the update is older than the staleness threshold. Stale
+    /// updates are only served when explicitly allowed by the query.
+    pub is_stale:                    bool,
+    /// Position of the price feed message among the leaves of the merkle
+    /// tree of its slot.
+    pub merkle_leaf_index:           u32,
+    /// Whether the price deviates anomalously from the previous price of the
+    /// feed, as flagged by the store's circuit breaker.
+    pub suspect:                     bool,
+    /// Whether the update links to the last update of the feed delivered
+    /// to the same subscriber, i.e. its `prev_publish_time` is the publish
+    /// time of that update. A gap in the series leaves it false. Always
+    /// true outside of subscriptions.
+    pub links_previous:              bool,
+    /// Whether the update is backfilled from archived accumulator messages
+    /// without a Vaa. Unverified updates have no update data.
+    pub unverified:                  bool,
+    /// Source the update was ingested from, for attributing the data when
+    /// several sources feed the store. `None` for the default source.
+    pub source:                      Option<String>,
+}
+
+// `PriceFeedMessage` does not implement Borsh, so its fields are written one
+// by one in their declaration order.
+impl BorshSerialize for PriceFeedUpdate {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let price_feed = &self.price_feed;
+        BorshSerialize::serialize(&price_feed.feed_id, writer)?;
+        BorshSerialize::serialize(&price_feed.price, writer)?;
+        BorshSerialize::serialize(&price_feed.conf, writer)?;
+        BorshSerialize::serialize(&price_feed.exponent, writer)?;
+        BorshSerialize::serialize(&price_feed.publish_time, writer)?;
+        BorshSerialize::serialize(&price_feed.prev_publish_time, writer)?;
+        BorshSerialize::serialize(&price_feed.ema_price, writer)?;
+        BorshSerialize::serialize(&price_feed.ema_conf, writer)?;
+        BorshSerialize::serialize(&self.slot, writer)?;
+        BorshSerialize::serialize(&self.received_at, writer)?;
+        BorshSerialize::serialize(&self.wormhole_merkle_update_data, writer)?;
+        BorshSerialize::serialize(&self.is_stale, writer)?;
+        BorshSerialize::serialize(&self.merkle_leaf_index, writer)?;
+        BorshSerialize::serialize(&self.suspect, writer)?;
+        BorshSerialize::serialize(&self.links_previous, writer)?;
+        BorshSerialize::serialize(&self.unverified, writer)?;
+        BorshSerialize::serialize(&self.source, writer)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl BorshDeserialize for PriceFeedUpdate {
+    fn deserialize_reader<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(Self {
+            price_feed:                  PriceFeedMessage {
+                feed_id:           BorshDeserialize::deserialize_reader(reader)?,
+                price:             BorshDeserialize::deserialize_reader(reader)?,
+                conf:              BorshDeserialize::deserialize_reader(reader)?,
+                exponent:          BorshDeserialize::deserialize_reader(reader)?,
+                publish_time:      BorshDeserialize::deserialize_reader(reader)?,
+                prev_publish_time: BorshDeserialize::deserialize_reader(reader)?,
+                ema_price:         BorshDeserialize::deserialize_reader(reader)?,
+                ema_conf:          BorshDeserialize::deserialize_reader(reader)?,
+            },
+            slot:                        BorshDeserialize::deserialize_reader(reader)?,
+            received_at:                 BorshDeserialize::deserialize_reader(reader)?,
+            wormhole_merkle_update_data: BorshDeserialize::deserialize_reader(reader)?,
+            is_stale:                    BorshDeserialize::deserialize_reader(reader)?,
+            merkle_leaf_index:           BorshDeserialize::deserialize_reader(reader)?,
+            suspect:                     BorshDeserialize::deserialize_reader(reader)?,
+            links_previous:              BorshDeserialize::deserialize_reader(reader)?,
+            unverified:                  BorshDeserialize::deserialize_reader(reader)?,
+            source:                      BorshDeserialize::deserialize_reader(reader)?,
+        })
+    }
+}
+
+/// A price with its confidence interval, as published at a point in time.
+/// The price is `value * 10^exponent`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PricePoint {
+    pub value:        i64,
+    pub conf:         u64,
+    pub exponent:     i32,
+    pub publish_time: UnixTimestamp,
+}
+
+/// The maximum scale (number of decimal digits) of a `Decimal`.
+const MAX_DECIMAL_SCALE: u32 = 28;
+
+impl PriceFeedUpdate {
+    /// Splits the update into its spot price and its EMA price. Both come
+    /// from the same price feed message and are therefore proven by the
+    /// same update data.
+    pub fn split_price_and_ema(&self) -> (PricePoint, PricePoint) {
+        let price_feed = &self.price_feed;
+        (
+            PricePoint {
+                value:        price_feed.price,
+                conf:         price_feed.conf,
+                exponent:     price_feed.exponent,
+                publish_time: price_feed.publish_time,
+            },
+            PricePoint {
+                value:        price_feed.ema_price,
+                conf:         price_feed.ema_conf,
+                exponent:     price_feed.exponent,
+                publish_time: price_feed.publish_time,
+            },
+        )
+    }
+
+    /// Returns the price with the exponent applied as an exact decimal.
+    ///
+    /// Fails if the price cannot be represented exactly, which happens when
+    /// it needs more than 28 decimal digits or overflows the 96 bit
+    /// mantissa of a `Decimal`.
+    pub fn as_decimal(&self) -> Result<Decimal> {
+        let price = self.price_feed.price as i128;
+        let exponent = self.price_feed.exponent;
+
+        if exponent >= 0 {
+            let mantissa = 10i128
+                .checked_pow(exponent as u32)
+                .and_then(|multiplier| price.checked_mul(multiplier))
+                .ok_or_else(|| anyhow!("Price overflows with exponent {}", exponent))?;
+            return Ok(Decimal::try_from_i128_with_scale(mantissa, 0)?);
+        }
+
+        // Trailing zeros of the price can be dropped to fit the scale without
+        // losing precision.
+        let mut mantissa = price;
+        let mut scale = exponent.unsigned_abs();
+        while scale > MAX_DECIMAL_SCALE && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        if scale > MAX_DECIMAL_SCALE {
+            return Err(anyhow!(
+                "Price cannot be represented exactly with exponent {}",
+                exponent
+            ));
+        }
+
+        Ok(Decimal::try_from_i128_with_scale(mantissa, scale)?)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, BorshSerialize, BorshDeserialize)]
 pub struct PriceFeedsWithUpdateData {
     pub price_feeds:                 Vec<PriceFeedUpdate>,
     pub wormhole_merkle_update_data: Vec<Vec<u8>>,
 }
+
+/// Encodings of a whole `PriceFeedsWithUpdateData`, for clients storing the
+/// response as a single opaque blob.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SerdeFormat {
+    Json,
+    MessagePack,
+    Borsh,
+}
+
+impl PriceFeedsWithUpdateData {
+    /// Encodes the price feeds and their update data in the given format.
+    pub fn serialize(&self, format: SerdeFormat) -> Result<Vec<u8>> {
+        Ok(match format {
+            SerdeFormat::Json => serde_json::to_vec(self)?,
+            SerdeFormat::MessagePack => rmp_serde::to_vec_named(self)?,
+            SerdeFormat::Borsh => self.try_to_vec()?,
+        })
+    }
+
+    /// Decodes price feeds and their update data encoded by `serialize` in
+    /// the same format.
+    pub fn deserialize(bytes: &[u8], format: SerdeFormat) -> Result<Self> {
+        Ok(match format {
+            SerdeFormat::Json => serde_json::from_slice(bytes)?,
+            SerdeFormat::MessagePack => rmp_serde::from_slice(bytes)?,
+            SerdeFormat::Borsh => Self::try_from_slice(bytes)?,
+        })
+    }
+}
+
+/// The figures that drive the cost of verifying update data on-chain.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct UpdateCostEstimate {
+    /// Number of guardian signatures over all the VAAs.
+    pub num_signatures:    usize,
+    /// Number of merkle path nodes over all the updated messages, or of
+    /// hashes for the multiproofs.
+    pub total_proof_nodes: usize,
+    pub update_data_bytes: usize,
+}
+
+impl PriceFeedsWithUpdateData {
+    /// Estimates the on-chain verification cost of the update data by parsing
+    /// the produced `AccumulatorUpdateData` or multiproof blobs.
+    pub fn cost_estimate(&self) -> Result<UpdateCostEstimate> {
+        let mut estimate = UpdateCostEstimate::default();
+        for update_data in self.wormhole_merkle_update_data.iter() {
+            estimate.update_data_bytes += update_data.len();
+            if update_data.starts_with(WORMHOLE_MERKLE_MULTIPROOF_MAGIC) {
+                let update_data = WormholeMerkleMultiProofUpdateData::try_from_slice(update_data)?;
+                let vaa: Vec<u8> = update_data.vaa.into();
+                let vaa = serde_wormhole::from_slice::<Vaa<&RawMessage>>(vaa.as_ref())?;
+                estimate.num_signatures += vaa.signatures.len();
+                estimate.total_proof_nodes += update_data.proof.hashes.len();
+                continue;
+            }
+            let update_data = AccumulatorUpdateData::try_from_slice(update_data.as_ref())
+                .map_err(|e| anyhow!("Failed to parse update data: {:?}", e))?;
+            match update_data.proof {
+                Proof::WormholeMerkle { vaa, updates } => {
+                    let vaa: Vec<u8> = vaa.into();
+                    let vaa = serde_wormhole::from_slice::<Vaa<&RawMessage>>(vaa.as_ref())?;
+                    estimate.num_signatures += vaa.signatures.len();
+                    estimate.total_proof_nodes += updates
+                        .iter()
+                        .map(|update| update.proof.len())
+                        .sum::<usize>();
+                }
+            }
+        }
+        Ok(estimate)
+    }
+}
+
+/// The shape of a retained slot, as listed by `Store::slot_summary`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlotSummary {
+    pub slot:        Slot,
+    /// Number of price feeds updated in the slot whose states are retained.
+    pub feed_count:  usize,
+    /// When the price feeds of the slot were received, if any is retained.
+    pub received_at: Option<UnixTimestamp>,
+}
+
+/// Side on which a sibling hash is concatenated with the node it is hashed
+/// with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+/// The merkle proof of a message as explicit sibling hashes, for verifiers
+/// that take the path level by level. Parent nodes hash their two children
+/// ordered by value, so the directions follow that order rather than the
+/// positions of the siblings in the tree.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StructuredProof {
+    /// Hash of the leaf of the message.
+    pub leaf:     [u8; 20],
+    /// Sibling hashes from the leaf level up to the level below the root.
+    pub siblings: Vec<([u8; 20], Direction)>,
+    pub root:     [u8; 20],
+}
+
+/// A price feed with the two layers of its proof kept apart: the accumulator
+/// proof takes the message to the merkle root, and the Vaa signs the root.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LayeredPriceFeed {
+    /// Serialized message of the feed, the leaf of the accumulator.
+    pub message:           Vec<u8>,
+    pub accumulator_proof: MerklePath<Keccak160>,
+    /// Merkle root of the slot, as signed in the Vaa payload.
+    pub wormhole_root:     [u8; 20],
+    pub vaa:               Vec<u8>,
+}
+
+/// What became available by priming the store with `Store::prime_feeds`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PrimeReport {
+    /// Slots completed by the primed updates, in ascending order.
+    pub slots:     Vec<Slot>,
+    /// Price feeds updated in the completed slots, ordered by id.
+    pub price_ids: Vec<PriceIdentifier>,
+}
+
+/// Notification of a slot whose message states are built.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CompletedSlot {
+    pub slot: Slot,
+}
+
+/// A summary of the state of the store, suitable to be served as is by a
+/// status endpoint.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct StoreStatus {
+    pub is_ready:                       bool,
+    pub latest_completed_slot:          Option<Slot>,
+    pub num_feeds:                      usize,
+    /// Seconds since the last completed update.
+    pub last_completed_update_age_secs: Option<f64>,
+    pub num_observed_vaas:              u64,
+    pub num_completed_slots:            u64,
+}
+
+/// Errors returned by the store that callers may want to handle
+/// specifically. They are wrapped in `anyhow::Error` and can be recovered
+/// with `downcast_ref`.
+#[derive(Clone, PartialEq, Eq, Debug, Display)]
+pub enum StoreError {
+    #[display(fmt = "Too many feeds requested: {} (limit: {})", requested, limit)]
+    TooManyFeeds { requested: usize, limit: usize },
+    #[display(fmt = "Latest update of price feed {:?} is stale", price_id)]
+    StalePriceFeed { price_id: PriceIdentifier },
+    #[display(
+        fmt = "No update for price feed {} within {:?} after {}",
+        price_id,
+        tolerance,
+        time
+    )]
+    NoDataWithinTolerance {
+        price_id:  PriceIdentifier,
+        time:      UnixTimestamp,
+        tolerance: Duration,
+    },
+    #[display(
+        fmt = "Constructing the update data exceeded the {:?} deadline",
+        deadline
+    )]
+    Timeout { deadline: Duration },
+    #[display(
+        fmt = "Cannot serve an update {} slots behind the newest with {} slots retained",
+        behind,
+        retained
+    )]
+    NotEnoughRetainedSlots { behind: usize, retained: usize },
+    #[display(fmt = "Slot {} is not retained", slot)]
+    SlotNotFound { slot: Slot },
+    #[display(fmt = "No retained slot has the merkle root {:?}", root)]
+    RootNotRetained { root: [u8; 20] },
+    #[display(fmt = "Price feed {} is not updated in slot {}", price_id, slot)]
+    FeedNotInSlot {
+        price_id: PriceIdentifier,
+        slot:     Slot,
+    },
+    #[display(fmt = "Conflicting accumulator messages for slot {}", slot)]
+    ConflictingAccumulatorMessages { slot: Slot },
+    #[display(
+        fmt = "Accumulator messages of slot {} do not match the merkle root of its Vaa",
+        slot
+    )]
+    MerkleRootMismatch { slot: Slot },
+    #[display(
+        fmt = "Update data of price feeds {:?} exceeds the limit of {} bytes",
+        price_ids,
+        limit
+    )]
+    UpdateTooLarge {
+        price_ids: Vec<PriceIdentifier>,
+        limit:     usize,
+    },
+    #[display(fmt = "Slot {} has no accumulator messages", slot)]
+    EmptySlot { slot: Slot },
+    #[display(
+        fmt = "The store is overloaded with {} concurrent updates",
+        max_concurrent_updates
+    )]
+    Overloaded { max_concurrent_updates: usize },
+    #[display(fmt = "The store is read-only")]
+    ReadOnly,
+    #[display(
+        fmt = "Vaa sequence {} signed at {} is older than {:?}",
+        sequence,
+        timestamp,
+        max_age
+    )]
+    StaleVaa {
+        sequence:  u64,
+        timestamp: u32,
+        max_age:   Duration,
+    },
+    #[display(fmt = "Feed group {} is not defined", name)]
+    UnknownFeedGroup { name: String },
+    #[display(
+        fmt = "Guardian set {} has {} keys, more than the limit of {}",
+        index,
+        keys,
+        limit
+    )]
+    TooManyGuardianKeys {
+        index: u32,
+        keys:  usize,
+        limit: usize,
+    },
+    #[display(
+        fmt = "Price feed {} is only verified by the retired guardian set {}",
+        price_id,
+        guardian_set_index
+    )]
+    RetiredGuardianSet {
+        price_id:           PriceIdentifier,
+        guardian_set_index: u32,
+    },
+    #[display(fmt = "Cannot pin more than {} slots", limit)]
+    TooManyPinnedSlots { limit: usize },
+    #[display(
+        fmt = "Vaa sequence {} is too far behind the processed sequence {}",
+        sequence,
+        high_water_sequence
+    )]
+    ReplayedVaa {
+        sequence:            u64,
+        high_water_sequence: u64,
+    },
+}
+
+impl std::error::Error for StoreError {
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        pythnet_sdk::{
+            accumulators::merkle::{
+                MerkleMultiProof,
+                MerklePath,
+            },
+            hashers::keccak256_160::Keccak160,
+            wire::{
+                to_vec,
+                v1::MerklePriceUpdate,
+            },
+        },
+        wormhole_sdk::{
+            vaa::Signature,
+            Address,
+            Chain,
+        },
+    };
+
+    fn create_vaa_with_two_signatures() -> Vaa<&'static RawMessage> {
+        Vaa {
+            nonce:              0,
+            version:            1,
+            sequence:           1,
+            timestamp:          0,
+            signatures:         vec![
+                Signature {
+                    index:     0,
+                    signature: [0; 65],
+                },
+                Signature {
+                    index:     1,
+                    signature: [0; 65],
+                },
+            ],
+            guardian_set_index: 0,
+            emitter_chain:      Chain::Pythnet,
+            emitter_address:    Address([0; 32]),
+            consistency_level:  0,
+            payload:            RawMessage::new(&[][..]),
+        }
+    }
+
+    #[test]
+    pub fn test_cost_estimate_counts_signatures_and_proof_nodes() {
+        let vaa = create_vaa_with_two_signatures();
+
+        let update_data =
+            to_vec::<_, byteorder::BE>(&AccumulatorUpdateData::new(Proof::WormholeMerkle {
+                vaa:     serde_wormhole::to_vec(&vaa).unwrap().into(),
+                updates: vec![
+                    MerklePriceUpdate {
+                        message: vec![1, 2, 3].into(),
+                        proof:   MerklePath::<Keccak160>::new(vec![[0; 20], [1; 20], [2; 20]]),
+                    },
+                    MerklePriceUpdate {
+                        message: vec![4, 5, 6, 7, 8].into(),
+                        proof:   MerklePath::<Keccak160>::new(vec![[3; 20]]),
+                    },
+                ],
+            }))
+            .unwrap();
+
+        let price_feeds_with_update_data = PriceFeedsWithUpdateData {
+            price_feeds:                 vec![],
+            wormhole_merkle_update_data: vec![update_data],
+        };
+
+        // The update data consists of:
+        // - 8 bytes of header (magic, versions, trailing and proof type),
+        // - a 2 byte length prefix followed by the 189 bytes VAA (138 bytes of
+        //   header with 2 signatures and 51 bytes of body with an empty payload),
+        // - a 1 byte update count,
+        // - 2 + 3 + 1 + 3 * 20 bytes for the first update,
+        // - 2 + 5 + 1 + 20 bytes for the second update.
+        assert_eq!(
+            price_feeds_with_update_data.cost_estimate().unwrap(),
+            UpdateCostEstimate {
+                num_signatures:    2,
+                total_proof_nodes: 4,
+                update_data_bytes: 294,
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_cost_estimate_counts_multiproof_hashes() {
+        let vaa = create_vaa_with_two_signatures();
+
+        let update_data = to_vec::<_, byteorder::BE>(&WormholeMerkleMultiProofUpdateData::new(
+            serde_wormhole::to_vec(&vaa).unwrap().into(),
+            vec![vec![1, 2, 3].into(), vec![4, 5, 6, 7, 8].into()],
+            MerkleMultiProof {
+                depth:        2,
+                leaf_indices: vec![0, 3].into(),
+                hashes:       vec![[0; 20], [1; 20]].into(),
+            },
+        ))
+        .unwrap();
+        // The multiproof is not mistaken for an `AccumulatorUpdateData`.
+        assert!(AccumulatorUpdateData::try_from_slice(update_data.as_ref()).is_err());
+
+        let price_feeds_with_update_data = PriceFeedsWithUpdateData {
+            price_feeds:                 vec![],
+            wormhole_merkle_update_data: vec![update_data.clone()],
+        };
+
+        assert_eq!(
+            price_feeds_with_update_data.cost_estimate().unwrap(),
+            UpdateCostEstimate {
+                num_signatures:    2,
+                total_proof_nodes: 2,
+                update_data_bytes: update_data.len(),
+            }
+        );
+    }
+
+    fn price_feed_update(price: i64, exponent: i32) -> PriceFeedUpdate {
+        PriceFeedUpdate {
+            price_feed:                  PriceFeedMessage {
+                feed_id: [1; 32],
+                price,
+                conf: 0,
+                exponent,
+                publish_time: 0,
+                prev_publish_time: 0,
+                ema_price: 0,
+                ema_conf: 0,
+            },
+            slot:                        0,
+            received_at:                 0,
+            wormhole_merkle_update_data: vec![],
+            is_stale:                    false,
+            merkle_leaf_index:           0,
+            suspect:                     false,
+            links_previous:              true,
+            unverified:                  false,
+            source:                      None,
+        }
+    }
+
+    #[test]
+    pub fn test_as_decimal_is_exact() {
+        for (price, exponent, expected) in [
+            (123456789, -8, "1.23456789"),
+            (-123456789, -8, "-1.23456789"),
+            (i64::MAX, -8, "92233720368.54775807"),
+            (i64::MIN, 0, "-9223372036854775808"),
+            (1, -28, "0.0000000000000000000000000001"),
+            // Trailing zeros are dropped to fit the maximum scale.
+            (1000, -30, "0.000000000000000000000000001"),
+            (42, 10, "420000000000"),
+            (i64::MAX, 9, "9223372036854775807000000000"),
+        ] {
+            assert_eq!(
+                price_feed_update(price, exponent)
+                    .as_decimal()
+                    .unwrap()
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_as_decimal_rejects_unrepresentable_prices() {
+        // Too many decimal digits.
+        assert!(price_feed_update(1, -30).as_decimal().is_err());
+        // Overflows the mantissa.
+        assert!(price_feed_update(i64::MAX, 20).as_decimal().is_err());
+        assert!(price_feed_update(1, 40).as_decimal().is_err());
+    }
+
+    #[test]
+    pub fn test_split_price_and_ema() {
+        let mut update = price_feed_update(100, -2);
+        update.price_feed.conf = 3;
+        update.price_feed.ema_price = 90;
+        update.price_feed.ema_conf = 4;
+        update.price_feed.publish_time = 10;
+
+        assert_eq!(
+            update.split_price_and_ema(),
+            (
+                PricePoint {
+                    value:        100,
+                    conf:         3,
+                    exponent:     -2,
+                    publish_time: 10,
+                },
+                PricePoint {
+                    value:        90,
+                    conf:         4,
+                    exponent:     -2,
+                    publish_time: 10,
+                },
+            )
+        );
+    }
+
+    fn create_dummy_price_feeds_with_update_data() -> PriceFeedsWithUpdateData {
+        let mut suspect_update = price_feed_update(-12345, -5);
+        suspect_update.price_feed.feed_id = [2; 32];
+        suspect_update.price_feed.publish_time = 1_700_000_000;
+        suspect_update.slot = 42;
+        suspect_update.wormhole_merkle_update_data = vec![7; 300];
+        suspect_update.merkle_leaf_index = 3;
+        suspect_update.suspect = true;
+
+        let mut stale_update = price_feed_update(u32::MAX as i64, 2);
+        stale_update.received_at = 1_700_000_001;
+        stale_update.is_stale = true;
+
+        PriceFeedsWithUpdateData {
+            price_feeds:                 vec![suspect_update, stale_update],
+            wormhole_merkle_update_data: vec![vec![1, 2, 3], vec![], vec![255; 64]],
+        }
+    }
+
+    #[test]
+    pub fn test_price_feeds_with_update_data_round_trips_through_every_format() {
+        for format in [
+            SerdeFormat::Json,
+            SerdeFormat::MessagePack,
+            SerdeFormat::Borsh,
+        ] {
+            let bytes = create_dummy_price_feeds_with_update_data()
+                .serialize(format)
+                .unwrap();
+            assert_eq!(
+                PriceFeedsWithUpdateData::deserialize(&bytes, format).unwrap(),
+                create_dummy_price_feeds_with_update_data(),
+                "{:?} round trip",
+                format
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_price_feeds_with_update_data_rejects_bytes_of_another_format() {
+        let bytes = create_dummy_price_feeds_with_update_data()
+            .serialize(SerdeFormat::Borsh)
+            .unwrap();
+
+        assert!(PriceFeedsWithUpdateData::deserialize(&bytes, SerdeFormat::Json).is_err());
+    }
+}
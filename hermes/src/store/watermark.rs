@@ -0,0 +1,69 @@
+use {
+    anyhow::Result,
+    std::path::PathBuf,
+};
+
+/// Persists the highest contiguous observed VAA sequence so previously
+/// processed VAAs can be skipped cheaply after a restart.
+pub trait SequenceWatermarkStore: Send + Sync {
+    fn load(&self) -> Result<Option<u64>>;
+    fn save(&self, watermark: u64) -> Result<()>;
+}
+
+/// Keeps the watermark in a file as a decimal number.
+pub struct FileSequenceWatermarkStore {
+    path: PathBuf,
+}
+
+impl FileSequenceWatermarkStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SequenceWatermarkStore for FileSequenceWatermarkStore {
+    fn load(&self) -> Result<Option<u64>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(Some(contents.trim().parse()?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, watermark: u64) -> Result<()> {
+        // Write to a temporary file first so a crash never leaves a partially
+        // written watermark behind.
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, watermark.to_string())?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn test_file_watermark_store_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "hermes-test-file-watermark-store-roundtrip-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let watermark_store = FileSequenceWatermarkStore::new(&path);
+        assert_eq!(watermark_store.load().unwrap(), None);
+
+        watermark_store.save(42).unwrap();
+        assert_eq!(watermark_store.load().unwrap(), Some(42));
+
+        watermark_store.save(43).unwrap();
+        assert_eq!(
+            FileSequenceWatermarkStore::new(&path).load().unwrap(),
+            Some(43)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
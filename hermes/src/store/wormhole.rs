@@ -21,6 +21,7 @@ use {
         vaa::{
             Body,
             Header,
+            Signature,
         },
         Vaa,
     },
@@ -46,6 +47,63 @@ impl std::fmt::Display for GuardianSet {
     }
 }
 
+/// A summary of a guardian set held by the store, used for diagnostics.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct GuardianSetInfo {
+    pub index:    u32,
+    pub num_keys: usize,
+}
+
+/// The header of a VAA, carrying the guardian signatures over its body.
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct VaaHeader {
+    pub version:            u8,
+    pub guardian_set_index: u32,
+    pub signatures:         Vec<Signature>,
+}
+
+/// Splits a serialized VAA into its parsed header and the raw bytes of its
+/// body. The body is what the guardians sign, so on-chain verifiers can hash
+/// it directly.
+pub fn split_vaa(vaa_bytes: &[u8]) -> Result<(VaaHeader, Vec<u8>)> {
+    let vaa = serde_wormhole::from_slice::<Vaa<&RawMessage>>(vaa_bytes)?;
+    let (header, _): (Header, Body<&RawMessage>) = vaa.into();
+
+    // The header is made of the version (1 byte), the guardian set index
+    // (4 bytes), the number of signatures (1 byte) and the signatures,
+    // each made of the guardian index (1 byte) and the signature (65 bytes).
+    let header_len = 1 + 4 + 1 + header.signatures.len() * (1 + 65);
+    let body = vaa_bytes
+        .get(header_len..)
+        .ok_or_else(|| anyhow!("VAA is shorter than its header"))?
+        .to_vec();
+
+    Ok((
+        VaaHeader {
+            version:            header.version,
+            guardian_set_index: header.guardian_set_index,
+            signatures:         header.signatures,
+        },
+        body,
+    ))
+}
+
+/// Recombines a header and a body split by `split_vaa` into the serialized
+/// VAA.
+pub fn join_vaa(header: &VaaHeader, body: &[u8]) -> Vec<u8> {
+    let mut vaa_bytes =
+        Vec::with_capacity(1 + 4 + 1 + header.signatures.len() * (1 + 65) + body.len());
+    vaa_bytes.push(header.version);
+    vaa_bytes.extend_from_slice(&header.guardian_set_index.to_be_bytes());
+    vaa_bytes.push(header.signatures.len() as u8);
+    for signature in header.signatures.iter() {
+        vaa_bytes.push(signature.index);
+        vaa_bytes.extend_from_slice(&signature.signature);
+    }
+    vaa_bytes.extend_from_slice(body);
+    vaa_bytes
+}
+
 /// BridgeData extracted from wormhole bridge account, due to no API.
 #[derive(borsh::BorshDeserialize)]
 #[allow(dead_code)]
@@ -73,68 +131,527 @@ pub struct GuardianSetData {
 }
 
 /// Verifies a VAA to ensure it is signed by the Wormhole guardian set.
+///
+/// Recovering the signers is CPU-bound, so it runs on the blocking thread
+/// pool to keep the async runtime responsive during bursts of VAAs.
+///
+/// During a guardian set upgrade the declared guardian set might not be
+/// known yet (or anymore). In that case the VAA is accepted if its
+/// signatures reach the quorum of an adjacent known guardian set that is not
+/// past its retirement grace period.
+///
+/// VAAs whose consistency level is below the minimum configured on the store
+/// are rejected before their signatures are checked.
+///
+/// Returns the VAA with the index of the guardian set that verified it,
+/// which differs from the declared one when an adjacent set is used.
 pub async fn verify_vaa<'a>(
     store: &Store,
     vaa: Vaa<&'a RawMessage>,
-) -> Result<Vaa<&'a RawMessage>> {
+) -> Result<(Vaa<&'a RawMessage>, u32)> {
+    if let Some(min_consistency_level) = store.config.min_consistency_level {
+        if vaa.consistency_level < min_consistency_level {
+            store.metrics.low_consistency_vaas.inc();
+            return Err(anyhow!(
+                "Consistency level {} is below the minimum {}",
+                vaa.consistency_level,
+                min_consistency_level
+            ));
+        }
+    }
+
     let (header, body): (Header, Body<&RawMessage>) = vaa.into();
     let digest = body.digest()?;
-    let guardian_set = store.guardian_set.read().await;
-    let guardian_set = guardian_set
-        .get(&header.guardian_set_index)
-        .ok_or_else(|| {
-            anyhow!(
-                "Message signed by an unknown guardian set: {}",
-                header.guardian_set_index
-            )
-        })?;
-
-    let mut num_correct_signers = 0;
-    for sig in header.signatures.iter() {
-        let signer_id: usize = sig.index.into();
 
-        let sig = sig.signature;
+    let secp256k_hash = digest.secp256k_hash;
+    let signatures: Vec<(usize, [u8; 65])> = header
+        .signatures
+        .iter()
+        .map(|sig| (sig.index.into(), sig.signature))
+        .collect();
+    let signers =
+        tokio::task::spawn_blocking(move || recover_signers(&secp256k_hash, &signatures)).await??;
 
-        // Recover the public key from ecdsa signature from [u8; 65] that has (v, r, s) format
-        let recid = RecoveryId::from_i32(sig[64].into())?;
-
-        let secp = Secp256k1::new();
+    let adjacent_indices = [
+        header.guardian_set_index.checked_sub(1),
+        header.guardian_set_index.checked_add(1),
+    ];
+    // The retirements are looked up before locking the guardian sets, which
+    // are locked before the retirements when the sets are updated.
+    let mut retired_adjacent_indices = Vec::new();
+    for index in adjacent_indices.into_iter().flatten() {
+        if store.is_past_retirement_grace(index).await? {
+            retired_adjacent_indices.push(index);
+        }
+    }
 
-        // To get the address we need to use the uncompressed public key
-        let pubkey: &[u8; 65] = &secp
-            .recover_ecdsa(
-                &Message::from_slice(&digest.secp256k_hash)?,
-                &RecoverableSignature::from_compact(&sig[..64], recid)?,
-            )?
-            .serialize_uncompressed();
+    let guardian_sets = store.guardian_set.read().await;
 
-        // The address is the last 20 bytes of the Keccak256 hash of the public key
-        let mut keccak = Keccak256::new();
-        keccak.update(&pubkey[1..]);
-        let address: [u8; 32] = keccak.finalize().into();
-        let address: [u8; 20] = address[address.len() - 20..].try_into()?;
+    if let Some(guardian_set) = guardian_sets.get(&header.guardian_set_index) {
+        let (num_correct_signers, quorum) = check_quorum(guardian_set, &signers);
+        if num_correct_signers < quorum {
+            return Err(anyhow!(
+                "Not enough correct signatures. Expected {:?}, received {:?}",
+                quorum,
+                num_correct_signers
+            ));
+        }
+        let guardian_set_index = header.guardian_set_index;
+        return Ok(((header, body).into(), guardian_set_index));
+    }
 
-        if guardian_set.keys.get(signer_id) == Some(&address) {
-            num_correct_signers += 1;
+    for index in adjacent_indices.into_iter().flatten() {
+        if retired_adjacent_indices.contains(&index) {
+            tracing::info!(
+                "Not using adjacent guardian set {} past its retirement grace period",
+                index
+            );
+            continue;
+        }
+        if let Some(guardian_set) = guardian_sets.get(&index) {
+            let (num_correct_signers, quorum) = check_quorum(guardian_set, &signers);
+            if num_correct_signers >= quorum {
+                tracing::info!(
+                    "Accepting VAA declaring unknown guardian set {} with the quorum of adjacent guardian set {}",
+                    header.guardian_set_index,
+                    index
+                );
+                return Ok(((header, body).into(), index));
+            }
         }
     }
 
-    // TODO: This check bypass checking the signatures on tests.
-    // Ideally we need to test the signatures but currently Wormhole
+    Err(anyhow!(
+        "Message signed by an unknown guardian set: {}",
+        header.guardian_set_index
+    ))
+}
+
+/// Returns the number of signers that belong to the guardian set and the
+/// quorum of the guardian set.
+fn check_quorum(guardian_set: &GuardianSet, signers: &[(usize, [u8; 20])]) -> (usize, usize) {
+    let num_correct_signers = signers
+        .iter()
+        .filter(|(signer_id, address)| guardian_set.keys.get(*signer_id) == Some(address))
+        .count();
+
+    // TODO: This check bypass checking the signatures on tests with unsigned
+    // VAAs. Ideally we need to test the signatures but currently Wormhole
     // doesn't give us any easy way for it.
-    let quorum = if cfg!(test) {
+    let quorum = if cfg!(test) && signers.is_empty() {
         0
     } else {
         (guardian_set.keys.len() * 2) / 3 + 1
     };
 
-    if num_correct_signers < quorum {
-        return Err(anyhow!(
-            "Not enough correct signatures. Expected {:?}, received {:?}",
-            quorum,
-            num_correct_signers
-        ));
+    (num_correct_signers, quorum)
+}
+
+/// Recovers the address of the signer of each (signer id, signature) pair.
+fn recover_signers(
+    secp256k_hash: &[u8; 32],
+    signatures: &[(usize, [u8; 65])],
+) -> Result<Vec<(usize, [u8; 20])>> {
+    let secp = Secp256k1::new();
+    let message = Message::from_slice(secp256k_hash)?;
+
+    signatures
+        .iter()
+        .map(|(signer_id, sig)| {
+            // Recover the public key from ecdsa signature from [u8; 65] that has (v, r, s) format
+            let recid = RecoveryId::from_i32(sig[64].into())?;
+
+            // To get the address we need to use the uncompressed public key
+            let pubkey: &[u8; 65] = &secp
+                .recover_ecdsa(
+                    &message,
+                    &RecoverableSignature::from_compact(&sig[..64], recid)?,
+                )?
+                .serialize_uncompressed();
+
+            // The address is the last 20 bytes of the Keccak256 hash of the public key
+            let mut keccak = Keccak256::new();
+            keccak.update(&pubkey[1..]);
+            let address: [u8; 32] = keccak.finalize().into();
+            let address: [u8; 20] = address[address.len() - 20..].try_into()?;
+
+            Ok((*signer_id, address))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::store::StoreConfig,
+        futures::future::join_all,
+        mock_instant::MockClock,
+        secp256k1::SecretKey,
+        std::{
+            collections::BTreeMap,
+            sync::{
+                atomic::{
+                    AtomicUsize,
+                    Ordering,
+                },
+                Arc,
+            },
+        },
+        wormhole_sdk::{
+            Address,
+            Chain,
+        },
+    };
+
+    fn guardian_address(secp: &Secp256k1<secp256k1::All>, secret_key: &SecretKey) -> [u8; 20] {
+        let pubkey = secret_key.public_key(secp).serialize_uncompressed();
+        let mut keccak = Keccak256::new();
+        keccak.update(&pubkey[1..]);
+        let address: [u8; 32] = keccak.finalize().into();
+        address[address.len() - 20..].try_into().unwrap()
     }
 
-    Ok((header, body).into())
+    fn sign_vaa<'a>(
+        secret_keys: &[SecretKey],
+        guardian_set_index: u32,
+        sequence: u64,
+        payload: &'a RawMessage,
+    ) -> Vaa<&'a RawMessage> {
+        let secp = Secp256k1::new();
+        let body = Body {
+            timestamp: 0,
+            nonce: 0,
+            emitter_chain: Chain::Pythnet,
+            emitter_address: Address(pythnet_sdk::ACCUMULATOR_EMITTER_ADDRESS),
+            sequence,
+            consistency_level: 0,
+            payload,
+        };
+        let digest = body.digest().unwrap();
+        let message = Message::from_slice(&digest.secp256k_hash).unwrap();
+
+        let signatures = secret_keys
+            .iter()
+            .enumerate()
+            .map(|(index, secret_key)| {
+                let (recid, compact) = secp
+                    .sign_ecdsa_recoverable(&message, secret_key)
+                    .serialize_compact();
+                let mut signature = [0; 65];
+                signature[..64].copy_from_slice(&compact);
+                signature[64] = recid.to_i32() as u8;
+                Signature {
+                    index: index as u8,
+                    signature,
+                }
+            })
+            .collect();
+
+        let header = Header {
+            version: 1,
+            guardian_set_index,
+            signatures,
+        };
+        (header, body).into()
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    pub async fn test_concurrent_verifications_do_not_block_runtime() {
+        let secp = Secp256k1::new();
+        let secret_keys: Vec<SecretKey> = (0..19)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new(update_tx, 10);
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: secret_keys
+                        .iter()
+                        .map(|secret_key| guardian_address(&secp, secret_key))
+                        .collect(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let payload = RawMessage::new(&b"payload"[..]);
+        let vaas: Vec<_> = (0..200)
+            .map(|sequence| sign_vaa(&secret_keys, 0, sequence, payload))
+            .collect();
+
+        // A ticker running on the same single threaded runtime only makes
+        // progress if the verifications yield to the executor.
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_millis(1));
+                // The first tick completes immediately.
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        let results = join_all(vaas.into_iter().map(|vaa| verify_vaa(&store, vaa))).await;
+        let ticks_during_verification = ticks.load(Ordering::SeqCst);
+        ticker.abort();
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(ticks_during_verification > 0);
+    }
+
+    #[tokio::test]
+    pub async fn test_verify_vaa_enforces_min_consistency_level() {
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new_with_config(
+            update_tx,
+            10,
+            StoreConfig {
+                min_consistency_level: Some(1),
+                ..Default::default()
+            },
+        );
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: vec![[0; 20]],
+                },
+            )
+            .await
+            .unwrap();
+
+        // The VAAs are unsigned, which bypasses the quorum in tests.
+        let payload = RawMessage::new(&b"payload"[..]);
+        for (consistency_level, accepted) in [(0, false), (1, true), (2, true)] {
+            let mut vaa = sign_vaa(&[], 0, 1, payload);
+            vaa.consistency_level = consistency_level;
+            assert_eq!(verify_vaa(&store, vaa).await.is_ok(), accepted);
+        }
+        assert_eq!(store.metrics.low_consistency_vaas.get(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    pub async fn test_verifications_never_observe_partially_replaced_guardian_sets() {
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new(update_tx, 10);
+        let guardian_set = GuardianSet {
+            keys: vec![[0; 20]],
+        };
+        let old_sets = BTreeMap::from([(0, guardian_set.clone())]);
+        let new_sets = BTreeMap::from([(1, guardian_set.clone()), (2, guardian_set)]);
+        store.replace_guardian_sets(old_sets.clone()).await.unwrap();
+
+        // The unsigned VAA declaring the set 0 is accepted by the old sets
+        // and, through the adjacent set 1, by the new ones, but not by an
+        // empty or partially populated map.
+        let swapper = tokio::spawn({
+            let store = store.clone();
+            async move {
+                for i in 0..200 {
+                    let sets = if i % 2 == 0 { &new_sets } else { &old_sets };
+                    store.replace_guardian_sets(sets.clone()).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            }
+        });
+        let verifiers = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let payload = RawMessage::new(&b"payload"[..]);
+                    for sequence in 0..200 {
+                        verify_vaa(&store, sign_vaa(&[], 0, sequence, payload))
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        swapper.await.unwrap();
+        for verifier in verifiers {
+            verifier.await.unwrap();
+        }
+    }
+
+    #[test]
+    pub fn test_recover_signers_returns_guardian_addresses() {
+        let secp = Secp256k1::new();
+        let secret_keys: Vec<SecretKey> = (0..3)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let payload = RawMessage::new(&b"payload"[..]);
+        let vaa = sign_vaa(&secret_keys, 0, 1, payload);
+        let (header, body): (Header, Body<&RawMessage>) = vaa.into();
+        let signatures: Vec<(usize, [u8; 65])> = header
+            .signatures
+            .iter()
+            .map(|sig| (sig.index.into(), sig.signature))
+            .collect();
+
+        assert_eq!(
+            recover_signers(&body.digest().unwrap().secp256k_hash, &signatures).unwrap(),
+            secret_keys
+                .iter()
+                .enumerate()
+                .map(|(index, secret_key)| (index, guardian_address(&secp, secret_key)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    pub fn test_split_vaa_body_hashes_to_verified_digest() {
+        let secret_keys: Vec<SecretKey> = (0..3)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let payload = RawMessage::new(&b"payload"[..]);
+        let vaa = sign_vaa(&secret_keys, 0, 1, payload);
+        let vaa_bytes = serde_wormhole::to_vec(&vaa).unwrap();
+
+        let (header, body) = split_vaa(&vaa_bytes).unwrap();
+        assert_eq!(header.version, 1);
+        assert_eq!(header.guardian_set_index, 0);
+        assert_eq!(header.signatures, vaa.signatures);
+
+        // The digest used by `verify_vaa` is the double Keccak256 hash of the
+        // body.
+        let (_, vaa_body): (Header, Body<&RawMessage>) = vaa.into();
+        let secp256k_hash: [u8; 32] = Keccak256::digest(Keccak256::digest(&body)).into();
+        assert_eq!(secp256k_hash, vaa_body.digest().unwrap().secp256k_hash);
+    }
+
+    #[test]
+    pub fn test_join_vaa_reproduces_split_vaa() {
+        let secret_keys: Vec<SecretKey> = (0..3)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let payload = RawMessage::new(&b"payload"[..]);
+        let vaa_bytes = serde_wormhole::to_vec(&sign_vaa(&secret_keys, 2, 1, payload)).unwrap();
+
+        let (header, body) = split_vaa(&vaa_bytes).unwrap();
+        assert_eq!(join_vaa(&header, &body), vaa_bytes);
+    }
+
+    #[test]
+    pub fn test_split_vaa_rejects_invalid_vaa() {
+        assert!(split_vaa(&[1, 0, 0, 0, 0]).is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_verify_vaa_falls_back_to_adjacent_guardian_set() {
+        let secp = Secp256k1::new();
+        let secret_keys: Vec<SecretKey> = (0..4)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new(update_tx, 10);
+        store
+            .update_guardian_set(
+                0,
+                GuardianSet {
+                    keys: secret_keys
+                        .iter()
+                        .map(|secret_key| guardian_address(&secp, secret_key))
+                        .collect(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let payload = RawMessage::new(&b"payload"[..]);
+
+        // Guardian set 1 is unknown but the signatures reach the quorum of
+        // guardian set 0.
+        assert!(verify_vaa(&store, sign_vaa(&secret_keys, 1, 1, payload))
+            .await
+            .is_ok());
+
+        // Signatures that do not reach the quorum of the adjacent set are
+        // rejected.
+        assert!(
+            verify_vaa(&store, sign_vaa(&secret_keys[..2], 1, 2, payload))
+                .await
+                .is_err()
+        );
+
+        // Keys unrelated to the adjacent set are rejected.
+        let other_secret_keys: Vec<SecretKey> = (0..4)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+        assert!(
+            verify_vaa(&store, sign_vaa(&other_secret_keys, 1, 3, payload))
+                .await
+                .is_err()
+        );
+
+        // Sets that are not adjacent are never used.
+        assert!(verify_vaa(&store, sign_vaa(&secret_keys, 2, 4, payload))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    pub async fn test_verify_vaa_reports_and_bounds_the_adjacent_guardian_set() {
+        let secp = Secp256k1::new();
+        let secret_keys: Vec<SecretKey> = (0..4)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+        let other_secret_keys: Vec<SecretKey> = (0..4)
+            .map(|_| SecretKey::new(&mut rand::thread_rng()))
+            .collect();
+
+        let (update_tx, _update_rx) = tokio::sync::mpsc::channel(1000);
+        let store = Store::new(update_tx, 10);
+        // Guardian set 2 replaces guardian set 0, which retires it.
+        for (index, keys) in [(0, &secret_keys), (2, &other_secret_keys)] {
+            store
+                .update_guardian_set(
+                    index,
+                    GuardianSet {
+                        keys: keys
+                            .iter()
+                            .map(|secret_key| guardian_address(&secp, secret_key))
+                            .collect(),
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        let payload = RawMessage::new(&b"payload"[..]);
+
+        // The VAA declares the unknown set 1 and is verified by the set 0,
+        // which is reported instead of the declared one.
+        let (_, guardian_set_index) = verify_vaa(&store, sign_vaa(&secret_keys, 1, 1, payload))
+            .await
+            .unwrap();
+        assert_eq!(guardian_set_index, 0);
+
+        // Past its retirement grace period, the set 0 is no longer used.
+        MockClock::advance_system_time(
+            store.config.retired_set_grace_period + std::time::Duration::from_secs(1),
+        );
+        assert!(verify_vaa(&store, sign_vaa(&secret_keys, 1, 2, payload))
+            .await
+            .is_err());
+
+        // The active adjacent set still verifies the VAAs it signs.
+        let (_, guardian_set_index) =
+            verify_vaa(&store, sign_vaa(&other_secret_keys, 1, 3, payload))
+                .await
+                .unwrap();
+        assert_eq!(guardian_set_index, 2);
+    }
 }
@@ -7,6 +7,7 @@ use {
             keccak256::Keccak256,
             Hasher,
         },
+        wire::PrefixedVec,
     },
     borsh::{
         BorshDeserialize,
@@ -16,6 +17,10 @@ use {
         Deserialize,
         Serialize,
     },
+    std::collections::{
+        BTreeMap,
+        BTreeSet,
+    },
 };
 
 // We need to discern between leaf and intermediate nodes to prevent trivial second pre-image
@@ -50,6 +55,22 @@ const NULL_PREFIX: &[u8] = &[2];
 )]
 pub struct MerklePath<H: Hasher>(Vec<H::Hash>);
 
+/// A MerkleMultiProof contains the hashes that prove membership of several leaves in a tree at
+/// once. Sibling hashes that can be derived from the proven leaves themselves are omitted, so the
+/// proof is smaller than the equivalent set of MerklePaths.
+///
+/// Both lists are prefixed with a u16 length on the wire, as a proof of a few dozen scattered
+/// leaves easily needs more than 255 sibling hashes.
+#[derive(Clone, Debug, Hash, PartialEq, Serialize, Deserialize)]
+pub struct MerkleMultiProof<H: Hasher> {
+    /// Depth of the tree the proof was generated from.
+    pub depth:        u8,
+    /// Positions of the proven leaves, in the same order as the items being checked.
+    pub leaf_indices: PrefixedVec<u16, u32>,
+    /// Sibling hashes, in the order they are consumed while walking the tree bottom up.
+    pub hashes:       PrefixedVec<u16, H::Hash>,
+}
+
 /// A MerkleRoot contains the root hash of a MerkleTree.
 #[derive(
     Clone,
@@ -93,6 +114,50 @@ impl<H: Hasher> MerkleRoot<H> {
         current == self.0
     }
 
+    /// Given a list of items and a corresponding MerkleMultiProof, check that it is a valid
+    /// membership proof for all of the items.
+    pub fn check_multi(&self, proof: MerkleMultiProof<H>, items: &[&[u8]]) -> bool {
+        if items.is_empty()
+            || proof.leaf_indices.len() != items.len()
+            || u32::from(proof.depth) >= usize::BITS - 1
+        {
+            return false;
+        }
+
+        // Nodes are laid out as in MerkleTree, where the leaves start at `1 << depth`.
+        let num_leaves = 1usize << proof.depth;
+        let mut known: BTreeMap<usize, H::Hash> = BTreeMap::new();
+        for (index, item) in proof.leaf_indices.iter().zip(items) {
+            let index = *index as usize;
+            if index >= num_leaves {
+                return false;
+            }
+
+            let hash = MerkleTree::<H>::hash_leaf(item);
+            if *known.entry(num_leaves + index).or_insert(hash) != hash {
+                return false;
+            }
+        }
+
+        let mut hashes = proof.hashes.into_iter();
+        while !known.contains_key(&1) {
+            let mut parents = BTreeMap::new();
+            for (id, hash) in known.iter() {
+                let sibling = match known.get(&(id ^ 1)) {
+                    Some(sibling) => *sibling,
+                    None => match hashes.next() {
+                        Some(sibling) => sibling,
+                        None => return false,
+                    },
+                };
+                parents.insert(id / 2, MerkleTree::<H>::hash_node(hash, &sibling));
+            }
+            known = parents;
+        }
+
+        hashes.next().is_none() && known.get(&1) == Some(&self.0)
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         self.0.as_ref()
     }
@@ -111,6 +176,15 @@ impl<H: Hasher> MerklePath<H> {
             .flat_map(|hash| hash.as_ref().to_vec())
             .collect()
     }
+
+    /// The number of hashes in the path.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 /// Presents an Accumulator friendly interface for MerkleTree.
@@ -187,6 +261,50 @@ impl<H: Hasher> MerkleTree<H> {
         MerklePath::new(path)
     }
 
+    /// Produces a Proof of membership for several leaf indices in the tree at once.
+    pub fn find_multi_path(&self, indices: &[usize]) -> Option<MerkleMultiProof<H>> {
+        let num_leaves = self.nodes.len() / 2;
+        if indices.is_empty() || indices.iter().any(|index| *index >= num_leaves) {
+            return None;
+        }
+
+        let mut known: BTreeSet<usize> = indices.iter().map(|index| num_leaves + index).collect();
+        let mut hashes = Vec::new();
+        while !known.contains(&1) {
+            let mut parents = BTreeSet::new();
+            for id in known.iter() {
+                if !known.contains(&(id ^ 1)) {
+                    hashes.push(self.nodes[id ^ 1]);
+                }
+                parents.insert(id / 2);
+            }
+            known = parents;
+        }
+
+        Some(MerkleMultiProof {
+            depth:        num_leaves.trailing_zeros() as u8,
+            leaf_indices: indices
+                .iter()
+                .map(|index| *index as u32)
+                .collect::<Vec<_>>()
+                .into(),
+            hashes:       hashes.into(),
+        })
+    }
+
+    /// Prove several items are in the tree by returning a single MerkleMultiProof.
+    pub fn prove_multi(&self, items: &[&[u8]]) -> Option<MerkleMultiProof<H>> {
+        let leaves = &self.nodes[self.nodes.len() / 2..];
+        let indices = items
+            .iter()
+            .map(|item| {
+                let item = MerkleTree::<H>::hash_leaf(item);
+                leaves.iter().position(|leaf| leaf == &item)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        self.find_multi_path(&indices)
+    }
+
     /// Check if a given MerklePath is a valid proof for a corresponding item.
     pub fn verify_path(&self, proof: MerklePath<H>, item: &[u8]) -> bool {
         self.root.check(proof, item)
@@ -405,6 +523,38 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_merkle_multi_proof() {
+        let items: Vec<[u8; 8]> = (0..8usize).map(|i| i.to_be_bytes()).collect();
+        let accumulator =
+            MerkleTree::<Keccak256>::from_set(items.iter().map(|i| i.as_ref())).unwrap();
+
+        // Prove three leaves out of eight at once.
+        let proven: Vec<&[u8]> = vec![&items[0][..], &items[1][..], &items[5][..]];
+        let proof = accumulator.prove_multi(&proven).unwrap();
+        assert!(accumulator.root.check_multi(proof.clone(), &proven));
+
+        // Siblings shared between the leaves are only included once.
+        let separate_proofs_len: usize = proven
+            .iter()
+            .map(|item| accumulator.prove(item).unwrap().0.len())
+            .sum();
+        assert!(proof.hashes.len() < separate_proofs_len);
+
+        // Swapping an item for one that is not proven must fail.
+        let wrong: Vec<&[u8]> = vec![&items[0][..], &items[2][..], &items[5][..]];
+        assert!(!accumulator.root.check_multi(proof.clone(), &wrong));
+
+        // Corrupting any of the hashes must fail.
+        for i in 0..proof.hashes.len() {
+            let mut corrupted_proof = proof.clone();
+            let mut hashes: Vec<_> = corrupted_proof.hashes.into();
+            hashes[i] = Default::default();
+            corrupted_proof.hashes = hashes.into();
+            assert!(!accumulator.root.check_multi(corrupted_proof, &proven));
+        }
+    }
+
     #[test]
     #[should_panic]
     // Generates a tree with four leaves, then uses the first leaf of the right subtree as the
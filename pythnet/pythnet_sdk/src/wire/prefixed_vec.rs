@@ -133,6 +133,14 @@ impl<L, T> PrefixedVec<L, T> {
     pub fn iter(&self) -> std::slice::Iter<T> {
         self.data.inner.iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.data.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.inner.is_empty()
+    }
 }
 
 impl<L, T> Serialize for PrefixedVec<L, T>